@@ -0,0 +1,44 @@
+//! Counts N-queens solutions with `dlx::ExactCover`, and draws one.
+//!
+//! ```ignore
+//! cargo run --release --example queens [n]
+//! ```
+//!
+//! Putting a queen on `(r, c)` is a row. Every rank and every file needs exactly one queen, so
+//! those are primary columns. A diagonal can have one queen or none, so the diagonals are
+//! secondary columns.
+
+use lists::dlx::ExactCover;
+use std::env;
+
+fn encode(n: usize) -> ExactCover {
+    let diagonals = 2 * n - 1;
+    let mut dlx = ExactCover::new(2 * n, 2 * diagonals);
+    for r in 0..n {
+        for c in 0..n {
+            dlx.add_row(&[r, n + c, 2 * n + r + c, 2 * n + diagonals + r + n - 1 - c]);
+        }
+    }
+    dlx
+}
+
+fn main() {
+    let n: usize = env::args()
+        .nth(1)
+        .map_or(8, |arg| arg.parse().expect("n should be a number"));
+    assert!(n > 0, "n should be at least 1");
+
+    for k in 1..=n {
+        println!("{:>2} queens: {} solutions", k, encode(k).count_solutions());
+    }
+
+    if let Some(rows) = encode(n).solve_first() {
+        println!();
+        // One queen per rank, and the rows come back sorted, so they're in rank order.
+        for row in rows {
+            let file = row % n;
+            let line: Vec<&str> = (0..n).map(|c| if c == file { "Q" } else { "." }).collect();
+            println!("{}", line.join(" "));
+        }
+    }
+}
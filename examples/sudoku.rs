@@ -0,0 +1,95 @@
+//! Solves a sudoku with `dlx::ExactCover`.
+//!
+//! ```ignore
+//! cargo run --example sudoku [81 digits, 0 or . for blanks]
+//! ```
+//!
+//! Every way of writing digit `d` in cell `(r, c)` is a row. It covers four columns: the cell
+//! itself, and "row `r` has a `d`", "column `c` has a `d`", "box `b` has a `d`". A filled grid is
+//! exactly a set of rows covering each of those 324 columns once.
+
+use lists::dlx::ExactCover;
+use std::env;
+
+const DEFAULT: &str = "\
+    800000000\
+    003600000\
+    070090200\
+    050007000\
+    000045700\
+    000100030\
+    001000068\
+    008500010\
+    090000400";
+
+fn row_index(r: usize, c: usize, d: usize) -> usize {
+    81 * r + 9 * c + d
+}
+
+fn encode(cells: &[Option<usize>]) -> ExactCover {
+    let mut dlx = ExactCover::new(4 * 81, 0);
+    for r in 0..9 {
+        for c in 0..9 {
+            let b = r / 3 * 3 + c / 3;
+            for d in 0..9 {
+                let row = match cells[r * 9 + c] {
+                    // A given cell only gets the row for its own digit. The rest are added
+                    // empty, so rows stay numbered by `row_index`.
+                    Some(given) if given != d => dlx.add_row(&[]),
+                    _ => {
+                        dlx.add_row(&[r * 9 + c, 81 + r * 9 + d, 162 + c * 9 + d, 243 + b * 9 + d])
+                    }
+                };
+                debug_assert_eq!(row, row_index(r, c, d));
+            }
+        }
+    }
+    dlx
+}
+
+fn print(cells: &[Option<usize>]) {
+    for (r, line) in cells.chunks(9).enumerate() {
+        if r % 3 == 0 && r > 0 {
+            println!("------+-------+------");
+        }
+        let digits: Vec<String> = line
+            .iter()
+            .map(|cell| cell.map_or(".".to_string(), |d| (d + 1).to_string()))
+            .collect();
+        println!(
+            "{} | {} | {}",
+            digits[0..3].join(" "),
+            digits[3..6].join(" "),
+            digits[6..9].join(" ")
+        );
+    }
+}
+
+fn main() {
+    let puzzle = env::args().nth(1).unwrap_or_else(|| DEFAULT.to_string());
+    let cells: Vec<Option<usize>> = puzzle
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(10).filter(|&d| d != 0).map(|d| d as usize - 1))
+        .collect();
+    assert_eq!(cells.len(), 81, "a sudoku has 81 cells");
+
+    print(&cells);
+    println!();
+
+    let mut dlx = encode(&cells);
+    match dlx.solve_first() {
+        Some(rows) => {
+            let mut solved = vec![None; 81];
+            for row in rows {
+                solved[row / 9] = Some(row % 9);
+            }
+            print(&solved);
+            let count = dlx.solve_all().take(2).count();
+            if count > 1 {
+                println!("\n(not the only solution)");
+            }
+        }
+        None => println!("no solution"),
+    }
+}
@@ -0,0 +1,813 @@
+//! A doubly linked list without `Rc<RefCell>` and without raw pointers.
+//!
+//! The trick is to stop using pointers at all. Every node lives in one `Vec`, and a link is just
+//! the position of another node in that `Vec`:
+//!
+//! ```ignore
+//! slots: [ (b, prev: 2, next: 3) | free | (a, prev: -, next: 0) | (c, prev: 0, next: -) ]
+//!             0                     1       2                     3
+//! head: 2, tail: 3, free: 1
+//! ```
+//!
+//! The borrow checker is happy because there's only one owner, the `Vec`, and following a link
+//! is an indexing operation instead of a dereference. Removing a node puts its slot on a free
+//! list that threads through the empty slots, and the next insert takes it from there.
+//!
+//! Reusing slots brings back a problem pointers have too: a handle to a removed node would
+//! quietly start pointing at whatever moved in next. So every slot also has a *generation* that
+//! goes up each time the slot is freed, and an `Index` remembers the generation it was handed
+//! out with. A handle from before the reuse then doesn't match and is simply refused.
+
+use std::fmt;
+
+/// Marks the end of the list (and of the free list). Links are `u32` rather than `usize` to keep
+/// nodes small, so a list can hold at most `NIL` elements.
+const NIL: u32 = u32::MAX;
+
+pub struct List<T> {
+    slots: Vec<Slot<T>>,
+    head: u32,
+    tail: u32,
+    /// First slot of the free list.
+    free: u32,
+    len: usize,
+    /// The generation brand new slots start at. `compact` raises it, so that a slot it dropped
+    /// doesn't come back at a generation some old handle still has.
+    floor: u32,
+}
+
+struct Slot<T> {
+    generation: u32,
+    entry: Entry<T>,
+}
+
+enum Entry<T> {
+    Occupied { elem: T, prev: u32, next: u32 },
+    Free { next_free: u32 },
+}
+
+/// A handle to one element of a `List`. It stays valid until that element is removed (or the
+/// list is compacted), even if other elements come and go.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Index {
+    slot: u32,
+    generation: u32,
+}
+
+impl fmt::Debug for Index {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Index({}v{})", self.slot, self.generation)
+    }
+}
+
+pub struct Iter<'a, T> {
+    list: &'a List<T>,
+    front: u32,
+    back: u32,
+    /// Stops the two ends from walking past each other.
+    remaining: usize,
+}
+
+pub struct IterMut<'a, T> {
+    /// Indexed by slot; each one is taken when it's yielded.
+    elems: Vec<Option<&'a mut T>>,
+    /// `(prev, next)` for every slot.
+    links: Vec<(u32, u32)>,
+    front: u32,
+    back: u32,
+    remaining: usize,
+}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List {
+            slots: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            free: NIL,
+            len: 0,
+            floor: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Slots in use plus slots on the free list.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot_of(&self, index: Index) -> Option<u32> {
+        match self.slots.get(index.slot as usize) {
+            Some(Slot {
+                generation,
+                entry: Entry::Occupied { .. },
+            }) if *generation == index.generation => Some(index.slot),
+            _ => None,
+        }
+    }
+
+    /// Like `slot_of`, for the methods that can't do anything sensible with a bad handle.
+    fn expect_slot(&self, index: Index) -> u32 {
+        self.slot_of(index)
+            .unwrap_or_else(|| panic!("{:?} is stale", index))
+    }
+
+    fn index_of(&self, slot: u32) -> Option<Index> {
+        if slot == NIL {
+            None
+        } else {
+            Some(Index {
+                slot,
+                generation: self.slots[slot as usize].generation,
+            })
+        }
+    }
+
+    fn links(&self, slot: u32) -> (u32, u32) {
+        match self.slots[slot as usize].entry {
+            Entry::Occupied { prev, next, .. } => (prev, next),
+            Entry::Free { .. } => unreachable!("followed a link to a free slot"),
+        }
+    }
+
+    fn set_prev(&mut self, slot: u32, to: u32) {
+        match &mut self.slots[slot as usize].entry {
+            Entry::Occupied { prev, .. } => *prev = to,
+            Entry::Free { .. } => unreachable!("followed a link to a free slot"),
+        }
+    }
+
+    fn set_next(&mut self, slot: u32, to: u32) {
+        match &mut self.slots[slot as usize].entry {
+            Entry::Occupied { next, .. } => *next = to,
+            Entry::Free { .. } => unreachable!("followed a link to a free slot"),
+        }
+    }
+
+    /// Puts `elem` between `prev` and `next`, which must be neighbours. `NIL` on either side
+    /// means the new node goes at that end.
+    fn link(&mut self, elem: T, prev: u32, next: u32) -> Index {
+        let entry = Entry::Occupied { elem, prev, next };
+        let slot = if self.free != NIL {
+            let slot = self.free;
+            let old = std::mem::replace(&mut self.slots[slot as usize].entry, entry);
+            match old {
+                Entry::Free { next_free } => self.free = next_free,
+                Entry::Occupied { .. } => unreachable!("occupied slot on the free list"),
+            }
+            slot
+        } else {
+            assert!(
+                self.slots.len() < NIL as usize,
+                "arena_list::List can't hold more than {} elements",
+                NIL
+            );
+            self.slots.push(Slot {
+                generation: self.floor,
+                entry,
+            });
+            (self.slots.len() - 1) as u32
+        };
+
+        self.splice_in(slot, prev, next);
+        self.len += 1;
+        self.index_of(slot).unwrap()
+    }
+
+    /// Hooks the occupied `slot` in between `prev` and `next`, which must be neighbours.
+    fn splice_in(&mut self, slot: u32, prev: u32, next: u32) {
+        self.set_prev(slot, prev);
+        self.set_next(slot, next);
+        match prev {
+            NIL => self.head = slot,
+            _ => self.set_next(prev, slot),
+        }
+        match next {
+            NIL => self.tail = slot,
+            _ => self.set_prev(next, slot),
+        }
+    }
+
+    /// Joins `slot`'s neighbours to each other. The slot itself stays occupied, with stale links.
+    fn splice_out(&mut self, slot: u32) {
+        let (prev, next) = self.links(slot);
+        match prev {
+            NIL => self.head = next,
+            _ => self.set_next(prev, next),
+        }
+        match next {
+            NIL => self.tail = prev,
+            _ => self.set_prev(next, prev),
+        }
+    }
+
+    fn unlink(&mut self, slot: u32) -> T {
+        self.splice_out(slot);
+
+        let freed = &mut self.slots[slot as usize];
+        // Every handle to this slot is stale from now on.
+        freed.generation = freed.generation.wrapping_add(1);
+        let old = std::mem::replace(
+            &mut freed.entry,
+            Entry::Free {
+                next_free: self.free,
+            },
+        );
+        self.free = slot;
+        self.len -= 1;
+        match old {
+            Entry::Occupied { elem, .. } => elem,
+            Entry::Free { .. } => unreachable!(),
+        }
+    }
+
+    pub fn push_front(&mut self, elem: T) -> Index {
+        self.link(elem, NIL, self.head)
+    }
+
+    pub fn push_back(&mut self, elem: T) -> Index {
+        self.link(elem, self.tail, NIL)
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        match self.head {
+            NIL => None,
+            head => Some(self.unlink(head)),
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        match self.tail {
+            NIL => None,
+            tail => Some(self.unlink(tail)),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If `at` is stale.
+    pub fn insert_before(&mut self, at: Index, elem: T) -> Index {
+        let slot = self.expect_slot(at);
+        let (prev, _) = self.links(slot);
+        self.link(elem, prev, slot)
+    }
+
+    /// # Panics
+    ///
+    /// If `at` is stale.
+    pub fn insert_after(&mut self, at: Index, elem: T) -> Index {
+        let slot = self.expect_slot(at);
+        let (_, next) = self.links(slot);
+        self.link(elem, slot, next)
+    }
+
+    /// move_to_front() relinks the element at the front. Nothing is copied and `index` (like
+    /// every other handle) stays valid.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is stale.
+    pub fn move_to_front(&mut self, index: Index) {
+        let slot = self.expect_slot(index);
+        if slot != self.head {
+            self.splice_out(slot);
+            self.splice_in(slot, NIL, self.head);
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If `index` is stale.
+    pub fn move_to_back(&mut self, index: Index) {
+        let slot = self.expect_slot(index);
+        if slot != self.tail {
+            self.splice_out(slot);
+            self.splice_in(slot, self.tail, NIL);
+        }
+    }
+
+    /// remove() gives back the element, or `None` if `index` is stale.
+    pub fn remove(&mut self, index: Index) -> Option<T> {
+        self.slot_of(index).map(|slot| self.unlink(slot))
+    }
+
+    pub fn contains(&self, index: Index) -> bool {
+        self.slot_of(index).is_some()
+    }
+
+    pub fn get(&self, index: Index) -> Option<&T> {
+        let slot = self.slot_of(index)?;
+        match &self.slots[slot as usize].entry {
+            Entry::Occupied { elem, .. } => Some(elem),
+            Entry::Free { .. } => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, index: Index) -> Option<&mut T> {
+        let slot = self.slot_of(index)?;
+        match &mut self.slots[slot as usize].entry {
+            Entry::Occupied { elem, .. } => Some(elem),
+            Entry::Free { .. } => None,
+        }
+    }
+
+    pub fn front_index(&self) -> Option<Index> {
+        self.index_of(self.head)
+    }
+
+    pub fn back_index(&self) -> Option<Index> {
+        self.index_of(self.tail)
+    }
+
+    /// The handle after `index`, if there is one and `index` isn't stale.
+    pub fn next_index(&self, index: Index) -> Option<Index> {
+        let (_, next) = self.links(self.slot_of(index)?);
+        self.index_of(next)
+    }
+
+    pub fn prev_index(&self, index: Index) -> Option<Index> {
+        let (prev, _) = self.links(self.slot_of(index)?);
+        self.index_of(prev)
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(self.front_index()?)
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.get(self.back_index()?)
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(self.front_index()?)
+    }
+
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(self.back_index()?)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            list: self,
+            front: self.head,
+            back: self.tail,
+            remaining: self.len,
+        }
+    }
+
+    /// iter_mut() can't just follow links through `&mut self.slots` while handing out `&mut`s
+    /// into it, so it first splits the `Vec` into one borrow per slot, which costs a pass over
+    /// all the slots and an allocation the size of `capacity()`.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let mut elems = Vec::with_capacity(self.slots.len());
+        let mut links = Vec::with_capacity(self.slots.len());
+        for slot in &mut self.slots {
+            match &mut slot.entry {
+                Entry::Occupied { elem, prev, next } => {
+                    elems.push(Some(elem));
+                    links.push((*prev, *next));
+                }
+                Entry::Free { .. } => {
+                    elems.push(None);
+                    links.push((NIL, NIL));
+                }
+            }
+        }
+        IterMut {
+            elems,
+            links,
+            front: self.head,
+            back: self.tail,
+            remaining: self.len,
+        }
+    }
+
+    /// compact() moves the elements into slots `0..len`, in list order, and drops the free
+    /// slots, so iterating walks the `Vec` front to back and no memory is left over from
+    /// removals.
+    ///
+    /// Every `Index` from before is stale afterwards, including the ones whose element didn't
+    /// move: all slots start a new generation, newer than any handle out there. Slots added
+    /// later start there too, so handles into the slots that were dropped stay stale.
+    pub fn compact(&mut self) {
+        let generation = self
+            .slots
+            .iter()
+            .map(|slot| slot.generation)
+            .fold(self.floor, u32::max)
+            .wrapping_add(1);
+        self.floor = generation;
+
+        let mut old = std::mem::take(&mut self.slots);
+        let mut slots = Vec::with_capacity(self.len);
+        let mut cur = self.head;
+        while cur != NIL {
+            let entry =
+                std::mem::replace(&mut old[cur as usize].entry, Entry::Free { next_free: NIL });
+            let (elem, next) = match entry {
+                Entry::Occupied { elem, next, .. } => (elem, next),
+                Entry::Free { .. } => unreachable!("followed a link to a free slot"),
+            };
+            let slot = slots.len() as u32;
+            slots.push(Slot {
+                generation,
+                entry: Entry::Occupied {
+                    elem,
+                    prev: if slot == 0 { NIL } else { slot - 1 },
+                    next: if next == NIL { NIL } else { slot + 1 },
+                },
+            });
+            cur = next;
+        }
+
+        self.slots = slots;
+        self.free = NIL;
+        let len = self.slots.len() as u32;
+        self.head = if len == 0 { NIL } else { 0 };
+        self.tail = if len == 0 { NIL } else { len - 1 };
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        match &self.list.slots[self.front as usize].entry {
+            Entry::Occupied { elem, next, .. } => {
+                self.front = *next;
+                Some(elem)
+            }
+            Entry::Free { .. } => unreachable!("followed a link to a free slot"),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        match &self.list.slots[self.back as usize].entry {
+            Entry::Occupied { elem, prev, .. } => {
+                self.back = *prev;
+                Some(elem)
+            }
+            Entry::Free { .. } => unreachable!("followed a link to a free slot"),
+        }
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let slot = self.front as usize;
+        self.front = self.links[slot].1;
+        self.elems[slot].take()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<&'a mut T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let slot = self.back as usize;
+        self.back = self.links[slot].0;
+        self.elems[slot].take()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.0.pop_back()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::List;
+    use crate::fourth;
+    use crate::test_util::XorShift;
+
+    #[test]
+    fn basics() {
+        let mut list = List::new();
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+
+        list.push_front(2);
+        list.push_front(1);
+        list.push_back(3);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.front(), Some(&1));
+        assert_eq!(list.back(), Some(&3));
+
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_back(), Some(2));
+        assert_eq!(list.pop_front(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn insert_and_remove_at_handles() {
+        let mut list = List::new();
+        let b = list.push_back('b');
+        let d = list.push_back('d');
+        let a = list.insert_before(b, 'a');
+        let c = list.insert_after(b, 'c');
+        list.insert_after(d, 'e');
+        assert_eq!(list.iter().collect::<String>(), "abcde");
+
+        assert_eq!(list.remove(c), Some('c'));
+        assert_eq!(list.remove(a), Some('a'));
+        assert_eq!(list.iter().collect::<String>(), "bde");
+        assert_eq!(list.next_index(b), Some(d));
+        assert_eq!(list.prev_index(b), None);
+
+        *list.get_mut(d).unwrap() = 'D';
+        assert_eq!(list.iter().rev().collect::<String>(), "eDb");
+    }
+
+    #[test]
+    fn stale_handles() {
+        let mut list = List::new();
+        let old = list.push_back(1);
+        assert_eq!(list.remove(old), Some(1));
+        assert_eq!(list.remove(old), None);
+
+        // The new element reuses the slot, but not the generation.
+        let new = list.push_back(2);
+        assert_eq!(list.capacity(), 1);
+        assert_ne!(old, new);
+        assert!(!list.contains(old));
+        assert_eq!(list.get(old), None);
+        assert_eq!(list.next_index(old), None);
+        assert_eq!(list.get(new), Some(&2));
+    }
+
+    #[test]
+    #[should_panic(expected = "stale")]
+    fn insert_at_stale_handle() {
+        let mut list = List::new();
+        let old = list.push_back(1);
+        list.pop_back();
+        list.insert_after(old, 2);
+    }
+
+    #[test]
+    fn compact() {
+        let mut list = List::new();
+        let handles: Vec<_> = (0..10).map(|i| list.push_back(i)).collect();
+        for &index in handles.iter().step_by(3) {
+            list.remove(index);
+        }
+        list.push_front(-1);
+        assert_eq!(list.capacity(), 10);
+
+        list.compact();
+        assert_eq!(list.capacity(), 7);
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            [-1, 1, 2, 4, 5, 7, 8]
+        );
+        assert!(handles.iter().all(|&index| !list.contains(index)));
+
+        // And it's still a working list.
+        let front = list.front_index().unwrap();
+        list.insert_after(front, 0);
+        assert_eq!(list.pop_back(), Some(8));
+        assert_eq!(
+            list.iter().rev().copied().collect::<Vec<_>>(),
+            [7, 5, 4, 2, 1, 0, -1]
+        );
+
+        let mut empty = List::<u8>::new();
+        empty.push_back(1);
+        empty.pop_back();
+        empty.compact();
+        assert_eq!(empty.capacity(), 0);
+        assert_eq!(empty.front(), None);
+    }
+
+    #[test]
+    fn compact_then_grow() {
+        let mut list = List::new();
+        list.push_back("a");
+        list.push_back("b");
+        let c = list.push_back("c");
+        list.remove(list.front_index().unwrap());
+        list.compact();
+
+        // "x" lands in slot 2 again, where "c" was, but in a newer generation.
+        let x = list.push_back("x");
+        assert_ne!(x, c);
+        assert!(!list.contains(c));
+        assert_eq!(list.get(c), None);
+        assert_eq!(list.get(x), Some(&"x"));
+
+        // Compacting again doesn't bring old generations back either.
+        list.remove(x);
+        list.compact();
+        list.compact();
+        let y = list.push_back("y");
+        assert!(!list.contains(c) && !list.contains(x));
+        assert_eq!(list.get(y), Some(&"y"));
+    }
+
+    #[test]
+    fn iter_both_ways() {
+        let mut list = List::new();
+        for i in 0..6 {
+            list.push_back(i);
+        }
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&5));
+        assert_eq!(iter.next_back(), Some(&4));
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next_back(), Some(&3));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        let mut iter = list.into_iter();
+        assert_eq!(iter.next_back(), Some(5));
+        assert_eq!(iter.next(), Some(0));
+    }
+
+    #[test]
+    fn move_to_ends() {
+        let mut list = List::new();
+        let handles: Vec<_> = "abcde".chars().map(|c| list.push_back(c)).collect();
+        list.move_to_back(handles[1]);
+        list.move_to_front(handles[3]);
+        list.move_to_front(handles[3]);
+        list.move_to_back(handles[4]);
+        assert_eq!(list.iter().collect::<String>(), "dacbe");
+        assert_eq!(list.iter().rev().collect::<String>(), "ebcad");
+        // Moving doesn't invalidate anything.
+        assert!(handles.iter().all(|&index| list.contains(index)));
+        assert_eq!(list.capacity(), 5);
+
+        let mut one = List::new();
+        let only = one.push_back(1);
+        one.move_to_back(only);
+        one.move_to_front(only);
+        assert_eq!(one.front_index(), Some(only));
+        assert_eq!(one.back_index(), Some(only));
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut list = List::new();
+        let handles: Vec<_> = (0..6).map(|i| list.push_back(i)).collect();
+        list.remove(handles[2]);
+        list.move_to_front(handles[4]);
+
+        let mut iter = list.iter_mut();
+        assert_eq!(iter.len(), 5);
+        *iter.next().unwrap() += 10;
+        *iter.next_back().unwrap() += 20;
+        assert_eq!(iter.map(|x| *x).collect::<Vec<_>>(), [0, 1, 3]);
+        for x in &mut list {
+            *x *= 2;
+        }
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [28, 0, 2, 6, 50]);
+    }
+
+    /// Runs the same random pushes, pops and peeks on this list and on `fourth::List` and checks
+    /// that they always agree.
+    #[test]
+    fn same_as_fourth() {
+        let mut ours = List::new();
+        let mut theirs = fourth::List::new();
+        let mut len = 0;
+        let mut rng = XorShift::new();
+
+        for i in 0..20_000 {
+            let bits = rng.next_u64();
+            match bits % 5 {
+                0 => {
+                    ours.push_front(i);
+                    theirs.push_front(i);
+                    len += 1;
+                }
+                1 => {
+                    ours.push_back(i);
+                    theirs.push_back(i);
+                    len += 1;
+                }
+                2 => {
+                    let popped = ours.pop_front();
+                    assert_eq!(popped, theirs.pop_front());
+                    len -= popped.is_some() as usize;
+                }
+                3 => {
+                    let popped = ours.pop_back();
+                    assert_eq!(popped, theirs.pop_back());
+                    len -= popped.is_some() as usize;
+                }
+                _ => {
+                    if let Some(mut front) = theirs.peek_front_mut() {
+                        *front += 1;
+                        *ours.front_mut().unwrap() += 1;
+                    }
+                    if i % 1_000 == 0 {
+                        ours.compact();
+                    }
+                }
+            }
+            assert_eq!(ours.front(), theirs.peek_front().as_deref());
+            assert_eq!(ours.back(), theirs.peek_back().as_deref());
+            assert_eq!(ours.len(), len);
+        }
+
+        let drained: Vec<_> = theirs.into_iter().collect();
+        assert_eq!(ours.iter().copied().collect::<Vec<_>>(), drained);
+        assert_eq!(ours.into_iter().rev().collect::<Vec<_>>(), {
+            let mut rev = drained;
+            rev.reverse();
+            rev
+        });
+    }
+}
@@ -0,0 +1,240 @@
+//! A persistent association-list map.
+//!
+//! This is the oldest map there is: a `third::List` of (key, value) pairs where the first pair
+//! with a matching key wins. Inserting never touches the old bindings, it just conses a new pair in
+//! front of them, which shadows any older binding of the same key:
+//!
+//! ```ignore
+//! global = (x, 1) -> (y, 2)
+//! inner  = (x, 10) ---^         inner.get(x) == 10, global.get(x) == 1
+//! ```
+//!
+//! That's exactly what a lexical environment needs: entering a scope is O(1) and shares the whole
+//! parent environment, and leaving it is just going back to the parent, which nobody has mutated.
+//!
+//! Removing can't unlink a node that other environments share, so it conses a *tombstone* instead:
+//! a binding that says "this key is unbound from here on".
+
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use crate::third::{self, List};
+
+pub struct AssocMap<K, V> {
+    bindings: List<Binding<K, V>>,
+}
+
+struct Binding<K, V> {
+    key: K,
+    /// `None` is a tombstone.
+    value: Option<V>,
+}
+
+impl<K, V> AssocMap<K, V> {
+    pub fn new() -> Self {
+        AssocMap {
+            bindings: List::new(),
+        }
+    }
+
+    /// insert() binds `key` in a new map that shadows any earlier binding of it. O(1), and the new
+    /// map shares every binding of this one.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        AssocMap {
+            bindings: self.bindings.append(Binding {
+                key,
+                value: Some(value),
+            }),
+        }
+    }
+
+    /// Every binding, newest first, including shadowed ones and tombstones (as `None`). This is
+    /// the raw spine; most callers want `iter_visible`.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.bindings.iter(),
+        }
+    }
+}
+
+impl<K: PartialEq, V> AssocMap<K, V> {
+    /// get() returns the most recent binding of `key`. O(depth of that binding).
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.key == *key)
+            .and_then(|binding| binding.value.as_ref())
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// remove() unbinds `key` by consing a tombstone for it. If `key` isn't bound to begin with,
+    /// the map is returned as is.
+    pub fn remove(&self, key: K) -> Self {
+        if !self.contains_key(&key) {
+            return self.clone();
+        }
+        AssocMap {
+            bindings: self.bindings.append(Binding { key, value: None }),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> AssocMap<K, V> {
+    /// The bindings `get` can see, newest first: shadowed bindings and removed keys are skipped.
+    pub fn iter_visible(&self) -> IterVisible<'_, K, V> {
+        IterVisible {
+            inner: self.bindings.iter(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Number of visible bindings. O(n), since we have to walk past the shadowed ones.
+    pub fn len(&self) -> usize {
+        self.iter_visible().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter_visible().next().is_none()
+    }
+}
+
+impl<K, V> Default for AssocMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Clone for AssocMap<K, V> {
+    fn clone(&self) -> Self {
+        AssocMap {
+            bindings: self.bindings.clone(),
+        }
+    }
+}
+
+pub struct Iter<'a, K: 'a, V: 'a> {
+    inner: third::Iter<'a, Binding<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, Option<&'a V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|binding| (&binding.key, binding.value.as_ref()))
+    }
+}
+
+pub struct IterVisible<'a, K: 'a, V: 'a> {
+    inner: third::Iter<'a, Binding<K, V>>,
+    /// Keys we've already walked past. Anything after them is shadowed.
+    seen: HashSet<&'a K>,
+}
+
+impl<'a, K: Hash + Eq, V> Iterator for IterVisible<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for binding in self.inner.by_ref() {
+            if !self.seen.insert(&binding.key) {
+                continue;
+            }
+            if let Some(value) = &binding.value {
+                return Some((&binding.key, value));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AssocMap;
+
+    fn visible(env: &AssocMap<&'static str, i32>) -> Vec<(&'static str, i32)> {
+        env.iter_visible().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    #[test]
+    fn basics() {
+        let env = AssocMap::new();
+        assert_eq!(env.get(&"x"), None);
+        assert!(env.is_empty());
+
+        let env = env.insert("x", 1).insert("y", 2);
+        assert_eq!(env.get(&"x"), Some(&1));
+        assert_eq!(env.get(&"y"), Some(&2));
+        assert_eq!(env.len(), 2);
+
+        let env = env.insert("x", 3);
+        assert_eq!(env.get(&"x"), Some(&3));
+        assert_eq!(visible(&env), vec![("x", 3), ("y", 2)]);
+        assert_eq!(env.iter().count(), 3);
+
+        let env = env.remove("x");
+        assert_eq!(env.get(&"x"), None);
+        assert!(!env.contains_key(&"x"));
+        assert_eq!(visible(&env), vec![("y", 2)]);
+
+        // Removing something that isn't there doesn't grow the spine.
+        let same = env.remove("z");
+        assert!(same.bindings.ptr_eq(&env.bindings));
+
+        let env = env.insert("x", 4);
+        assert_eq!(env.get(&"x"), Some(&4));
+        assert_eq!(
+            env.iter()
+                .map(|(k, v)| (*k, v.copied()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("x", Some(4)),
+                ("x", None),
+                ("x", Some(3)),
+                ("y", Some(2)),
+                ("x", Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_scopes() {
+        let global = AssocMap::new().insert("print", 0).insert("x", 1);
+        let before = visible(&global);
+
+        let outer = global.insert("x", 10).insert("y", 20);
+        // Extending a scope shares the parent's spine.
+        assert!(outer.bindings.tail().tail().ptr_eq(&global.bindings));
+
+        let inner = outer.insert("y", 200).remove("print");
+        assert!(inner.bindings.tail().tail().ptr_eq(&outer.bindings));
+        assert_eq!(inner.get(&"x"), Some(&10));
+        assert_eq!(inner.get(&"y"), Some(&200));
+        assert_eq!(inner.get(&"print"), None);
+        assert_eq!(visible(&inner), vec![("y", 200), ("x", 10)]);
+
+        // Popping back out: every outer scope still sees what it saw before.
+        drop(inner);
+        assert_eq!(outer.get(&"y"), Some(&20));
+        assert_eq!(outer.get(&"print"), Some(&0));
+        assert_eq!(visible(&outer), vec![("y", 20), ("x", 10), ("print", 0)]);
+
+        drop(outer);
+        assert_eq!(visible(&global), before);
+        assert_eq!(global.get(&"x"), Some(&1));
+    }
+
+    #[test]
+    fn sibling_scopes() {
+        let parent = AssocMap::new().insert("a", 1);
+        let left = parent.insert("b", 2);
+        let right = parent.remove("a").insert("c", 3);
+
+        assert_eq!(visible(&left), vec![("b", 2), ("a", 1)]);
+        assert_eq!(visible(&right), vec![("c", 3)]);
+        assert_eq!(visible(&parent), vec![("a", 1)]);
+    }
+}
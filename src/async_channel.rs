@@ -0,0 +1,671 @@
+//! An async multi-producer single-consumer channel.
+//!
+//! Messages go through a `concurrent::MpscQueue` exactly like in `channel`; what's different is
+//! how we wait. A blocked thread can sleep on a `Condvar`, but a blocked future has to return
+//! `Pending` and leave a `Waker` behind for whoever makes progress possible. Those wakers are kept
+//! in an *intrusive* doubly linked list: each node lives inside the future that is waiting,
+//! not in a separate allocation.
+//!
+//! ```ignore
+//! state.send_waiters: head -> [Waiter in Send #1] <-> [Waiter in Send #2] <- tail
+//! ```
+//!
+//! That's what makes cancellation cheap. A future that is dropped while waiting unlinks its own
+//! node in O(1), which it can only do because the list is doubly linked. And since the node is
+//! part of the future, the future must not move while it's linked: both futures are `!Unpin`.
+//!
+//! Everything about a waiter, including its links, is only touched with the channel's lock held.
+//!
+//! Only `std::task` is used, so any executor will do; `block_on` is a minimal one.
+
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::marker::PhantomPinned;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+pub use crate::channel::{RecvError, SendError, TryRecvError, TrySendError};
+use crate::concurrent::{MpscQueue, PopResult};
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    queue: MpscQueue<T>,
+    cap: Option<usize>,
+    state: Mutex<State>,
+}
+
+struct State {
+    len: usize,
+    senders: usize,
+    receiver: bool,
+    recv_waiters: WaitList,
+    send_waiters: WaitList,
+}
+
+/// Lives inside a `Recv` or `Send` future.
+struct Waiter {
+    waker: Option<Waker>,
+    prev: *mut Waiter,
+    next: *mut Waiter,
+    /// In a list right now.
+    queued: bool,
+    /// Taken off the list by a wakeup that the future hasn't acted on yet.
+    notified: bool,
+}
+
+struct WaitList {
+    head: *mut Waiter,
+    tail: *mut Waiter,
+}
+
+// The raw pointers in the wait lists point into futures that unlink themselves before they go
+// away, and are only followed with the lock held.
+unsafe impl Send for State {}
+
+impl Waiter {
+    fn new() -> Self {
+        Waiter {
+            waker: None,
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+            queued: false,
+            notified: false,
+        }
+    }
+}
+
+impl WaitList {
+    fn new() -> Self {
+        WaitList {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    /// # Safety
+    ///
+    /// `waiter` must be valid and not in any list.
+    unsafe fn push_back(&mut self, waiter: *mut Waiter) {
+        (*waiter).prev = self.tail;
+        (*waiter).next = ptr::null_mut();
+        (*waiter).queued = true;
+        match self.tail.as_mut() {
+            Some(tail) => tail.next = waiter,
+            None => self.head = waiter,
+        }
+        self.tail = waiter;
+    }
+
+    /// # Safety
+    ///
+    /// `waiter` must be in this list.
+    unsafe fn remove(&mut self, waiter: *mut Waiter) {
+        let prev = (*waiter).prev;
+        let next = (*waiter).next;
+        match prev.as_mut() {
+            Some(prev) => prev.next = next,
+            None => self.head = next,
+        }
+        match next.as_mut() {
+            Some(next) => next.prev = prev,
+            None => self.tail = prev,
+        }
+        (*waiter).prev = ptr::null_mut();
+        (*waiter).next = ptr::null_mut();
+        (*waiter).queued = false;
+    }
+
+    /// Unlinks the oldest waiter, marks it notified and hands back its waker. The caller wakes it
+    /// after letting go of the lock.
+    fn notify_one(&mut self) -> Option<Waker> {
+        let waiter = self.head;
+        if waiter.is_null() {
+            return None;
+        }
+        unsafe {
+            self.remove(waiter);
+            (*waiter).notified = true;
+            (*waiter).waker.take()
+        }
+    }
+
+    fn notify_all(&mut self) -> Vec<Waker> {
+        let mut wakers = Vec::new();
+        while !self.is_empty() {
+            wakers.extend(self.notify_one());
+        }
+        wakers
+    }
+}
+
+/// A channel that holds at most `cap` messages; `send` waits while it's full.
+///
+/// # Panics
+///
+/// If `cap` is 0.
+pub fn bounded<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "channel capacity must be at least 1");
+    channel(Some(cap))
+}
+
+/// A channel whose `send` is ready right away.
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    channel(None)
+}
+
+fn channel<T>(cap: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: MpscQueue::new(),
+        cap,
+        state: Mutex::new(State {
+            len: 0,
+            senders: 1,
+            receiver: true,
+            recv_waiters: WaitList::new(),
+            send_waiters: WaitList::new(),
+        }),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_full(&self, state: &State) -> bool {
+        self.cap.is_some_and(|cap| state.len >= cap)
+    }
+
+    /// Called with the lock held and room in the buffer. Returns the receiver to wake.
+    fn push(&self, state: &mut State, elem: T) -> Option<Waker> {
+        state.len += 1;
+        self.queue.push(elem);
+        state.recv_waiters.notify_one()
+    }
+
+    /// Called with the lock held and `len > 0`. Returns the sender to wake.
+    fn pop(&self, state: &mut State) -> (T, Option<Waker>) {
+        state.len -= 1;
+        let elem = match self.queue.pop() {
+            PopResult::Data(elem) => elem,
+            // Every push finished under the lock we're holding.
+            PopResult::Empty | PopResult::Inconsistent => unreachable!(),
+        };
+        (elem, state.send_waiters.notify_one())
+    }
+}
+
+impl<T> Sender<T> {
+    /// send() waits while a bounded channel is full, and fails, handing the message back, once
+    /// the receiver is gone.
+    pub fn send(&self, elem: T) -> SendFuture<'_, T> {
+        SendFuture {
+            sender: self,
+            elem: Some(elem),
+            waiter: UnsafeCell::new(Waiter::new()),
+            _pin: PhantomPinned,
+        }
+    }
+
+    pub fn try_send(&self, elem: T) -> Result<(), TrySendError<T>> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        if !state.receiver {
+            Err(TrySendError::Disconnected(elem))
+        } else if shared.is_full(&state) {
+            Err(TrySendError::Full(elem))
+        } else {
+            let waker = shared.push(&mut state, elem);
+            drop(state);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+            Ok(())
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// recv() waits for a message. Messages sent before the last sender left are still
+    /// delivered; only then does it fail.
+    pub fn recv(&self) -> RecvFuture<'_, T> {
+        RecvFuture {
+            receiver: self,
+            waiter: UnsafeCell::new(Waiter::new()),
+            _pin: PhantomPinned,
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        if state.len > 0 {
+            let (elem, waker) = shared.pop(&mut state);
+            drop(state);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+            Ok(elem)
+        } else if state.senders == 0 {
+            Err(TryRecvError::Disconnected)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+}
+
+/// Future returned by `Sender::send`.
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    /// Taken once the future completes.
+    elem: Option<T>,
+    waiter: UnsafeCell<Waiter>,
+    _pin: PhantomPinned,
+}
+
+/// Future returned by `Receiver::recv`.
+pub struct RecvFuture<'a, T> {
+    receiver: &'a Receiver<T>,
+    waiter: UnsafeCell<Waiter>,
+    _pin: PhantomPinned,
+}
+
+unsafe impl<'a, T: Send> Send for SendFuture<'a, T> {}
+unsafe impl<'a, T: Send> Send for RecvFuture<'a, T> {}
+
+/// Parks `waiter` on `list` (or just refreshes its waker if it's already there).
+///
+/// # Safety
+///
+/// `waiter` must stay put until it's unlinked, and the lock for `list` must be held.
+unsafe fn wait_on(list: &mut WaitList, waiter: *mut Waiter, cx: &Context<'_>) {
+    let waker = &mut (*waiter).waker;
+    if !waker.as_ref().is_some_and(|w| w.will_wake(cx.waker())) {
+        *waker = Some(cx.waker().clone());
+    }
+    (*waiter).notified = false;
+    if !(*waiter).queued {
+        list.push_back(waiter);
+    }
+}
+
+/// Takes `waiter` out of `list` for good. If a wakeup was meant for it that it will now never act
+/// on, pass that wakeup on to the next waiter instead of losing it.
+///
+/// # Safety
+///
+/// The lock for `list` must be held.
+unsafe fn leave(list: &mut WaitList, waiter: *mut Waiter) -> Option<Waker> {
+    if (*waiter).queued {
+        list.remove(waiter);
+        None
+    } else if (*waiter).notified {
+        (*waiter).notified = false;
+        list.notify_one()
+    } else {
+        None
+    }
+}
+
+impl<'a, T> Future for SendFuture<'a, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // We never move out of `self`, only take `elem` and hand out a pointer to the waiter.
+        let this = unsafe { self.get_unchecked_mut() };
+        let shared = &*this.sender.shared;
+        let waiter = this.waiter.get();
+        let elem = this
+            .elem
+            .take()
+            .expect("SendFuture polled after completion");
+
+        let mut state = shared.lock();
+        if !state.receiver {
+            unsafe { leave(&mut state.send_waiters, waiter) };
+            return Poll::Ready(Err(SendError(elem)));
+        }
+        if shared.is_full(&state) {
+            this.elem = Some(elem);
+            unsafe { wait_on(&mut state.send_waiters, waiter, cx) };
+            return Poll::Pending;
+        }
+
+        // Leaving can't hand a wakeup to another sender here: we're using the room we were
+        // woken for.
+        unsafe {
+            if (*waiter).queued {
+                state.send_waiters.remove(waiter);
+            }
+            (*waiter).notified = false;
+        }
+        let waker = shared.push(&mut state, elem);
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, T> Drop for SendFuture<'a, T> {
+    fn drop(&mut self) {
+        let waiter = self.waiter.get();
+        let mut state = self.sender.shared.lock();
+        let waker = unsafe { leave(&mut state.send_waiters, waiter) };
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<'a, T> Future for RecvFuture<'a, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let shared = &*this.receiver.shared;
+        let waiter = this.waiter.get();
+
+        let mut state = shared.lock();
+        if state.len > 0 {
+            unsafe {
+                if (*waiter).queued {
+                    state.recv_waiters.remove(waiter);
+                }
+                (*waiter).notified = false;
+            }
+            let (elem, waker) = shared.pop(&mut state);
+            drop(state);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+            return Poll::Ready(Ok(elem));
+        }
+        if state.senders == 0 {
+            unsafe { leave(&mut state.recv_waiters, waiter) };
+            return Poll::Ready(Err(RecvError));
+        }
+        unsafe { wait_on(&mut state.recv_waiters, waiter, cx) };
+        Poll::Pending
+    }
+}
+
+impl<'a, T> Drop for RecvFuture<'a, T> {
+    fn drop(&mut self) {
+        let waiter = self.waiter.get();
+        let mut state = self.receiver.shared.lock();
+        let waker = unsafe { leave(&mut state.recv_waiters, waiter) };
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            let wakers = state.recv_waiters.notify_all();
+            drop(state);
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver = false;
+        let wakers = state.send_waiters.notify_all();
+        drop(state);
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Runs a future to completion on the current thread, parking it while the future is pending.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            // A spurious unpark just means one more poll.
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Counts its wakeups, so tests can poll by hand and see who got woken.
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn counting_waker() -> (Arc<CountingWaker>, Waker) {
+        let count = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        (count.clone(), Waker::from(count))
+    }
+
+    fn poll_once<F: Future>(future: Pin<&mut F>, waker: &Waker) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(waker))
+    }
+
+    #[test]
+    fn basics() {
+        let (tx, rx) = unbounded();
+        block_on(tx.send(1)).unwrap();
+        tx.try_send(2).unwrap();
+        assert_eq!(block_on(rx.recv()), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        drop(tx);
+        assert_eq!(block_on(rx.recv()), Err(RecvError));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn recv_wakes_up() {
+        let (tx, rx) = unbounded();
+        let (count, waker) = counting_waker();
+
+        let mut recv = Box::pin(rx.recv());
+        assert_eq!(poll_once(recv.as_mut(), &waker), Poll::Pending);
+        assert_eq!(poll_once(recv.as_mut(), &waker), Poll::Pending);
+        // Polling twice doesn't queue twice.
+        assert_eq!(count.0.load(Ordering::SeqCst), 0);
+
+        tx.try_send(5).unwrap();
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll_once(recv.as_mut(), &waker), Poll::Ready(Ok(5)));
+    }
+
+    #[test]
+    fn send_waits_while_full() {
+        let (tx, rx) = bounded(1);
+        tx.try_send(1).unwrap();
+        let (count, waker) = counting_waker();
+
+        let mut send = Box::pin(tx.send(2));
+        assert!(poll_once(send.as_mut(), &waker).is_pending());
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll_once(send.as_mut(), &waker), Poll::Ready(Ok(())));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn dropped_future_unlinks() {
+        let (tx, rx) = bounded(1);
+        tx.try_send(0).unwrap();
+        let (_, waker) = counting_waker();
+
+        let mut sends: Vec<_> = (1..4).map(|i| Box::pin(tx.send(i))).collect();
+        for send in &mut sends {
+            assert!(poll_once(send.as_mut(), &waker).is_pending());
+        }
+        // Drop the one in the middle, then the rest.
+        drop(sends.remove(1));
+        drop(sends);
+        assert!(tx.shared.lock().send_waiters.is_empty());
+
+        let mut recv = Box::pin(rx.recv());
+        assert_eq!(poll_once(recv.as_mut(), &waker), Poll::Ready(Ok(0)));
+        drop(recv);
+        let mut recv = Box::pin(rx.recv());
+        assert!(poll_once(recv.as_mut(), &waker).is_pending());
+        drop(recv);
+        assert!(rx.shared.lock().recv_waiters.is_empty());
+    }
+
+    #[test]
+    fn dropped_future_passes_wakeup_on() {
+        let (tx, rx) = bounded(1);
+        tx.try_send(0).unwrap();
+        let (first_count, first_waker) = counting_waker();
+        let (second_count, second_waker) = counting_waker();
+
+        let mut first = Box::pin(tx.send(1));
+        let mut second = Box::pin(tx.send(2));
+        assert!(poll_once(first.as_mut(), &first_waker).is_pending());
+        assert!(poll_once(second.as_mut(), &second_waker).is_pending());
+
+        // Room for one: the first sender is woken...
+        assert_eq!(rx.try_recv(), Ok(0));
+        assert_eq!(first_count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(second_count.0.load(Ordering::SeqCst), 0);
+
+        // ...but gives up without sending, so the second one gets the wakeup instead.
+        drop(first);
+        assert_eq!(second_count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            poll_once(second.as_mut(), &second_waker),
+            Poll::Ready(Ok(()))
+        );
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn disconnect_wakes_waiters() {
+        let (tx, rx) = bounded(1);
+        tx.try_send(1).unwrap();
+        let (count, waker) = counting_waker();
+        let mut send = Box::pin(tx.send(2));
+        assert!(poll_once(send.as_mut(), &waker).is_pending());
+        drop(rx);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            poll_once(send.as_mut(), &waker),
+            Poll::Ready(Err(SendError(2)))
+        );
+        assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+
+        let (tx, rx) = unbounded::<u8>();
+        let (count, waker) = counting_waker();
+        let mut recv = Box::pin(rx.recv());
+        assert!(poll_once(recv.as_mut(), &waker).is_pending());
+        drop(tx);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            poll_once(recv.as_mut(), &waker),
+            Poll::Ready(Err(RecvError))
+        );
+    }
+
+    #[test]
+    fn across_threads() {
+        const SENDERS: usize = 4;
+        const PER_SENDER: usize = 5_000;
+
+        let (tx, rx) = bounded(8);
+        let handles: Vec<_> = (0..SENDERS)
+            .map(|s| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    block_on(async {
+                        for seq in 0..PER_SENDER {
+                            tx.send((s, seq)).await.unwrap();
+                        }
+                    })
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let next = block_on(async {
+            let mut next = [0; SENDERS];
+            while let Ok((s, seq)) = rx.recv().await {
+                assert_eq!(seq, next[s]);
+                next[s] += 1;
+            }
+            next
+        });
+        assert!(next.iter().all(|&n| n == PER_SENDER));
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn block_on_waits_for_another_thread() {
+        let (tx, rx) = unbounded();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.try_send("late").unwrap();
+        });
+        assert_eq!(block_on(rx.recv()), Ok("late"));
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,95 @@
+//! Scan throughput of `unrolled::List` against `second::List` and `Vec`.
+//!
+//! ```ignore
+//! cargo run --release --bin scan_bench [elements] [rounds]
+//! ```
+//!
+//! Each list gets the same `u64`s and is summed `rounds` times; the best round is reported, to
+//! keep one-off hiccups out of it. `second::List` is built by pushing every element separately,
+//! so on a fresh heap its nodes come out nearly contiguous, which flatters it. The scattered run
+//! interleaves other allocations, which is closer to what a long-running program sees.
+
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use lists::{second, unrolled};
+
+fn best_of<F: FnMut() -> u64>(rounds: usize, mut scan: F) -> (Duration, u64) {
+    let mut best = Duration::MAX;
+    let mut sum = 0;
+    for _ in 0..rounds {
+        let start = Instant::now();
+        sum = black_box(scan());
+        best = best.min(start.elapsed());
+    }
+    (best, sum)
+}
+
+fn report(name: &str, elements: usize, (time, sum): (Duration, u64)) {
+    let ns = time.as_nanos() as f64 / elements as f64;
+    println!("{:<24} {:>8.3} ns/elem   (sum {})", name, ns, sum);
+}
+
+/// `second::List` with something else allocated between every two nodes, so neighbouring
+/// nodes don't share a cache line.
+fn scattered_second(elements: usize) -> second::List<u64> {
+    let mut list = second::List::new();
+    let mut junk = Vec::with_capacity(elements);
+    for i in (0..elements as u64).rev() {
+        list.push(i);
+        junk.push(Box::new([0u8; 48]));
+    }
+    drop(junk);
+    list
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let elements: usize = args
+        .next()
+        .map_or(1_000_000, |a| a.parse().expect("elements"));
+    let rounds: usize = args.next().map_or(20, |a| a.parse().expect("rounds"));
+    println!("{} elements, best of {} rounds", elements, rounds);
+
+    let vec: Vec<u64> = (0..elements as u64).collect();
+    report("Vec", elements, best_of(rounds, || vec.iter().sum()));
+
+    let mut second = second::List::new();
+    for i in (0..elements as u64).rev() {
+        second.push(i);
+    }
+    report(
+        "second::List",
+        elements,
+        best_of(rounds, || second.iter().sum()),
+    );
+
+    let scattered = scattered_second(elements);
+    report(
+        "second::List scattered",
+        elements,
+        best_of(rounds, || scattered.iter().sum()),
+    );
+
+    let unrolled8: unrolled::List<u64, 8> = (0..elements as u64).collect();
+    report(
+        "unrolled::List<_, 8>",
+        elements,
+        best_of(rounds, || unrolled8.iter().sum()),
+    );
+
+    let unrolled32: unrolled::List<u64, 32> = (0..elements as u64).collect();
+    report(
+        "unrolled::List<_, 32>",
+        elements,
+        best_of(rounds, || unrolled32.iter().sum()),
+    );
+
+    let unrolled128: unrolled::List<u64, 128> = (0..elements as u64).collect();
+    report(
+        "unrolled::List<_, 128>",
+        elements,
+        best_of(rounds, || unrolled128.iter().sum()),
+    );
+}
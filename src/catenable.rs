@@ -0,0 +1,774 @@
+//! A persistent list with O(1) concatenation: Okasaki's catenable lists.
+//!
+//! Gluing two `third::List`s together means copying the whole first one. A `CatList` is a tree
+//! instead: the first element sits at the root, and the rest of the list is the root's children,
+//! read oldest first. Appending a list just makes it the root's newest child:
+//!
+//! ```ignore
+//! [1, 2] ++ [3, 4] ++ [5]
+//!
+//!        1'
+//!      / | \
+//!     2  3  5          1 is copied, the rest is shared
+//!        |
+//!        4
+//! ```
+//!
+//! The children are kept in a persistent queue, so adding one to a copy of the root is O(1) and
+//! every other node is shared with the lists we started from.
+//!
+//! Taking the first element off (`uncons`) has to turn the root's children back into one list.
+//! Doing that eagerly costs one copied node per child, every time, and an old version with a lot
+//! of children can be unconsed (or appended to and then unconsed) as often as you like. So it's
+//! done lazily, the way Okasaki does it: the oldest child becomes the new root, and the others
+//! are put behind it as a single *suspension*, which links them (again lazily) the first time
+//! somebody looks inside. A suspension remembers its result, so every version that shares it
+//! shares the work too:
+//!
+//! ```ignore
+//! root: 1, children [a, b, c]
+//!
+//! uncons -> a', children [...a's children, $[b, c]]        $ = not linked yet
+//! ```
+//!
+//! The queue has to stay fast when old versions are reused as well, so it's Okasaki's banker's
+//! queue: a lazily built front and a `third::List` at the back, newest first. When the back gets
+//! longer than the front, the front becomes "front, then the back reversed", to be worked out a
+//! cell at a time as it's used. With all of that, `append`, `cons` and `snoc` are O(1) and
+//! `uncons` is amortized O(1), however the versions are shared.
+//!
+//! Suspensions here are data ("link these pieces", "this front, then that back reversed"), not
+//! closures. Trees can be as deep as the list is long, so dropping is one loop with an explicit
+//! stack that can take apart every kind of node, and iterating and forcing suspensions are loops
+//! too.
+
+use std::cell::{Cell, OnceCell};
+use std::iter::FromIterator;
+use std::rc::Rc;
+
+use crate::third;
+
+pub struct CatList<T> {
+    root: Link<T>,
+}
+
+type Link<T> = Option<Rc<Node<T>>>;
+
+struct Node<T> {
+    elem: T,
+    len: usize,
+    /// The rest of the list, in pieces, oldest first. None of them are empty.
+    children: Queue<T>,
+}
+
+/// A piece of a list, which may not have been put together yet.
+type Susp<T> = Rc<Suspension<T>>;
+
+struct Suspension<T> {
+    len: usize,
+    value: OnceCell<CatList<T>>,
+    /// The pieces to link into `value`. Taken out right before that happens.
+    pending: Cell<Option<Queue<T>>>,
+}
+
+/// Okasaki's banker's queue. There are never more pieces at the back than at the front, so the
+/// front is only empty when the queue is.
+struct Queue<T> {
+    front: Stream<T>,
+    front_len: usize,
+    /// Newest first.
+    rear: third::List<Susp<T>>,
+    rear_len: usize,
+    /// How many elements the pieces hold between them.
+    len: usize,
+}
+
+/// The front of a queue: a list of pieces whose cells are worked out when first looked at.
+struct Stream<T>(Option<Rc<StreamCell<T>>>);
+
+struct StreamCell<T> {
+    value: OnceCell<(Susp<T>, Stream<T>)>,
+    pending: Cell<Option<Rotation<T>>>,
+}
+
+/// A stream cell that's `front ++ reverse(rear)`, not worked out yet.
+struct Rotation<T> {
+    front: Stream<T>,
+    rear: third::List<Susp<T>>,
+}
+
+pub struct Iter<'a, T: 'a> {
+    /// Subtrees still to visit, the next one on top.
+    pending: Vec<&'a Node<T>>,
+}
+
+impl<T> Stream<T> {
+    fn cons(piece: Susp<T>, rest: Stream<T>) -> Self {
+        let value = OnceCell::new();
+        let _ = value.set((piece, rest));
+        Stream(Some(Rc::new(StreamCell {
+            value,
+            pending: Cell::new(None),
+        })))
+    }
+
+    /// `front ++ reverse(rear)`, for a non-empty `rear`.
+    fn rotate(front: Stream<T>, rear: third::List<Susp<T>>) -> Self {
+        Stream(Some(Rc::new(StreamCell {
+            value: OnceCell::new(),
+            pending: Cell::new(Some(Rotation { front, rear })),
+        })))
+    }
+
+    /// The first piece and the rest. Working out a rotated cell takes one step of its front,
+    /// which may be a rotation that isn't worked out yet either, but each rotation at least
+    /// doubles the front, so that's O(log n) deep at most.
+    fn uncons(&self) -> Option<&(Susp<T>, Stream<T>)> {
+        let cell = self.0.as_ref()?;
+        Some(cell.value.get_or_init(|| {
+            let Rotation { front, rear } =
+                cell.pending.take().expect("stream cell without a value");
+            match front.uncons() {
+                Some((piece, rest)) => (piece.clone(), Stream::rotate(rest.clone(), rear)),
+                None => {
+                    let mut pieces: Vec<Susp<T>> = rear.iter().cloned().collect();
+                    let oldest = pieces.pop().expect("rotated an empty rear");
+                    let rest = pieces
+                        .into_iter()
+                        .fold(Stream(None), |rest, piece| Stream::cons(piece, rest));
+                    (oldest, rest)
+                }
+            }
+        }))
+    }
+}
+
+impl<T> Clone for Stream<T> {
+    fn clone(&self) -> Self {
+        Stream(self.0.clone())
+    }
+}
+
+impl<T> Queue<T> {
+    fn new() -> Self {
+        Queue {
+            front: Stream(None),
+            front_len: 0,
+            rear: third::List::new(),
+            rear_len: 0,
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.front_len == 0
+    }
+
+    /// Puts the queue back in balance: once the back outgrows the front, it's moved over.
+    fn check(
+        front: Stream<T>,
+        front_len: usize,
+        rear: third::List<Susp<T>>,
+        rear_len: usize,
+        len: usize,
+    ) -> Self {
+        if rear_len <= front_len {
+            Queue {
+                front,
+                front_len,
+                rear,
+                rear_len,
+                len,
+            }
+        } else {
+            Queue {
+                front: Stream::rotate(front, rear),
+                front_len: front_len + rear_len,
+                rear: third::List::new(),
+                rear_len: 0,
+                len,
+            }
+        }
+    }
+
+    fn snoc(&self, piece: Susp<T>) -> Self {
+        let len = self.len + piece.len;
+        Self::check(
+            self.front.clone(),
+            self.front_len,
+            self.rear.append(piece),
+            self.rear_len + 1,
+            len,
+        )
+    }
+
+    fn head(&self) -> Option<&Susp<T>> {
+        self.front.uncons().map(|(piece, _)| piece)
+    }
+
+    fn tail(&self) -> Self {
+        match self.front.uncons() {
+            Some((piece, rest)) => Self::check(
+                rest.clone(),
+                self.front_len - 1,
+                self.rear.clone(),
+                self.rear_len,
+                self.len - piece.len,
+            ),
+            None => Queue::new(),
+        }
+    }
+
+    /// Every piece, oldest first.
+    fn pieces(&self) -> Vec<&Susp<T>> {
+        let mut pieces = Vec::with_capacity(self.front_len + self.rear_len);
+        let mut cur = &self.front;
+        while let Some((piece, rest)) = cur.uncons() {
+            pieces.push(piece);
+            cur = rest;
+        }
+        let back = pieces.len();
+        pieces.extend(self.rear.iter());
+        pieces[back..].reverse();
+        pieces
+    }
+}
+
+impl<T> Suspension<T> {
+    fn ready(list: CatList<T>) -> Susp<T> {
+        let len = list.len();
+        let value = OnceCell::new();
+        let _ = value.set(list);
+        Rc::new(Suspension {
+            len,
+            value,
+            pending: Cell::new(None),
+        })
+    }
+
+    fn delayed(pieces: Queue<T>) -> Susp<T> {
+        Rc::new(Suspension {
+            len: pieces.len,
+            value: OnceCell::new(),
+            pending: Cell::new(Some(pieces)),
+        })
+    }
+}
+
+impl<T: Clone> Suspension<T> {
+    /// force() links the pieces the first time it's called, and hands back the same list after
+    /// that. The oldest piece may be a suspension that hasn't been forced either, and so may its
+    /// oldest piece, and so on: unconsing the same end of a list over and over stacks them up as
+    /// deep as the list is long. So it goes down that chain with a loop, then links the pieces on
+    /// the way back up.
+    fn force(&self) -> &CatList<T> {
+        if let Some(list) = self.value.get() {
+            return list;
+        }
+        // pieces[i] is what level i of the chain still has to link, and firsts[i] is its oldest
+        // piece, which is level i + 1. Level 0 is `self`.
+        let mut pieces = Vec::new();
+        let mut firsts: Vec<Susp<T>> = Vec::new();
+        let mut cur = self.pending.take().expect("suspension without a value");
+        loop {
+            let first = cur.head().expect("linking no pieces").clone();
+            pieces.push(cur);
+            if first.value.get().is_some() {
+                firsts.push(first);
+                break;
+            }
+            cur = first.pending.take().expect("suspension without a value");
+            firsts.push(first);
+        }
+
+        while let Some(level) = pieces.pop() {
+            let first = firsts.pop().expect("one oldest piece per level");
+            let list = CatList::link_behind(first.value.get().expect("forced below"), &level);
+            let susp: &Suspension<T> = firsts.last().map_or(self, |susp| susp);
+            let _ = susp.value.set(list);
+        }
+        self.value.get().expect("forced last")
+    }
+}
+
+impl<T> CatList<T> {
+    pub fn new() -> Self {
+        CatList { root: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |node| node.len)
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.root.as_ref().map(|node| &node.elem)
+    }
+
+    /// ptr_eq() tells whether the two are the very same version. Two empty lists are the same list.
+    pub fn ptr_eq(&self, other: &CatList<T>) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Clone> CatList<T> {
+    fn singleton(elem: T) -> Self {
+        CatList {
+            root: Some(Rc::new(Node {
+                elem,
+                len: 1,
+                children: Queue::new(),
+            })),
+        }
+    }
+
+    /// A copy of `front`'s root with `back` as its newest child. `back` may not be empty.
+    fn link(front: &Rc<Node<T>>, back: Susp<T>) -> CatList<T> {
+        CatList {
+            root: Some(Rc::new(Node {
+                elem: front.elem.clone(),
+                len: front.len + back.len,
+                children: front.children.snoc(back),
+            })),
+        }
+    }
+
+    /// Turns a non-empty queue of pieces into one list: the oldest piece, with a suspension of
+    /// the others as its newest child.
+    fn link_all(pieces: &Queue<T>) -> CatList<T> {
+        let first = pieces.head().expect("linking no pieces").force();
+        Self::link_behind(first, pieces)
+    }
+
+    /// `link_all`, with the oldest piece already forced to `first`.
+    fn link_behind(first: &CatList<T>, pieces: &Queue<T>) -> CatList<T> {
+        let others = pieces.tail();
+        if others.is_empty() {
+            return first.clone();
+        }
+        let root = first.root.as_ref().expect("pieces are never empty");
+        Self::link(root, Suspension::delayed(others))
+    }
+
+    /// append() concatenates: all of `self`, then all of `other`. O(1) either way round.
+    ///
+    /// Not to be confused with `third::List::append`, which puts a single element in front.
+    pub fn append(&self, other: &CatList<T>) -> CatList<T> {
+        match &self.root {
+            None => other.clone(),
+            Some(_) if other.is_empty() => self.clone(),
+            Some(front) => Self::link(front, Suspension::ready(other.clone())),
+        }
+    }
+
+    /// cons() puts an element in front.
+    pub fn cons(&self, elem: T) -> CatList<T> {
+        Self::singleton(elem).append(self)
+    }
+
+    /// snoc() puts an element at the back.
+    pub fn snoc(&self, elem: T) -> CatList<T> {
+        self.append(&Self::singleton(elem))
+    }
+
+    /// uncons() splits off the first element, in amortized O(1). See the module docs.
+    pub fn uncons(&self) -> Option<(&T, CatList<T>)> {
+        let node = self.root.as_ref()?;
+        let rest = if node.children.is_empty() {
+            CatList::new()
+        } else {
+            Self::link_all(&node.children)
+        };
+        Some((&node.elem, rest))
+    }
+
+    /// tail() is everything but the first element. The tail of an empty list is empty.
+    pub fn tail(&self) -> CatList<T> {
+        self.uncons().map_or_else(CatList::new, |(_, rest)| rest)
+    }
+
+    /// iter() goes in order. It works out whatever suspensions it passes, so it needs `Clone`
+    /// like the rest.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            pending: self.root.as_deref().into_iter().collect(),
+        }
+    }
+
+    /// from_list() copies the elements of a `third::List`, in the same order.
+    pub fn from_list(list: &third::List<T>) -> Self {
+        list.iter().cloned().collect()
+    }
+
+    /// to_list() copies the elements into a `third::List`, in the same order.
+    pub fn to_list(&self) -> third::List<T> {
+        let elems: Vec<&T> = self.iter().collect();
+        elems
+            .into_iter()
+            .rev()
+            .fold(third::List::new(), |list, elem| list.append(elem.clone()))
+    }
+}
+
+impl<T> Default for CatList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for CatList<T> {
+    fn clone(&self) -> Self {
+        CatList {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for CatList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(CatList::new(), |list, elem| list.snoc(elem))
+    }
+}
+
+/// Everything a `CatList` is made of that can be shared, and so has to be taken apart carefully.
+enum Garbage<T> {
+    Node(Rc<Node<T>>),
+    Suspension(Susp<T>),
+    Stream(Rc<StreamCell<T>>),
+    Pieces(third::List<Susp<T>>),
+}
+
+/// The `Rc::try_unwrap` destructor once more, over every kind of node at once. Whatever we turn
+/// out to be the last owner of is taken apart here and its insides go on the stack, so nothing
+/// gets dropped recursively; whatever is still shared just loses a count.
+fn dismantle<T>(garbage: Garbage<T>) {
+    let mut pending = vec![garbage];
+    while let Some(garbage) = pending.pop() {
+        match garbage {
+            Garbage::Node(node) => {
+                if let Ok(node) = Rc::try_unwrap(node) {
+                    queue_garbage(node.children, &mut pending);
+                }
+            }
+            Garbage::Suspension(susp) => {
+                if let Ok(mut susp) = Rc::try_unwrap(susp) {
+                    if let Some(mut list) = susp.value.take() {
+                        pending.extend(list.root.take().map(Garbage::Node));
+                    }
+                    if let Some(pieces) = susp.pending.take() {
+                        queue_garbage(pieces, &mut pending);
+                    }
+                }
+            }
+            Garbage::Stream(cell) => {
+                if let Ok(cell) = Rc::try_unwrap(cell) {
+                    if let Some((piece, mut rest)) = cell.value.into_inner() {
+                        pending.push(Garbage::Suspension(piece));
+                        pending.extend(rest.0.take().map(Garbage::Stream));
+                    }
+                    if let Some(Rotation { mut front, rear }) = cell.pending.into_inner() {
+                        pending.extend(front.0.take().map(Garbage::Stream));
+                        pending.push(Garbage::Pieces(rear));
+                    }
+                }
+            }
+            Garbage::Pieces(mut list) => {
+                let mut cell = list.head.take();
+                while let Some(shared) = cell {
+                    match Rc::try_unwrap(shared) {
+                        Ok(node) => {
+                            pending.push(Garbage::Suspension(node.elem));
+                            cell = node.next;
+                        }
+                        Err(_) => break,
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn queue_garbage<T>(queue: Queue<T>, pending: &mut Vec<Garbage<T>>) {
+    let Queue {
+        mut front, rear, ..
+    } = queue;
+    pending.extend(front.0.take().map(Garbage::Stream));
+    pending.push(Garbage::Pieces(rear));
+}
+
+impl<T> Drop for CatList<T> {
+    fn drop(&mut self) {
+        if let Some(root) = self.root.take() {
+            dismantle(Garbage::Node(root));
+        }
+    }
+}
+
+impl<T> Drop for Stream<T> {
+    fn drop(&mut self) {
+        if let Some(cell) = self.0.take() {
+            dismantle(Garbage::Stream(cell));
+        }
+    }
+}
+
+impl<T> Drop for Suspension<T> {
+    /// A suspension dropped anywhere but in `dismantle` (at the end of a `third::List` of pieces,
+    /// say) hands its insides over to it, instead of dropping them recursively.
+    fn drop(&mut self) {
+        if let Some(list) = self.value.take() {
+            drop(list);
+        }
+        if let Some(pieces) = self.pending.take() {
+            let mut pending = Vec::new();
+            queue_garbage(pieces, &mut pending);
+            for garbage in pending {
+                dismantle(garbage);
+            }
+        }
+    }
+}
+
+impl<'a, T: Clone> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.pending.pop()?;
+        // The oldest piece has to come off the stack first, so it goes on last.
+        let pieces = node.children.pieces();
+        self.pending.extend(
+            pieces
+                .into_iter()
+                .rev()
+                .filter_map(|piece| piece.force().root.as_deref()),
+        );
+        Some(&node.elem)
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a CatList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CatList;
+    use crate::test_util::XorShift;
+    use crate::third;
+
+    fn collect(list: &CatList<u32>) -> Vec<u32> {
+        list.iter().copied().collect()
+    }
+
+    /// Drains the list one uncons at a time.
+    fn drain(list: &CatList<u32>) -> Vec<u32> {
+        let mut out = Vec::new();
+        let mut cur = list.clone();
+        while let Some((elem, rest)) = cur.uncons() {
+            out.push(*elem);
+            cur = rest;
+        }
+        out
+    }
+
+    #[test]
+    fn basics() {
+        let empty = CatList::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.head(), None);
+        assert!(empty.uncons().is_none());
+        assert!(empty.tail().is_empty());
+
+        let list = empty.snoc(2).snoc(3).cons(1);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.head(), Some(&1));
+        assert_eq!(collect(&list), [1, 2, 3]);
+        assert_eq!(collect(&list.tail()), [2, 3]);
+
+        let other: CatList<u32> = vec![4, 5].into_iter().collect();
+        let both = list.append(&other);
+        assert_eq!(collect(&both), [1, 2, 3, 4, 5]);
+        assert_eq!(drain(&both), [1, 2, 3, 4, 5]);
+        assert_eq!(collect(&other.append(&list)), [4, 5, 1, 2, 3]);
+
+        assert!(list.append(&empty).ptr_eq(&list));
+        assert!(empty.append(&list).ptr_eq(&list));
+    }
+
+    #[test]
+    fn persistence() {
+        let a: CatList<u32> = (0..3).collect();
+        let b: CatList<u32> = (3..6).collect();
+        let ab = a.append(&b);
+        let ab_tail = ab.tail();
+        let ba = b.append(&a).snoc(6);
+
+        assert_eq!(collect(&a), [0, 1, 2]);
+        assert_eq!(collect(&b), [3, 4, 5]);
+        assert_eq!(collect(&ab), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(collect(&ab_tail), [1, 2, 3, 4, 5]);
+        assert_eq!(collect(&ba), [3, 4, 5, 0, 1, 2, 6]);
+        assert_eq!(drain(&ab), drain(&ab));
+    }
+
+    #[test]
+    fn third_list_round_trip() {
+        let list = third::List::new().append(3).append(2).append(1);
+        let cat = CatList::from_list(&list);
+        assert_eq!(collect(&cat), [1, 2, 3]);
+
+        let back = cat.append(&cat).to_list();
+        assert_eq!(back.iter().copied().collect::<Vec<_>>(), [1, 2, 3, 1, 2, 3]);
+        assert!(CatList::<u32>::new().to_list().head().is_none());
+    }
+
+    const DEEP: u32 = 200_000;
+
+    #[test]
+    fn left_nested() {
+        // (((0 ++ 1) ++ 2) ++ ...): one root with DEEP - 1 children.
+        let mut list = CatList::new();
+        for i in 0..DEEP {
+            list = list.append(&CatList::new().cons(i));
+        }
+        assert_eq!(list.len(), DEEP as usize);
+        assert!(list.iter().copied().eq(0..DEEP));
+        assert!(drain(&list).into_iter().eq(0..DEEP));
+    }
+
+    #[test]
+    fn right_nested() {
+        // (0 ++ (1 ++ (2 ++ ...))): a chain DEEP long, built from the back.
+        let mut list = CatList::new();
+        for i in (0..DEEP).rev() {
+            list = CatList::new().cons(i).append(&list);
+        }
+        assert!(list.iter().copied().eq(0..DEEP));
+        assert!(list.to_list().iter().copied().eq(0..DEEP));
+        assert!(drain(&list).into_iter().eq(0..DEEP));
+    }
+
+    #[test]
+    fn right_nested_with_a_tail() {
+        // With one more piece at the back, every tail leaves the previous one's suspension
+        // unforced as the oldest piece of the next, so they pile up a chain DEEP long.
+        let mut list = CatList::new();
+        for i in (0..DEEP).rev() {
+            list = CatList::new().cons(i).append(&list);
+        }
+        let mut list = list.snoc(DEEP);
+        for _ in 0..DEEP - 1 {
+            list = list.tail();
+        }
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.iter().count(), 2);
+        assert_eq!(collect(&list), [DEEP - 1, DEEP]);
+        assert_eq!(collect(&list.tail()), [DEEP]);
+
+        // The same, iterating instead of draining.
+        let mut list = CatList::new();
+        for i in (0..DEEP).rev() {
+            list = CatList::new().cons(i).append(&list);
+        }
+        let list = list.snoc(DEEP);
+        let mut rest = list.clone();
+        for _ in 0..DEEP - 1 {
+            rest = rest.tail();
+        }
+        assert!(list.iter().copied().eq(0..=DEEP));
+        assert!(drain(&rest).into_iter().eq(DEEP - 1..=DEEP));
+    }
+
+    #[test]
+    fn uncons_an_old_version_again_and_again() {
+        // A root with DEEP - 1 children. Each of these is O(1) amortized; linking the children
+        // eagerly would make the loops quadratic.
+        let wide: CatList<u32> = (0..DEEP).collect();
+        for _ in 0..DEEP {
+            let (head, rest) = wide.uncons().unwrap();
+            assert_eq!(*head, 0);
+            assert_eq!(rest.len(), DEEP as usize - 1);
+        }
+        // The same for versions that share the wide root's children but have one more.
+        for i in 0..DEEP {
+            let (_, rest) = wide.snoc(DEEP + i).uncons().unwrap();
+            assert_eq!(rest.head(), Some(&1));
+        }
+
+        assert!(drain(&wide.snoc(DEEP)).into_iter().eq(0..=DEEP));
+        assert!(wide.tail().iter().copied().eq(1..DEEP));
+        assert!(drain(&wide).into_iter().eq(0..DEEP));
+    }
+
+    #[test]
+    fn nested_both_ways() {
+        // Left-nested lists of right-nested lists, so the tree is deep in every direction.
+        let mut list = CatList::new();
+        let mut expected = Vec::new();
+        for chunk in 0..400 {
+            let mut piece = CatList::new();
+            for i in (0..500).rev() {
+                piece = piece.cons(chunk * 500 + i);
+            }
+            expected.extend(chunk * 500..(chunk + 1) * 500);
+            list = list.append(&piece);
+        }
+        assert_eq!(collect(&list), expected);
+        assert_eq!(list.tail().len(), expected.len() - 1);
+    }
+
+    /// Random operations on random old versions, each checked against a `Vec` of what it should
+    /// hold.
+    #[test]
+    fn same_as_vec() {
+        let mut versions: Vec<(CatList<u32>, Vec<u32>)> = vec![(CatList::new(), Vec::new())];
+        let mut rng = XorShift::new();
+
+        for step in 0..3_000 {
+            let bits = rng.next_u64();
+            let (list, model) = &versions[(bits >> 8) as usize % versions.len()];
+            let mut model = model.clone();
+            let list = match bits % 5 {
+                0 => {
+                    model.insert(0, step);
+                    list.cons(step)
+                }
+                1 => {
+                    model.push(step);
+                    list.snoc(step)
+                }
+                2 => {
+                    if !model.is_empty() {
+                        model.remove(0);
+                    }
+                    list.tail()
+                }
+                _ => {
+                    let (other, other_model) = &versions[(bits >> 24) as usize % versions.len()];
+                    model.extend(other_model);
+                    list.append(other)
+                }
+            };
+            assert_eq!(list.head(), model.first());
+            versions.push((list, model));
+        }
+
+        for (list, model) in &versions {
+            assert_eq!(list.len(), model.len());
+            assert_eq!(&collect(list), model);
+        }
+    }
+}
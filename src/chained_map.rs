@@ -0,0 +1,594 @@
+//! A hash map with separate chaining, where every bucket is a `second::List`.
+//!
+//! The key's hash picks a bucket, and the bucket is a plain stack of `(key, value)` pairs that
+//! happen to land there. Lookups walk one chain:
+//!
+//! ```ignore
+//! buckets[0] -> ("b", 2) -> nil
+//! buckets[1] -> nil
+//! buckets[2] -> ("d", 4) -> ("a", 1) -> nil      "a" and "d" collided
+//! buckets[3] -> ("c", 3) -> nil
+//! ```
+//!
+//! As long as there are at least as many buckets as entries and the hasher spreads keys out,
+//! chains stay short and everything is O(1) on average. When the map gets fuller than that, the
+//! bucket count doubles. The entries don't move in memory when that happens: each node is popped
+//! off its old chain and pushed onto its new one, box and all. A bad hasher shows up as long
+//! chains, which `chain_stats` reports.
+
+use crate::second::List;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::{fmt, slice};
+
+/// Grow when there'd be more entries than buckets.
+const MAX_LOAD: usize = 1;
+
+const MIN_BUCKETS: usize = 8;
+
+pub struct HashMap<K, V, S = RandomState> {
+    /// Always empty or a power of two, so a hash can be masked down to an index.
+    buckets: Vec<List<(K, V)>>,
+    len: usize,
+    hasher: S,
+}
+
+pub enum Entry<'a, K, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// An entry whose key is in the map. It holds on to the key it was looked up with and to the
+/// key's chain, and walks the chain again to get at the value.
+pub struct OccupiedEntry<'a, K, V> {
+    key: K,
+    bucket: &'a mut List<(K, V)>,
+    len: &'a mut usize,
+}
+
+pub struct VacantEntry<'a, K, V> {
+    key: K,
+    bucket: &'a mut List<(K, V)>,
+    len: &'a mut usize,
+}
+
+/// How long the chains are, to tell a bad hasher from bad luck.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainStats {
+    pub buckets: usize,
+    pub entries: usize,
+    /// `lengths[n]` is how many buckets hold exactly `n` entries.
+    pub lengths: Vec<usize>,
+}
+
+pub struct Iter<'a, K, V> {
+    buckets: slice::Iter<'a, List<(K, V)>>,
+    chain: Option<crate::second::Iter<'a, (K, V)>>,
+    remaining: usize,
+}
+
+pub struct IterMut<'a, K, V> {
+    buckets: slice::IterMut<'a, List<(K, V)>>,
+    chain: Option<crate::second::IterMut<'a, (K, V)>>,
+    remaining: usize,
+}
+
+impl<K: Hash + Eq, V> HashMap<K, V, RandomState> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> HashMap<K, V, S> {
+    /// with_hasher() doesn't allocate; the first insert does.
+    pub fn with_hasher(hasher: S) -> Self {
+        HashMap {
+            buckets: Vec::new(),
+            len: 0,
+            hasher,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How many entries fit before the next resize.
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * MAX_LOAD
+    }
+
+    fn bucket_index<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        self.hasher.hash_one(key) as usize & (self.buckets.len() - 1)
+    }
+
+    fn bucket<Q>(&self, key: &Q) -> Option<&List<(K, V)>>
+    where
+        Q: Hash + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        Some(&self.buckets[self.bucket_index(key)])
+    }
+
+    /// Makes room for one more entry, doubling the bucket count if needed.
+    fn reserve_one(&mut self) {
+        if self.len < self.capacity() {
+            return;
+        }
+        let new_len = (self.buckets.len() * 2).max(MIN_BUCKETS);
+        let old = std::mem::replace(&mut self.buckets, Vec::with_capacity(new_len));
+        self.buckets.resize_with(new_len, List::new);
+        for mut chain in old {
+            // Relink, don't reallocate: the box that held the entry moves to its new chain.
+            while let Some(node) = chain.pop_node() {
+                let index = self.bucket_index(&node.elem().0);
+                self.buckets[index].push_node(node);
+            }
+        }
+    }
+
+    /// insert() returns the old value if `key` was already there. The old key is kept, like std
+    /// does.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.bucket(key)?
+            .iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let index = self.bucket_index(key);
+        self.buckets[index]
+            .iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let index = self.bucket_index(key);
+        let (_, value) = self.buckets[index].remove_first(|(k, _)| k.borrow() == key)?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// entry() makes room for the key up front, like std's does, so that inserting into a
+    /// `VacantEntry` never has to resize the table it's borrowing from.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        self.reserve_one();
+        let index = self.bucket_index(&key);
+        let bucket = &mut self.buckets[index];
+        let len = &mut self.len;
+        if bucket.iter().any(|(k, _)| *k == key) {
+            Entry::Occupied(OccupiedEntry { key, bucket, len })
+        } else {
+            Entry::Vacant(VacantEntry { key, bucket, len })
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            buckets: self.buckets.iter(),
+            chain: None,
+            remaining: self.len,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            buckets: self.buckets.iter_mut(),
+            chain: None,
+            remaining: self.len,
+        }
+    }
+
+    pub fn chain_stats(&self) -> ChainStats {
+        let mut lengths = Vec::new();
+        for chain in &self.buckets {
+            let n = chain.iter().count();
+            if lengths.len() <= n {
+                lengths.resize(n + 1, 0);
+            }
+            lengths[n] += 1;
+        }
+        ChainStats {
+            buckets: self.buckets.len(),
+            entries: self.len,
+            lengths,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> Default for HashMap<K, V, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
+where
+    K: Hash + Eq + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => &entry.key,
+            Entry::Vacant(entry) => &entry.key,
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V
+    where
+        K: Eq,
+    {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V
+    where
+        K: Eq,
+    {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        K: Eq,
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self
+    where
+        K: Eq,
+    {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Eq, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        let key = &self.key;
+        let (_, value) = self.bucket.iter().find(|(k, _)| k == key).unwrap();
+        value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        let key = &self.key;
+        let (_, value) = self.bucket.iter_mut().find(|(k, _)| k == key).unwrap();
+        value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        let key = self.key;
+        let (_, value) = self.bucket.iter_mut().find(|(k, _)| *k == key).unwrap();
+        value
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        let key = &self.key;
+        let (_, value) = self.bucket.remove_first(|(k, _)| k == key).unwrap();
+        *self.len -= 1;
+        value
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.bucket.push((self.key, value));
+        *self.len += 1;
+        &mut self.bucket.peek_mut().unwrap().1
+    }
+}
+
+impl ChainStats {
+    pub fn longest(&self) -> usize {
+        self.lengths.len().saturating_sub(1)
+    }
+
+    pub fn empty(&self) -> usize {
+        self.lengths.first().copied().unwrap_or(0)
+    }
+
+    /// The average chain a successful lookup walks, counting the entry itself. About 1.5 at a
+    /// load of 1 for a good hasher, and (n + 1) / 2 when everything lands in one bucket.
+    pub fn mean_probe(&self) -> f64 {
+        if self.entries == 0 {
+            return 0.0;
+        }
+        // A chain of n costs 1 + 2 + ... + n over its n entries.
+        let total: usize = self
+            .lengths
+            .iter()
+            .enumerate()
+            .map(|(n, &count)| count * n * (n + 1) / 2)
+            .sum();
+        total as f64 / self.entries as f64
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, v)) = self.chain.as_mut().and_then(Iterator::next) {
+                self.remaining -= 1;
+                return Some((k, v));
+            }
+            self.chain = Some(self.buckets.next()?.iter());
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, v)) = self.chain.as_mut().and_then(Iterator::next) {
+                self.remaining -= 1;
+                return Some((&*k, v));
+            }
+            self.chain = Some(self.buckets.next()?.iter_mut());
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> IntoIterator for &'a HashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> IntoIterator for &'a mut HashMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Entry, HashMap};
+    use crate::test_util::XorShift;
+    use std::collections::HashMap as StdMap;
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    /// Sends every key to the same bucket.
+    #[derive(Default)]
+    struct Constant;
+
+    impl Hasher for Constant {
+        fn finish(&self) -> u64 {
+            42
+        }
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    #[test]
+    fn basics() {
+        let mut map = HashMap::new();
+        assert_eq!(map.get("a"), None);
+        assert_eq!(map.remove("a"), None);
+        assert_eq!(map.capacity(), 0);
+
+        assert_eq!(map.insert("a".to_string(), 1), None);
+        assert_eq!(map.insert("b".to_string(), 2), None);
+        assert_eq!(map.insert("a".to_string(), 10), Some(1));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("a"), Some(&10));
+        *map.get_mut("b").unwrap() += 1;
+        assert_eq!(map.get("b"), Some(&3));
+        assert!(map.contains_key("b"));
+
+        assert_eq!(map.remove("a"), Some(10));
+        assert_eq!(map.remove("a"), None);
+        assert_eq!(map.len(), 1);
+        assert_eq!(format!("{:?}", map), r#"{"b": 3}"#);
+    }
+
+    #[test]
+    fn entry() {
+        let mut counts = HashMap::new();
+        for word in "the cat and the hat and the bat".split(' ') {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(counts.get("the"), Some(&3));
+        assert_eq!(counts.get("and"), Some(&2));
+        assert_eq!(counts.len(), 5);
+
+        counts.entry("cat").and_modify(|n| *n *= 10).or_default();
+        counts.entry("dog").and_modify(|n| *n *= 10).or_default();
+        assert_eq!(counts.get("cat"), Some(&10));
+        assert_eq!(counts.get("dog"), Some(&0));
+
+        match counts.entry("hat") {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.key(), &"hat");
+                assert_eq!(entry.insert(7), 1);
+                assert_eq!(entry.remove(), 7);
+            }
+            Entry::Vacant(_) => panic!("hat should be there"),
+        }
+        match counts.entry("hat") {
+            Entry::Occupied(_) => panic!("hat should be gone"),
+            Entry::Vacant(entry) => assert_eq!(entry.into_key(), "hat"),
+        }
+        assert_eq!(counts.len(), 5);
+    }
+
+    #[test]
+    fn growing_relinks_nodes() {
+        let mut map = HashMap::new();
+        map.insert(0, 0);
+        let before = map.get(&0).unwrap() as *const i32;
+        let buckets = map.chain_stats().buckets;
+
+        for i in 1..1_000 {
+            map.insert(i, i);
+        }
+        assert!(map.chain_stats().buckets > buckets);
+        assert!(map.len() <= map.capacity());
+        // Same box, new chain.
+        assert_eq!(map.get(&0).unwrap() as *const i32, before);
+        for i in 0..1_000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn iter() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        for (_, v) in map.iter_mut() {
+            *v *= 2;
+        }
+        let iter = map.iter();
+        assert_eq!(iter.len(), 100);
+        let mut pairs: Vec<_> = iter.map(|(&k, &v)| (k, v)).collect();
+        pairs.sort();
+        assert_eq!(pairs, (0..100).map(|i| (i, i * 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn chain_stats() {
+        let mut good = HashMap::new();
+        let mut bad: HashMap<u32, (), BuildHasherDefault<Constant>> = HashMap::default();
+        for i in 0..1_000 {
+            good.insert(i, ());
+            bad.insert(i, ());
+        }
+
+        let stats = bad.chain_stats();
+        assert_eq!(stats.entries, 1_000);
+        assert_eq!(stats.longest(), 1_000);
+        assert_eq!(stats.empty(), stats.buckets - 1);
+        assert_eq!(stats.mean_probe(), 500.5);
+
+        let stats = good.chain_stats();
+        assert_eq!(stats.lengths.iter().sum::<usize>(), stats.buckets);
+        assert!(stats.longest() < 12, "{:?}", stats);
+        assert!(stats.mean_probe() < 2.0, "{:?}", stats);
+
+        // Even with one chain, everything still works, just slowly.
+        assert_eq!(bad.remove(&500), Some(()));
+        assert_eq!(bad.get(&500), None);
+        assert_eq!(bad.get(&501), Some(&()));
+    }
+
+    #[test]
+    fn same_as_std() {
+        let mut ours = HashMap::new();
+        let mut model = StdMap::new();
+        let mut rng = XorShift::new();
+
+        for i in 0..20_000u64 {
+            let bits = rng.next_u64();
+            let key = (bits >> 32) % 2_000;
+            match bits % 5 {
+                0 | 1 => assert_eq!(ours.insert(key, i), model.insert(key, i)),
+                2 => assert_eq!(ours.remove(&key), model.remove(&key)),
+                3 => assert_eq!(ours.get(&key), model.get(&key)),
+                _ => {
+                    *ours.entry(key).or_insert(0) += 1;
+                    *model.entry(key).or_insert(0) += 1;
+                }
+            }
+            assert_eq!(ours.len(), model.len());
+        }
+        let ours: StdMap<_, _> = ours.iter().map(|(&k, &v)| (k, v)).collect();
+        assert_eq!(ours, model);
+    }
+}
@@ -0,0 +1,458 @@
+//! Blocking channels whose buffer is a linked queue.
+//!
+//! Same shape as `std::sync::mpsc`: any number of `Sender`s, one `Receiver`, and either an
+//! unbounded buffer or one that holds at most `cap` messages. The buffer is a
+//! `concurrent::MpscQueue`, so a message costs one node and nothing ever has to be copied into a
+//! bigger ring buffer.
+//!
+//! Blocking is plain `Mutex` + `Condvar`. The mutex guards the bookkeeping (how many messages are
+//! buffered, who is still connected), and every push happens while holding it, so whenever the
+//! receiver sees `len > 0` the node is already fully linked and `pop` can't come back
+//! `Inconsistent`.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::concurrent::{MpscQueue, PopResult};
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    queue: MpscQueue<T>,
+    /// `None` for unbounded channels.
+    cap: Option<usize>,
+    state: Mutex<State>,
+    /// Signalled when a message is pushed or the last sender leaves.
+    not_empty: Condvar,
+    /// Signalled when a message is popped or the receiver leaves.
+    not_full: Condvar,
+}
+
+struct State {
+    len: usize,
+    senders: usize,
+    receiver: bool,
+}
+
+/// The receiver is gone; here's your message back.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+/// Every sender is gone and the buffer is empty.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
+}
+
+/// A channel that holds at most `cap` messages; `send` blocks while it's full.
+///
+/// # Panics
+///
+/// If `cap` is 0. (There's no rendezvous mode.)
+pub fn bounded<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "channel capacity must be at least 1");
+    channel(Some(cap))
+}
+
+/// A channel whose `send` never blocks.
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    channel(None)
+}
+
+fn channel<T>(cap: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: MpscQueue::new(),
+        cap,
+        state: Mutex::new(State {
+            len: 0,
+            senders: 1,
+            receiver: true,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // Nothing panics while holding the lock, but don't make a poisoned channel worse.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_full(&self, state: &State) -> bool {
+        self.cap.is_some_and(|cap| state.len >= cap)
+    }
+
+    /// Called with the lock held and room in the buffer.
+    fn push(&self, mut state: MutexGuard<'_, State>, elem: T) {
+        state.len += 1;
+        self.queue.push(elem);
+        drop(state);
+        self.not_empty.notify_one();
+    }
+
+    /// Called with the lock held and `len > 0`.
+    fn pop(&self, mut state: MutexGuard<'_, State>) -> T {
+        state.len -= 1;
+        let elem = match self.queue.pop() {
+            PopResult::Data(elem) => elem,
+            // Every push finished under the lock we're holding.
+            PopResult::Empty | PopResult::Inconsistent => unreachable!(),
+        };
+        drop(state);
+        self.not_full.notify_one();
+        elem
+    }
+}
+
+impl<T> Sender<T> {
+    /// send() blocks while a bounded channel is full. It fails, handing the message back, once
+    /// the receiver has been dropped, even if it was waiting for room at the time.
+    pub fn send(&self, elem: T) -> Result<(), SendError<T>> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        loop {
+            if !state.receiver {
+                return Err(SendError(elem));
+            }
+            if !shared.is_full(&state) {
+                shared.push(state, elem);
+                return Ok(());
+            }
+            state = shared
+                .not_full
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    pub fn try_send(&self, elem: T) -> Result<(), TrySendError<T>> {
+        let shared = &*self.shared;
+        let state = shared.lock();
+        if !state.receiver {
+            Err(TrySendError::Disconnected(elem))
+        } else if shared.is_full(&state) {
+            Err(TrySendError::Full(elem))
+        } else {
+            shared.push(state, elem);
+            Ok(())
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// recv() blocks until there's a message. Messages that were sent before the last sender left
+    /// are still delivered; only then does it fail.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        loop {
+            if state.len > 0 {
+                return Ok(shared.pop(state));
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = shared
+                .not_empty
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let shared = &*self.shared;
+        let deadline = Instant::now() + timeout;
+        let mut state = shared.lock();
+        loop {
+            if state.len > 0 {
+                return Ok(shared.pop(state));
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = shared
+                .not_empty
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let shared = &*self.shared;
+        let state = shared.lock();
+        if state.len > 0 {
+            Ok(shared.pop(state))
+        } else if state.senders == 0 {
+            Err(TryRecvError::Disconnected)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    /// Wakes every sender blocked on a full channel so they can fail. Whatever is still buffered
+    /// is dropped with the queue, once the last sender is gone too.
+    fn drop(&mut self) {
+        self.shared.lock().receiver = false;
+        self.shared.not_full.notify_all();
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a closed channel")
+    }
+}
+
+impl<T: fmt::Debug> Error for SendError<T> {}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending on a full channel"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a closed channel"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> Error for TrySendError<T> {}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("receiving on a closed channel")
+    }
+}
+
+impl Error for RecvError {}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Disconnected => f.write_str("receiving on a closed channel"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => f.write_str("timed out waiting on channel"),
+            RecvTimeoutError::Disconnected => f.write_str("receiving on a closed channel"),
+        }
+    }
+}
+
+impl Error for RecvTimeoutError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let (tx, rx) = unbounded();
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        tx.send(3).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Ok(3));
+    }
+
+    #[test]
+    fn capacity() {
+        let (tx, rx) = bounded(2);
+        assert_eq!(tx.try_send(1), Ok(()));
+        assert_eq!(tx.try_send(2), Ok(()));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(tx.try_send(3), Ok(()));
+        assert_eq!(tx.try_send(4), Err(TrySendError::Full(4)));
+        assert_eq!(rx.recv(), Ok(2));
+        assert_eq!(rx.recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn send_blocks_while_full() {
+        let (tx, rx) = bounded(1);
+        tx.send(0).unwrap();
+        let handle = thread::spawn(move || {
+            for i in 1..100 {
+                tx.send(i).unwrap();
+            }
+        });
+        for i in 0..100 {
+            assert_eq!(rx.recv(), Ok(i));
+            // Never more than one message in flight.
+            assert!(rx.shared.lock().len <= 1);
+        }
+        handle.join().unwrap();
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1")]
+    fn zero_capacity() {
+        let _ = bounded::<u8>(0);
+    }
+
+    #[test]
+    fn receiver_gone() {
+        let (tx, rx) = bounded(1);
+        drop(rx);
+        assert_eq!(tx.send(1), Err(SendError(1)));
+        assert_eq!(tx.try_send(2), Err(TrySendError::Disconnected(2)));
+    }
+
+    #[test]
+    fn receiver_gone_while_sender_blocked() {
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+        let handle = thread::spawn(move || tx.send(2));
+        thread::sleep(Duration::from_millis(20));
+        drop(rx);
+        assert_eq!(handle.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn senders_gone_with_messages_buffered() {
+        let (tx, rx) = unbounded();
+        let tx2 = tx.clone();
+        tx.send(1).unwrap();
+        tx2.send(2).unwrap();
+        drop(tx);
+        assert_eq!(rx.recv(), Ok(1));
+        drop(tx2);
+        // Still delivered after every sender left.
+        assert_eq!(rx.recv(), Ok(2));
+        assert_eq!(rx.recv(), Err(RecvError));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn senders_gone_while_receiver_blocked() {
+        let (tx, rx) = unbounded::<u8>();
+        let handle = thread::spawn(move || rx.recv());
+        thread::sleep(Duration::from_millis(20));
+        drop(tx);
+        assert_eq!(handle.join().unwrap(), Err(RecvError));
+
+        let (tx, rx) = unbounded::<u8>();
+        let handle = thread::spawn(move || rx.recv_timeout(Duration::from_secs(60)));
+        thread::sleep(Duration::from_millis(20));
+        drop(tx);
+        assert_eq!(handle.join().unwrap(), Err(RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn buffered_messages_are_dropped() {
+        let (tx, rx) = bounded(4);
+        let msg = Arc::new(());
+        tx.send(msg.clone()).unwrap();
+        tx.send(msg.clone()).unwrap();
+        drop(rx);
+        assert_eq!(Arc::strong_count(&msg), 3);
+        drop(tx);
+        assert_eq!(Arc::strong_count(&msg), 1);
+    }
+
+    #[test]
+    fn many_senders() {
+        const SENDERS: usize = 8;
+        const PER_SENDER: usize = 10_000;
+
+        let (tx, rx) = bounded(16);
+        let handles: Vec<_> = (0..SENDERS)
+            .map(|s| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for seq in 0..PER_SENDER {
+                        tx.send((s, seq)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let mut next = [0; SENDERS];
+        while let Ok((s, seq)) = rx.recv() {
+            assert_eq!(seq, next[s]);
+            next[s] += 1;
+        }
+        assert!(next.iter().all(|&n| n == PER_SENDER));
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Hazard pointers.
+//!
+//! The problem every lock-free linked structure runs into: thread A loads the head, thread B pops
+//! that node and frees it, and A dereferences freed memory. Worse, the allocator may hand the
+//! same address right back to a new node, and A's compare-and-swap happily succeeds on a node it
+//! never saw (the ABA problem).
+//!
+//! The fix here is Michael's hazard pointers. Before a thread dereferences a shared node it
+//! publishes the address in a *hazard record*, then checks the node is still reachable. Nodes
+//! that have been unlinked aren't freed but *retired*; every so often the retired ones are
+//! compared against all published hazards, and only the ones nobody is looking at get freed. A
+//! node someone still protects isn't freed, so its address can't come back either.
+//!
+//! Each structure owns its `Domain` (its records and its retired nodes) instead of sharing one
+//! global domain. That keeps retired nodes from outliving the structure, so nothing needs to be
+//! `'static`, and dropping the structure can simply free whatever is left.
+
+use std::collections::HashSet;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+pub(crate) struct Domain {
+    /// Push-only list of records. Records are reused but never freed until the domain is.
+    records: AtomicPtr<Record>,
+    nrecords: AtomicUsize,
+    /// Retired nodes waiting for a scan.
+    retired: AtomicPtr<Retired>,
+    nretired: AtomicUsize,
+}
+
+struct Record {
+    ptr: AtomicPtr<u8>,
+    active: AtomicBool,
+    /// Set before the record is published and never changed afterwards.
+    next: *mut Record,
+}
+
+struct Retired {
+    ptr: *mut u8,
+    /// `Box::from_raw` for the right type.
+    free: unsafe fn(*mut u8),
+    next: *mut Retired,
+}
+
+/// One published hazard slot. It's given back to the domain when dropped.
+pub(crate) struct Hazard<'a> {
+    record: &'a Record,
+}
+
+impl Domain {
+    pub(crate) fn new() -> Self {
+        Domain {
+            records: AtomicPtr::new(ptr::null_mut()),
+            nrecords: AtomicUsize::new(0),
+            retired: AtomicPtr::new(ptr::null_mut()),
+            nretired: AtomicUsize::new(0),
+        }
+    }
+
+    /// hazard() grabs an unused record, or adds a new one if all of them are taken.
+    pub(crate) fn hazard(&self) -> Hazard<'_> {
+        let mut cur = self.records.load(Ordering::Acquire);
+        while !cur.is_null() {
+            let record = unsafe { &*cur };
+            if !record.active.load(Ordering::Relaxed)
+                && record
+                    .active
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return Hazard { record };
+            }
+            cur = record.next;
+        }
+
+        let record = Box::into_raw(Box::new(Record {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+            active: AtomicBool::new(true),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.records.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*record).next = head };
+            match self.records.compare_exchange_weak(
+                head,
+                record,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => head = actual,
+            }
+        }
+        self.nrecords.fetch_add(1, Ordering::Relaxed);
+        Hazard {
+            record: unsafe { &*record },
+        }
+    }
+
+    /// retire() hands over a node that has been unlinked; it's freed with `Box::from_raw` once no
+    /// hazard points at it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Box::into_raw`, must no longer be reachable from the structure, and
+    /// must not be retired twice.
+    pub(crate) unsafe fn retire<T>(&self, ptr: *mut T) {
+        unsafe fn free<T>(ptr: *mut u8) {
+            drop(Box::from_raw(ptr as *mut T));
+        }
+
+        let retired = Box::into_raw(Box::new(Retired {
+            ptr: ptr as *mut u8,
+            free: free::<T>,
+            next: ptr::null_mut(),
+        }));
+        // Count it before publishing it: a concurrent scan may free it right away and subtract.
+        let nretired = self.nretired.fetch_add(1, Ordering::Relaxed) + 1;
+        self.push_retired(retired);
+
+        // Scanning costs O(records), so wait until that's paid for by a proportional batch.
+        if nretired >= (2 * self.nrecords.load(Ordering::Relaxed)).max(64) {
+            self.scan();
+        }
+    }
+
+    fn push_retired(&self, retired: *mut Retired) {
+        let mut head = self.retired.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*retired).next = head };
+            match self.retired.compare_exchange_weak(
+                head,
+                retired,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// Frees every retired node that isn't protected right now.
+    fn scan(&self) {
+        // Take the whole batch, so concurrent scans never see the same node.
+        let mut cur = self.retired.swap(ptr::null_mut(), Ordering::Acquire);
+        if cur.is_null() {
+            return;
+        }
+
+        // Pairs with the `SeqCst` store/load in `protect`: a reader either published its hazard
+        // before we look at the records, or it will see that the node is gone and retry.
+        fence(Ordering::SeqCst);
+        let mut protected = HashSet::new();
+        let mut record = self.records.load(Ordering::Acquire);
+        while !record.is_null() {
+            let ptr = unsafe { (*record).ptr.load(Ordering::Relaxed) };
+            if !ptr.is_null() {
+                protected.insert(ptr);
+            }
+            record = unsafe { (*record).next };
+        }
+
+        let mut freed = 0;
+        while !cur.is_null() {
+            let retired = unsafe { Box::from_raw(cur) };
+            cur = retired.next;
+            if protected.contains(&retired.ptr) {
+                self.push_retired(Box::into_raw(retired));
+            } else {
+                unsafe { (retired.free)(retired.ptr) };
+                freed += 1;
+            }
+        }
+        self.nretired.fetch_sub(freed, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Domain {
+    /// Nobody else can hold a hazard anymore, so everything that's retired goes.
+    fn drop(&mut self) {
+        let mut cur = *self.retired.get_mut();
+        while !cur.is_null() {
+            let retired = unsafe { Box::from_raw(cur) };
+            cur = retired.next;
+            unsafe { (retired.free)(retired.ptr) };
+        }
+
+        let mut cur = *self.records.get_mut();
+        while !cur.is_null() {
+            let record = unsafe { Box::from_raw(cur) };
+            cur = record.next;
+        }
+    }
+}
+
+impl<'a> Hazard<'a> {
+    /// protect() loads `src` and publishes it as hazardous, retrying until the published value is
+    /// still the current one. The returned node (if not null) won't be freed until this hazard is
+    /// cleared or moved on.
+    pub(crate) fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        let mut ptr = src.load(Ordering::Relaxed);
+        loop {
+            self.record.ptr.store(ptr as *mut u8, Ordering::SeqCst);
+            let again = src.load(Ordering::SeqCst);
+            if again == ptr {
+                return ptr;
+            }
+            ptr = again;
+        }
+    }
+
+    /// set() publishes `ptr` without validating it. The caller has to check afterwards that `ptr`
+    /// is still reachable before trusting it.
+    pub(crate) fn set<T>(&self, ptr: *mut T) {
+        self.record.ptr.store(ptr as *mut u8, Ordering::SeqCst);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.record.ptr.store(ptr::null_mut(), Ordering::Release);
+    }
+}
+
+impl<'a> Drop for Hazard<'a> {
+    fn drop(&mut self) {
+        self.clear();
+        self.record.active.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Domain;
+    use crate::test_util::Drops;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+
+    #[test]
+    fn protected_nodes_survive_scans() {
+        let drops = Drops::new();
+        let domain = Domain::new();
+
+        let kept = Box::into_raw(Box::new(drops.counted()));
+        let slot = AtomicPtr::new(kept);
+        let hazard = domain.hazard();
+        assert_eq!(hazard.protect(&slot), kept);
+
+        unsafe { domain.retire(kept) };
+        for _ in 0..1_000 {
+            unsafe { domain.retire(Box::into_raw(Box::new(drops.counted()))) };
+        }
+        // Everything but the protected node has been through a scan by now.
+        assert!(drops.get() >= 1_000 - 64);
+        assert_eq!(domain.nretired.load(Ordering::SeqCst), 1_001 - drops.get());
+
+        drop(hazard);
+        drop(domain);
+        assert_eq!(drops.get(), 1_001);
+    }
+
+    #[test]
+    fn records_are_reused() {
+        let domain = Domain::new();
+        for _ in 0..10 {
+            let _a = domain.hazard();
+            let _b = domain.hazard();
+        }
+        assert_eq!(domain.nrecords.load(Ordering::SeqCst), 2);
+    }
+}
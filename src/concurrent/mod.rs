@@ -0,0 +1,15 @@
+//! Lock-free lists that can be shared between threads.
+//!
+//! Everything in here is built from raw pointers and atomics rather than `Box`/`Rc`, because the
+//! whole point is that several threads link and unlink nodes at once. Memory reclamation is the
+//! job of `hazard`, which every structure that frees nodes other threads may still be reading
+//! goes through.
+
+mod hazard;
+mod mpsc;
+mod ms_queue;
+mod treiber;
+
+pub use self::mpsc::{MpscQueue, PopResult};
+pub use self::ms_queue::MsQueue;
+pub use self::treiber::TreiberStack;
@@ -0,0 +1,327 @@
+use std::cell::UnsafeCell;
+use std::hint;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::thread;
+
+/// What `MpscQueue::pop` found.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PopResult<T> {
+    Data(T),
+    Empty,
+    /// A producer has claimed its spot in the queue but hasn't linked its node yet. The element
+    /// will show up in a moment; try again.
+    Inconsistent,
+}
+
+/// Dmitry Vyukov's intrusive multi-producer single-consumer queue.
+///
+/// The queue only ever sees the `Link` header at the start of each node, never the element, so
+/// the same code would work for nodes embedded in some other struct. Producers push at `head`
+/// with a single `swap` and a single store, no loops, which makes `push` wait-free:
+///
+/// ```ignore
+/// tail -> (stub) -> (a) -> (b) -> (c) <- head
+/// (the consumer pops here)             (producers push here)
+/// ```
+///
+/// The catch is the moment between the `swap` and the store: `head` already points at the new
+/// node but the old last node's `next` doesn't yet. A consumer that reaches the old last node in
+/// that window can't see past it and gets `Inconsistent` rather than waiting on the producer.
+///
+/// The stub node keeps the list from ever being really empty, so neither side has to special-case
+/// the last node. It's pushed back whenever the consumer is about to take the last real node.
+///
+/// There may only be one consumer. `pop` checks that and panics if it's called from two threads
+/// at once, which costs the consumer one uncontended atomic swap and the producers nothing.
+pub struct MpscQueue<T> {
+    /// The last node pushed. Only producers (and the consumer re-pushing the stub) swap it.
+    head: AtomicPtr<Link>,
+    /// The next node to pop. Only the consumer touches it.
+    tail: UnsafeCell<*mut Link>,
+    /// Boxed so its address doesn't change when the queue moves.
+    stub: Box<Link>,
+    popping: AtomicBool,
+    _marker: PhantomData<T>,
+}
+
+#[repr(C)]
+struct Link {
+    next: AtomicPtr<Link>,
+}
+
+/// `#[repr(C)]` puts `link` at offset 0, so a `*mut Link` to a node can be cast back.
+#[repr(C)]
+struct Node<T> {
+    link: Link,
+    elem: T,
+}
+
+unsafe impl<T: Send> Send for MpscQueue<T> {}
+unsafe impl<T: Send> Sync for MpscQueue<T> {}
+
+impl Link {
+    fn new() -> Self {
+        Link {
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl<T> MpscQueue<T> {
+    pub fn new() -> Self {
+        let stub = Box::new(Link::new());
+        let stub_ptr = &*stub as *const Link as *mut Link;
+        MpscQueue {
+            head: AtomicPtr::new(stub_ptr),
+            tail: UnsafeCell::new(stub_ptr),
+            stub,
+            popping: AtomicBool::new(false),
+            _marker: PhantomData,
+        }
+    }
+
+    fn stub(&self) -> *mut Link {
+        &*self.stub as *const Link as *mut Link
+    }
+
+    /// push() is wait-free: one allocation, one swap, one store.
+    pub fn push(&self, elem: T) {
+        let node = Box::into_raw(Box::new(Node {
+            link: Link::new(),
+            elem,
+        }));
+        self.push_link(node as *mut Link);
+    }
+
+    fn push_link(&self, link: *mut Link) {
+        unsafe { (*link).next.store(ptr::null_mut(), Ordering::Relaxed) };
+        let prev = self.head.swap(link, Ordering::AcqRel);
+        // The consumer may be stuck at `prev` right now, reporting `Inconsistent`.
+        unsafe { (*prev).next.store(link, Ordering::Release) };
+    }
+
+    /// pop() takes the oldest element. Each producer's elements come out in the order it pushed
+    /// them.
+    ///
+    /// # Panics
+    ///
+    /// If another thread is popping at the same time.
+    pub fn pop(&self) -> PopResult<T> {
+        if self.popping.swap(true, Ordering::Acquire) {
+            panic!("MpscQueue::pop called from two threads at once");
+        }
+        let result = unsafe { self.pop_consumer() };
+        self.popping.store(false, Ordering::Release);
+        result
+    }
+
+    /// # Safety
+    ///
+    /// Only the consumer may call this.
+    unsafe fn pop_consumer(&self) -> PopResult<T> {
+        let stub = self.stub();
+        let tail = &mut *self.tail.get();
+        let mut first = *tail;
+        let mut next = (*first).next.load(Ordering::Acquire);
+
+        // Step over the stub; it has no element.
+        if first == stub {
+            if next.is_null() {
+                return if self.head.load(Ordering::Acquire) == stub {
+                    PopResult::Empty
+                } else {
+                    PopResult::Inconsistent
+                };
+            }
+            *tail = next;
+            first = next;
+            next = (*next).next.load(Ordering::Acquire);
+        }
+
+        if !next.is_null() {
+            *tail = next;
+            return PopResult::Data(Self::take(first));
+        }
+
+        // `first` looks like the last node. If it isn't, a producer is halfway through a push.
+        if self.head.load(Ordering::Acquire) != first {
+            return PopResult::Inconsistent;
+        }
+
+        // Put the stub behind it so we can take `first` without leaving the queue empty.
+        self.push_link(stub);
+        next = (*first).next.load(Ordering::Acquire);
+        if !next.is_null() {
+            *tail = next;
+            return PopResult::Data(Self::take(first));
+        }
+        // A producer got in between our `head` check and pushing the stub.
+        PopResult::Inconsistent
+    }
+
+    unsafe fn take(link: *mut Link) -> T {
+        Box::from_raw(link as *mut Node<T>).elem
+    }
+
+    /// pop_blocking() waits until there is an element: it spins for a little while, which is
+    /// enough to ride out an `Inconsistent`, and then falls back to yielding the thread.
+    ///
+    /// There's no wakeup here, so on an empty queue this burns a core's worth of `yield_now`s
+    /// until someone pushes. That's fine for a consumer that has nothing else to do anyway.
+    pub fn pop_blocking(&self) -> T {
+        let mut spins = 0u32;
+        loop {
+            match self.pop() {
+                PopResult::Data(elem) => return elem,
+                PopResult::Empty | PopResult::Inconsistent => {
+                    if spins < 64 {
+                        spins += 1;
+                        hint::spin_loop();
+                    } else {
+                        thread::yield_now();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for MpscQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for MpscQueue<T> {
+    /// With `&mut self` every push has finished, so the list is fully linked.
+    fn drop(&mut self) {
+        let stub = self.stub();
+        let mut cur = *self.tail.get_mut();
+        while !cur.is_null() {
+            let next = unsafe { (*cur).next.load(Ordering::Relaxed) };
+            if cur != stub {
+                drop(unsafe { Box::from_raw(cur as *mut Node<T>) });
+            }
+            cur = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Link, MpscQueue, Node, PopResult};
+    use crate::test_util::Drops;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let queue = MpscQueue::new();
+        assert_eq!(queue.pop(), PopResult::Empty);
+
+        queue.push(1);
+        queue.push(2);
+        queue.push(3);
+        assert_eq!(queue.pop(), PopResult::Data(1));
+        assert_eq!(queue.pop(), PopResult::Data(2));
+
+        queue.push(4);
+        assert_eq!(queue.pop(), PopResult::Data(3));
+        assert_eq!(queue.pop(), PopResult::Data(4));
+        assert_eq!(queue.pop(), PopResult::Empty);
+
+        queue.push(5);
+        assert_eq!(queue.pop_blocking(), 5);
+        assert_eq!(queue.pop(), PopResult::Empty);
+    }
+
+    #[test]
+    fn inconsistent() {
+        let queue = MpscQueue::new();
+        queue.push(1);
+
+        // Do the first half of a push by hand: claim `head`, don't link.
+        let node = Box::into_raw(Box::new(Node {
+            link: Link::new(),
+            elem: 2,
+        })) as *mut Link;
+        let prev = queue.head.swap(node, Ordering::AcqRel);
+
+        assert_eq!(queue.pop(), PopResult::Inconsistent);
+        assert_eq!(queue.pop(), PopResult::Inconsistent);
+
+        unsafe { (*prev).next.store(node, Ordering::Release) };
+        assert_eq!(queue.pop(), PopResult::Data(1));
+        assert_eq!(queue.pop(), PopResult::Data(2));
+        assert_eq!(queue.pop(), PopResult::Empty);
+
+        // And once more with only the stub in the queue.
+        let node = Box::into_raw(Box::new(Node {
+            link: Link::new(),
+            elem: 3,
+        })) as *mut Link;
+        let prev = queue.head.swap(node, Ordering::AcqRel);
+        assert_eq!(queue.pop(), PopResult::Inconsistent);
+        unsafe { (*prev).next.store(node, Ordering::Release) };
+        assert_eq!(queue.pop(), PopResult::Data(3));
+        assert_eq!(queue.pop(), PopResult::Empty);
+    }
+
+    #[test]
+    fn drops_everything_once() {
+        let drops = Drops::new();
+        let queue = MpscQueue::new();
+        for _ in 0..100 {
+            queue.push(drops.counted());
+        }
+        for _ in 0..30 {
+            drop(queue.pop_blocking());
+        }
+        assert_eq!(drops.get(), 30);
+        drop(queue);
+        assert_eq!(drops.get(), 100);
+    }
+
+    #[test]
+    fn many_producers() {
+        const PRODUCERS: usize = 8;
+        const PER_PRODUCER: usize = 50_000;
+
+        let queue = Arc::new(MpscQueue::new());
+        let handles: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for seq in 0..PER_PRODUCER {
+                        queue.push((p, seq));
+                    }
+                })
+            })
+            .collect();
+
+        let mut next = [0; PRODUCERS];
+        for _ in 0..PRODUCERS * PER_PRODUCER {
+            let (p, seq) = queue.pop_blocking();
+            assert_eq!(seq, next[p], "producer {} out of order", p);
+            next[p] += 1;
+        }
+        assert_eq!(queue.pop(), PopResult::Empty);
+        assert!(next.iter().all(|&n| n == PER_PRODUCER));
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "two threads at once")]
+    fn second_consumer() {
+        let queue = MpscQueue::<u8>::new();
+        // Pretend another thread is in the middle of a pop.
+        queue.popping.store(true, Ordering::SeqCst);
+        queue.pop();
+    }
+}
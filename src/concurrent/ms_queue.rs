@@ -0,0 +1,304 @@
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use super::hazard::Domain;
+
+/// The Michael–Scott lock-free queue.
+///
+/// A singly linked list with a pointer to each end, except that the first node is always a *dummy*
+/// whose element has already been taken:
+///
+/// ```ignore
+/// head -> (dummy) -> (a) -> (b) -> (c) <- tail
+/// ```
+///
+/// With the dummy, enqueuers only ever touch `tail` and dequeuers only ever touch `head`, even
+/// when the queue is empty (then both point at the dummy). Dequeuing moves `head` one node on and
+/// takes the element out of the new first node, which becomes the new dummy.
+///
+/// Enqueuing is two steps, linking the node after the last one and then swinging `tail`, so
+/// `tail` may lag one node behind. Anyone who notices that helps it along before carrying on.
+///
+/// Nodes are freed through the same hazard pointers as `TreiberStack`.
+pub struct MsQueue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+    /// Incremented before an element is linked and decremented after it's unlinked, so it never
+    /// goes below the real length.
+    len: AtomicUsize,
+    domain: Domain,
+    _marker: PhantomData<T>,
+}
+
+struct Node<T> {
+    /// Uninitialized in the dummy, and moved out when a node becomes the dummy.
+    elem: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for MsQueue<T> {}
+unsafe impl<T: Send> Sync for MsQueue<T> {}
+
+impl<T> Node<T> {
+    fn new(elem: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            elem,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+impl<T> MsQueue<T> {
+    pub fn new() -> Self {
+        let dummy = Node::new(MaybeUninit::uninit());
+        MsQueue {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            len: AtomicUsize::new(0),
+            domain: Domain::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn enqueue(&self, elem: T) {
+        let node = Node::new(MaybeUninit::new(elem));
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        let hazard = self.domain.hazard();
+        loop {
+            let tail = hazard.protect(&self.tail);
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+            if !next.is_null() {
+                // `tail` is lagging; help it and try again.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            let linked = unsafe {
+                (*tail).next.compare_exchange(
+                    ptr::null_mut(),
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+            };
+            if linked.is_ok() {
+                // If this fails someone already helped us.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    pub fn dequeue(&self) -> Option<T> {
+        let head_hazard = self.domain.hazard();
+        let next_hazard = self.domain.hazard();
+        loop {
+            let head = head_hazard.protect(&self.head);
+            let next = unsafe { (*head).next.load(Ordering::Acquire) };
+            next_hazard.set(next);
+            // While `head` is still the head, `next` is still linked after it and can't have been
+            // retired yet, so the hazard we just published is good.
+            if self.head.load(Ordering::SeqCst) != head {
+                continue;
+            }
+            if next.is_null() {
+                return None;
+            }
+            let tail = self.tail.load(Ordering::Acquire);
+            if head == tail {
+                // Never move `head` past a lagging `tail`, or `tail` would point at a freed node.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                // `next` is the new dummy and we're the only one who'll take its element.
+                let elem = unsafe { ptr::read((*next).elem.as_ptr()) };
+                drop(head_hazard);
+                drop(next_hazard);
+                unsafe { self.domain.retire(head) };
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                return Some(elem);
+            }
+        }
+    }
+
+    /// Only a snapshot, like `TreiberStack::is_empty`.
+    pub fn is_empty(&self) -> bool {
+        let hazard = self.domain.hazard();
+        let head = hazard.protect(&self.head);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+
+    /// approx_len() is the number of elements enqueued minus the number dequeued, as far as this
+    /// thread can tell. It may count an element whose `enqueue` hasn't returned yet.
+    pub fn approx_len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Default for MsQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for MsQueue<T> {
+    fn drop(&mut self) {
+        let dummy = unsafe { Box::from_raw(*self.head.get_mut()) };
+        let mut cur = dummy.next.load(Ordering::Relaxed);
+        while !cur.is_null() {
+            let mut node = unsafe { Box::from_raw(cur) };
+            unsafe { ptr::drop_in_place(node.elem.as_mut_ptr()) };
+            cur = *node.next.get_mut();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MsQueue;
+    use crate::test_util::Drops;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let queue = MsQueue::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.dequeue(), None);
+
+        queue.enqueue(1);
+        queue.enqueue(2);
+        queue.enqueue(3);
+        assert_eq!(queue.approx_len(), 3);
+        assert!(!queue.is_empty());
+
+        assert_eq!(queue.dequeue(), Some(1));
+        assert_eq!(queue.dequeue(), Some(2));
+
+        queue.enqueue(4);
+        queue.enqueue(5);
+        assert_eq!(queue.approx_len(), 3);
+
+        assert_eq!(queue.dequeue(), Some(3));
+        assert_eq!(queue.dequeue(), Some(4));
+        assert_eq!(queue.dequeue(), Some(5));
+        assert_eq!(queue.dequeue(), None);
+        assert_eq!(queue.approx_len(), 0);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drops_everything_once() {
+        let drops = Drops::new();
+        let queue = MsQueue::new();
+        for _ in 0..1_000 {
+            queue.enqueue(drops.counted());
+        }
+        for _ in 0..400 {
+            drop(queue.dequeue());
+        }
+        assert_eq!(drops.get(), 400);
+        drop(queue);
+        assert_eq!(drops.get(), 1_000);
+    }
+
+    /// Producers enqueue `(producer, seq)`; each consumer must see every producer's sequence
+    /// numbers in increasing order, and between them they must see each pair exactly once.
+    fn producers_consumers(producers: usize, consumers: usize, per_producer: usize) {
+        let queue = Arc::new(MsQueue::new());
+        let done = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(producers + consumers));
+
+        let producer_handles: Vec<_> = (0..producers)
+            .map(|p| {
+                let queue = queue.clone();
+                let done = done.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    for seq in 0..per_producer {
+                        queue.enqueue((p, seq));
+                    }
+                    done.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        let consumer_handles: Vec<_> = (0..consumers)
+            .map(|_| {
+                let queue = queue.clone();
+                let done = done.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let mut last = vec![None; producers];
+                    let mut got = Vec::new();
+                    loop {
+                        match queue.dequeue() {
+                            Some((p, seq)) => {
+                                assert!(last[p] < Some(seq), "producer {} out of order", p);
+                                last[p] = Some(seq);
+                                got.push((p, seq));
+                            }
+                            None if done.load(Ordering::SeqCst) == producers => {
+                                if queue.is_empty() {
+                                    return got;
+                                }
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in producer_handles {
+            handle.join().unwrap();
+        }
+        let mut all: Vec<(usize, usize)> = consumer_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+
+        all.sort_unstable();
+        let expected: Vec<_> = (0..producers)
+            .flat_map(|p| (0..per_producer).map(move |seq| (p, seq)))
+            .collect();
+        assert_eq!(all, expected);
+        assert_eq!(queue.approx_len(), 0);
+    }
+
+    #[test]
+    fn spsc() {
+        producers_consumers(1, 1, 100_000);
+    }
+
+    #[test]
+    fn mpsc() {
+        producers_consumers(4, 1, 25_000);
+    }
+
+    #[test]
+    fn spmc() {
+        producers_consumers(1, 4, 100_000);
+    }
+
+    #[test]
+    fn mpmc() {
+        producers_consumers(4, 4, 25_000);
+    }
+}
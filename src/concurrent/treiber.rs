@@ -0,0 +1,229 @@
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use super::hazard::Domain;
+
+/// Treiber's lock-free stack.
+///
+/// It's `second::List` with the head swapped for an `AtomicPtr` and `Option<Box<_>>` links swapped
+/// for raw pointers:
+///
+/// ```ignore
+/// head: AtomicPtr -> (elem, next) -> (elem, next) -> null
+/// ```
+///
+/// push() builds the node off to the side and swings `head` to it with a compare-and-swap, and
+/// pop() swings `head` to `head.next`. The only hard part is that pop() has to read `head.next`
+/// from a node another popper may free at any moment, which is what the hazard pointers are for:
+/// the node we're looking at is never freed, so it can't be reused under our CAS either.
+pub struct TreiberStack<T> {
+    head: AtomicPtr<Node<T>>,
+    domain: Domain,
+    _marker: PhantomData<T>,
+}
+
+struct Node<T> {
+    /// Moved out by the popper that unlinks the node, so retiring the node mustn't drop it.
+    elem: ManuallyDrop<T>,
+    /// Never changes once the node is published.
+    next: *mut Node<T>,
+}
+
+unsafe impl<T: Send> Send for TreiberStack<T> {}
+unsafe impl<T: Send> Sync for TreiberStack<T> {}
+
+impl<T> TreiberStack<T> {
+    pub fn new() -> Self {
+        TreiberStack {
+            head: AtomicPtr::new(ptr::null_mut()),
+            domain: Domain::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// push() never dereferences a shared node, so it needs no hazard.
+    pub fn push(&self, elem: T) {
+        let node = Box::into_raw(Box::new(Node {
+            elem: ManuallyDrop::new(elem),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let hazard = self.domain.hazard();
+        loop {
+            let head = hazard.protect(&self.head);
+            if head.is_null() {
+                return None;
+            }
+            // Safe to read: `head` is protected, so it hasn't been freed.
+            let next = unsafe { (*head).next };
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                hazard.clear();
+                // We unlinked it, so we're the only one who'll ever take the element.
+                let elem = unsafe { ptr::read(&*(*head).elem) };
+                unsafe { self.domain.retire(head) };
+                return Some(elem);
+            }
+        }
+    }
+
+    /// Only a snapshot, of course: another thread may push or pop right after.
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<T> Default for TreiberStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for TreiberStack<T> {
+    /// `&mut self` means nobody else is looking, so this is just `second::List`'s destructor. The
+    /// domain frees the retired nodes after us.
+    fn drop(&mut self) {
+        let mut cur = *self.head.get_mut();
+        while !cur.is_null() {
+            let mut node = unsafe { Box::from_raw(cur) };
+            unsafe { ManuallyDrop::drop(&mut node.elem) };
+            cur = node.next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TreiberStack;
+    use crate::test_util::Drops;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let stack = TreiberStack::new();
+        assert!(stack.is_empty());
+        assert_eq!(stack.pop(), None);
+
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+        assert!(!stack.is_empty());
+
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+
+        stack.push(4);
+        assert_eq!(stack.pop(), Some(4));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn drops_everything_once() {
+        let drops = Drops::new();
+        let stack = TreiberStack::new();
+        for _ in 0..1_000 {
+            stack.push(drops.counted());
+        }
+        for _ in 0..600 {
+            drop(stack.pop());
+        }
+        assert_eq!(drops.get(), 600);
+        drop(stack);
+        assert_eq!(drops.get(), 1_000);
+    }
+
+    /// Every thread pushes its own range and pops whatever it can at the same time; afterwards
+    /// each value must have been popped exactly once.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 8;
+        const PER_THREAD: usize = 20_000;
+
+        let stack = Arc::new(TreiberStack::new());
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let stack = stack.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let mut popped = Vec::new();
+                    for i in 0..PER_THREAD {
+                        stack.push(t * PER_THREAD + i);
+                        if i % 2 == 0 {
+                            popped.extend(stack.pop());
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        let mut seen: Vec<usize> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        while let Some(elem) = stack.pop() {
+            seen.push(elem);
+        }
+
+        seen.sort_unstable();
+        assert_eq!(seen, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+    }
+
+    /// A tiny stack that is pushed and popped in a tight loop: the same few addresses keep coming
+    /// back, which is exactly when an unprotected CAS would go wrong.
+    #[test]
+    fn aba() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 50_000;
+
+        let stack = Arc::new(TreiberStack::new());
+        for i in 0..THREADS {
+            stack.push(i);
+        }
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    for _ in 0..ROUNDS {
+                        if let Some(elem) = stack.pop() {
+                            stack.push(elem);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut left = Vec::new();
+        while let Some(elem) = stack.pop() {
+            left.push(elem);
+        }
+        left.sort_unstable();
+        assert_eq!(left, (0..THREADS).collect::<Vec<_>>());
+    }
+}
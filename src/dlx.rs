@@ -0,0 +1,490 @@
+//! Knuth's Algorithm X with dancing links, for exact cover problems.
+//!
+//! An exact cover problem is a set of columns and a set of rows, each row covering some of the
+//! columns. A solution picks rows so that every column is covered exactly once. Sudoku,
+//! N-queens, pentomino tilings and many scheduling puzzles are all exact cover in disguise.
+//!
+//! Algorithm X is plain backtracking: pick a column, try each row that covers it, remove every
+//! column that row covers (and every other row that clashes with it), recurse, then put it all
+//! back. Dancing links makes the "remove" and "put back" cheap. The matrix is a grid of circular
+//! doubly linked lists, one per row and one per column, with a header node on each column:
+//!
+//! ```ignore
+//!  root <-> [A] <-> [B] <-> [C] <-> root      column headers
+//!            |       |       |
+//!           (1) <----------> (1)              row 1 covers A and C
+//!            |       |       |
+//!            |      (2) <--> (2)              row 2 covers B and C
+//! ```
+//!
+//! Unlinking a node from a doubly linked list leaves the node's own links alone:
+//!
+//! ```ignore
+//! x.left.right = x.right;  x.right.left = x.left;     // remove x
+//! x.left.right = x;        x.right.left = x;          // ...and it's back
+//! ```
+//!
+//! so as long as things are put back in the reverse order they were taken out, the search can
+//! undo every step without remembering anything. The nodes live in one `Vec` and link to each
+//! other by position, like `arena_list`'s.
+//!
+//! Secondary columns may be covered at most once rather than exactly once. Their headers just
+//! aren't on the root's list, so the search never has to pick one, but covering them still
+//! knocks out the rows that clash.
+
+/// The root's own column. Columns are `1..=columns` in the arena, rows come after.
+const ROOT: usize = 0;
+
+pub struct ExactCover {
+    nodes: Vec<Node>,
+    /// How many rows are still in each column, headers included (the root's entry is unused).
+    sizes: Vec<usize>,
+    rows: usize,
+}
+
+struct Node {
+    left: usize,
+    right: usize,
+    up: usize,
+    down: usize,
+    /// The column header this node is under; a header points at itself.
+    column: usize,
+    /// Which row it's part of, `usize::MAX` for headers.
+    row: usize,
+}
+
+/// Iterator over every solution, as the indices of the rows it picks, in increasing order.
+///
+/// It borrows the problem mutably because the search unlinks nodes as it goes. Dropping it
+/// before the end relinks them, so the problem is left as it was.
+pub struct Solutions<'a> {
+    dlx: &'a mut ExactCover,
+    /// A row node for each level of the search. `Try` is about to try the top one's row; in the
+    /// other states, every one of them is covered.
+    choices: Vec<usize>,
+    state: State,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Look for a column to cover next.
+    Enter,
+    /// The top choice's column is covered; try its row, or give up on the column at its header.
+    Try,
+    /// Undo the top choice and move on to the next row down.
+    Backtrack,
+    Done,
+}
+
+impl ExactCover {
+    /// A problem with `primary` columns that must be covered exactly once, numbered from 0,
+    /// followed by `secondary` columns that may be covered at most once.
+    pub fn new(primary: usize, secondary: usize) -> Self {
+        let columns = primary + secondary;
+        let mut nodes = Vec::with_capacity(columns + 1);
+        for column in 0..=columns {
+            nodes.push(Node {
+                left: column,
+                right: column,
+                up: column,
+                down: column,
+                column,
+                row: usize::MAX,
+            });
+        }
+        // Put the root and the primary headers in one ring; secondary headers stay on their own.
+        for column in 0..=primary {
+            nodes[column].right = (column + 1) % (primary + 1);
+            nodes[(column + 1) % (primary + 1)].left = column;
+        }
+        ExactCover {
+            nodes,
+            sizes: vec![0; columns + 1],
+            rows: 0,
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.sizes.len() - 1
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// add_row() adds a row covering `columns` and returns its index, which is how solutions
+    /// refer to it. A row that covers nothing is allowed, and never part of a solution.
+    ///
+    /// # Panics
+    ///
+    /// If a column is out of range, or listed twice.
+    pub fn add_row(&mut self, columns: &[usize]) -> usize {
+        let row = self.rows;
+        let mut sorted = columns.to_vec();
+        sorted.sort_unstable();
+        for pair in sorted.windows(2) {
+            assert!(
+                pair[0] != pair[1],
+                "column {} is in row {} twice",
+                pair[0],
+                row
+            );
+        }
+        if let Some(&last) = sorted.last() {
+            assert!(
+                last < self.columns(),
+                "column {} out of range for {} columns",
+                last,
+                self.columns()
+            );
+        }
+
+        let first = self.nodes.len();
+        let n = columns.len();
+        for (i, &column) in columns.iter().enumerate() {
+            let node = first + i;
+            let header = column + 1;
+            let above = self.nodes[header].up;
+            // Into the row's ring, and at the bottom of the column's.
+            self.nodes.push(Node {
+                left: first + (i + n - 1) % n,
+                right: first + (i + 1) % n,
+                up: above,
+                down: header,
+                column: header,
+                row,
+            });
+            self.nodes[above].down = node;
+            self.nodes[header].up = node;
+            self.sizes[header] += 1;
+        }
+        self.rows += 1;
+        row
+    }
+
+    /// Takes `column` off the header list and every row that uses it out of the other columns.
+    fn cover(&mut self, column: usize) {
+        let (left, right) = (self.nodes[column].left, self.nodes[column].right);
+        self.nodes[left].right = right;
+        self.nodes[right].left = left;
+
+        let mut i = self.nodes[column].down;
+        while i != column {
+            let mut j = self.nodes[i].right;
+            while j != i {
+                let (up, down) = (self.nodes[j].up, self.nodes[j].down);
+                self.nodes[up].down = down;
+                self.nodes[down].up = up;
+                self.sizes[self.nodes[j].column] -= 1;
+                j = self.nodes[j].right;
+            }
+            i = self.nodes[i].down;
+        }
+    }
+
+    /// `cover` backwards: the same links, in the opposite order.
+    fn uncover(&mut self, column: usize) {
+        let mut i = self.nodes[column].up;
+        while i != column {
+            let mut j = self.nodes[i].left;
+            while j != i {
+                self.sizes[self.nodes[j].column] += 1;
+                let (up, down) = (self.nodes[j].up, self.nodes[j].down);
+                self.nodes[up].down = j;
+                self.nodes[down].up = j;
+                j = self.nodes[j].left;
+            }
+            i = self.nodes[i].up;
+        }
+
+        let (left, right) = (self.nodes[column].left, self.nodes[column].right);
+        self.nodes[left].right = column;
+        self.nodes[right].left = column;
+    }
+
+    /// Covers the columns of `node`'s row other than its own.
+    fn cover_row(&mut self, node: usize) {
+        let mut j = self.nodes[node].right;
+        while j != node {
+            self.cover(self.nodes[j].column);
+            j = self.nodes[j].right;
+        }
+    }
+
+    fn uncover_row(&mut self, node: usize) {
+        let mut j = self.nodes[node].left;
+        while j != node {
+            self.uncover(self.nodes[j].column);
+            j = self.nodes[j].left;
+        }
+    }
+
+    /// The primary column with the fewest rows left, which keeps the search tree narrow.
+    fn choose_column(&self) -> usize {
+        let mut best = self.nodes[ROOT].right;
+        let mut column = best;
+        while column != ROOT {
+            if self.sizes[column] < self.sizes[best] {
+                best = column;
+            }
+            column = self.nodes[column].right;
+        }
+        best
+    }
+
+    pub fn solve_all(&mut self) -> Solutions<'_> {
+        Solutions {
+            dlx: self,
+            choices: Vec::new(),
+            state: State::Enter,
+        }
+    }
+
+    pub fn solve_first(&mut self) -> Option<Vec<usize>> {
+        self.solve_all().next()
+    }
+
+    pub fn count_solutions(&mut self) -> usize {
+        self.solve_all().count()
+    }
+}
+
+impl Solutions<'_> {
+    fn solution(&self) -> Vec<usize> {
+        let mut rows: Vec<_> = self
+            .choices
+            .iter()
+            .map(|&node| self.dlx.nodes[node].row)
+            .collect();
+        rows.sort_unstable();
+        rows
+    }
+}
+
+impl Iterator for Solutions<'_> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        loop {
+            match self.state {
+                State::Enter => {
+                    if self.dlx.nodes[ROOT].right == ROOT {
+                        // Every primary column is covered.
+                        self.state = State::Backtrack;
+                        return Some(self.solution());
+                    }
+                    let column = self.dlx.choose_column();
+                    self.dlx.cover(column);
+                    self.choices.push(self.dlx.nodes[column].down);
+                    self.state = State::Try;
+                }
+                State::Try => {
+                    let node = *self.choices.last().unwrap();
+                    let column = self.dlx.nodes[node].column;
+                    if node == column {
+                        // Back at the header: no rows left to try for this column.
+                        self.dlx.uncover(column);
+                        self.choices.pop();
+                        self.state = State::Backtrack;
+                    } else {
+                        self.dlx.cover_row(node);
+                        self.state = State::Enter;
+                    }
+                }
+                State::Backtrack => match self.choices.last_mut() {
+                    Some(node) => {
+                        let tried = *node;
+                        *node = self.dlx.nodes[tried].down;
+                        self.dlx.uncover_row(tried);
+                        self.state = State::Try;
+                    }
+                    None => {
+                        self.state = State::Done;
+                        return None;
+                    }
+                },
+                State::Done => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Solutions<'_> {
+    fn drop(&mut self) {
+        if self.state == State::Try {
+            // The top choice's row isn't covered yet, only its column.
+            let node = self.choices.pop().unwrap();
+            self.dlx.uncover(self.dlx.nodes[node].column);
+        }
+        while let Some(node) = self.choices.pop() {
+            self.dlx.uncover_row(node);
+            self.dlx.uncover(self.dlx.nodes[node].column);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ExactCover;
+
+    /// The example from Knuth's paper: columns A to G.
+    fn knuth() -> ExactCover {
+        let mut dlx = ExactCover::new(7, 0);
+        for row in [
+            &[2, 4, 5][..],
+            &[0, 3, 6],
+            &[1, 2, 5],
+            &[0, 3],
+            &[1, 6],
+            &[3, 4, 6],
+        ] {
+            dlx.add_row(row);
+        }
+        dlx
+    }
+
+    #[test]
+    fn knuths_example() {
+        let mut dlx = knuth();
+        assert_eq!(dlx.rows(), 6);
+        assert_eq!(dlx.columns(), 7);
+        assert_eq!(dlx.solve_first(), Some(vec![0, 3, 4]));
+        assert_eq!(dlx.solve_all().collect::<Vec<_>>(), [vec![0, 3, 4]]);
+        assert_eq!(dlx.count_solutions(), 1);
+    }
+
+    #[test]
+    fn edge_cases() {
+        // Nothing to cover: picking no rows does it.
+        assert_eq!(ExactCover::new(0, 0).count_solutions(), 1);
+        assert_eq!(ExactCover::new(0, 3).solve_first(), Some(vec![]));
+
+        // A column no row covers.
+        let mut dlx = ExactCover::new(2, 0);
+        dlx.add_row(&[0]);
+        assert_eq!(dlx.solve_first(), None);
+
+        // Several ways, and an empty row that never helps.
+        let mut dlx = ExactCover::new(2, 0);
+        dlx.add_row(&[0]);
+        dlx.add_row(&[1]);
+        dlx.add_row(&[0, 1]);
+        dlx.add_row(&[]);
+        dlx.add_row(&[1]);
+        let mut all: Vec<_> = dlx.solve_all().collect();
+        all.sort();
+        assert_eq!(all, [vec![0, 1], vec![0, 4], vec![2]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "twice")]
+    fn duplicate_column() {
+        ExactCover::new(3, 0).add_row(&[1, 2, 1]);
+    }
+
+    #[test]
+    fn secondary_columns() {
+        // Cover 0 and 1 exactly once; 2 at most once.
+        let mut dlx = ExactCover::new(2, 1);
+        dlx.add_row(&[0, 2]);
+        dlx.add_row(&[1, 2]);
+        dlx.add_row(&[0]);
+        dlx.add_row(&[1]);
+        let mut all: Vec<_> = dlx.solve_all().collect();
+        all.sort();
+        // Rows 0 and 1 clash on column 2; every other combination is fine.
+        assert_eq!(all, [vec![0, 3], vec![1, 2], vec![2, 3]]);
+    }
+
+    #[test]
+    fn stopping_early_puts_everything_back() {
+        let mut dlx = queens(6);
+        for stop in 0..4 {
+            assert_eq!(dlx.solve_all().take(stop).count(), stop);
+            assert_eq!(dlx.count_solutions(), 4);
+        }
+        // Also in the middle of trying a column.
+        let mut solutions = dlx.solve_all();
+        solutions.next();
+        drop(solutions);
+        assert_eq!(dlx.count_solutions(), 4);
+    }
+
+    /// Row `r * n + c` puts a queen on rank `r`, file `c`. Ranks and files are primary: each
+    /// needs exactly one queen. Diagonals are secondary: at most one.
+    fn queens(n: usize) -> ExactCover {
+        let diagonals = 2 * n - 1;
+        let mut dlx = ExactCover::new(2 * n, 2 * diagonals);
+        for r in 0..n {
+            for c in 0..n {
+                dlx.add_row(&[r, n + c, 2 * n + r + c, 2 * n + diagonals + r + n - 1 - c]);
+            }
+        }
+        dlx
+    }
+
+    #[test]
+    fn n_queens() {
+        let counts: Vec<_> = (1..=8).map(|n| queens(n).count_solutions()).collect();
+        assert_eq!(counts, [1, 0, 0, 2, 10, 4, 40, 92]);
+
+        let n = 8;
+        let solution = queens(n).solve_first().unwrap();
+        let placed: Vec<_> = solution.iter().map(|&row| (row / n, row % n)).collect();
+        for (i, &(r1, c1)) in placed.iter().enumerate() {
+            for &(r2, c2) in &placed[i + 1..] {
+                assert!(r1 != r2 && c1 != c2);
+                assert_ne!(r1.abs_diff(r2), c1.abs_diff(c2));
+            }
+        }
+    }
+
+    /// Row `81 * r + 9 * c + d` writes digit `d + 1` at row `r`, column `c`. The 324 columns
+    /// say that every cell has a digit and every row, column and box has every digit once.
+    /// Given cells only get the row for their digit.
+    fn sudoku(grid: &str) -> ExactCover {
+        let cells: Vec<_> = grid.chars().filter(|c| !c.is_whitespace()).collect();
+        assert_eq!(cells.len(), 81);
+        let mut dlx = ExactCover::new(4 * 81, 0);
+        for r in 0..9 {
+            for c in 0..9 {
+                let b = r / 3 * 3 + c / 3;
+                let given = cells[r * 9 + c].to_digit(10).filter(|&g| g != 0);
+                for d in 0..9 {
+                    let row = if given.is_some_and(|g| g as usize != d + 1) {
+                        // Ruled out by the given digit. Adding it empty keeps the numbering.
+                        dlx.add_row(&[])
+                    } else {
+                        dlx.add_row(&[r * 9 + c, 81 + r * 9 + d, 162 + c * 9 + d, 243 + b * 9 + d])
+                    };
+                    assert_eq!(row, 81 * r + 9 * c + d);
+                }
+            }
+        }
+        dlx
+    }
+
+    #[test]
+    fn sudoku_puzzle() {
+        let puzzle = "
+            530070000
+            600195000
+            098000060
+            800060003
+            400803001
+            700020006
+            060000280
+            000419005
+            000080079";
+        let mut dlx = sudoku(puzzle);
+        let solution = dlx.solve_first().unwrap();
+        assert_eq!(solution.len(), 81);
+        let mut grid = [[0; 9]; 9];
+        for row in solution {
+            grid[row / 81][row / 9 % 9] = row % 9 + 1;
+        }
+        assert_eq!(grid[0], [5, 3, 4, 6, 7, 8, 9, 1, 2]);
+        assert_eq!(grid[8], [3, 4, 5, 2, 8, 6, 1, 7, 9]);
+        assert_eq!(dlx.count_solutions(), 1);
+    }
+}
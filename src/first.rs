@@ -74,7 +74,7 @@ struct Node {
 /// Notes on ownership (https://cglab.ca/~abeinges/blah/too-many-lists/book/first-ownership.html):
 /// - `self`      - Value
 /// - `&mut self` - mutable reference. The only thing you can't do with an &mut is move the value
-/// out with no replacement.
+///   out with no replacement.
 /// - `&self`     - shared reference
 impl List {
     /// new() will take no parameter and construct an empty list for us
@@ -89,7 +89,7 @@ impl List {
     /// with no replacement.
     pub fn push(&mut self, elem: i32) {
         let new_node = Box::new(Node {
-            elem: elem,
+            elem,
             next: mem::replace(&mut self.head, Link::Empty),
         });
 
@@ -154,6 +154,12 @@ impl List {
     }
 }
 
+impl Default for List {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// ```ignore
 /// impl Drop for List {
 ///     fn drop(&mut self) {
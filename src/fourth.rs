@@ -21,7 +21,7 @@ pub struct List<T> {
 ///
 type Link<T> = Option<Rc<RefCell<Node<T>>>>;
 
-struct Node<T> {
+pub(crate) struct Node<T> {
     elem: T,
     next: Link<T>,
     prev: Link<T>,
@@ -29,10 +29,13 @@ struct Node<T> {
 
 pub struct IntoIter<T>(List<T>);
 
+// There's no `Iterator` impl for `Iter`: the `Ref` of the next node can't outlive the `Ref` of
+// the current one, so the field is never read.
+#[allow(dead_code)]
 pub struct Iter<'a, T: 'a>(Option<Ref<'a, Node<T>>>);
 
 impl<T> List<T> {
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter(self.head.as_ref().map(|head| head.borrow()))
     }
 }
@@ -44,7 +47,7 @@ impl<T> Node<T> {
     /// Node constructor.
     fn new(elem: T) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Node {
-            elem: elem,
+            elem,
             prev: None,
             next: None,
         }))
@@ -98,7 +101,7 @@ impl<T> List<T> {
                                       // total: -2 old, (no new)
                 }
             }
-            /// We need something that takes a RefCell<T> and gives us a T
+            // We need something that takes a RefCell<T> and gives us a T
             //old_head.elem
             //old_head.borrow_mut().elem
             //old_head.into_inner().elem
@@ -126,7 +129,7 @@ impl<T> List<T> {
     /// and the Ref goes out of scope.
     ///
     /// ```ignore
-    /// pub fn peek_front(&self) -> Option<Ref<T>> {
+    /// pub fn peek_front(&self) -> Option<Ref<'_, T>> {
     ///     self.head.as_ref().map(|node| {
     ///         node.borrow()
     ///     })
@@ -135,7 +138,7 @@ impl<T> List<T> {
     /// We have a Ref<Node<T>>, but we want a Ref<T>. We could abandon all hope of encapsulation
     /// and just return that. We could also make things even more complicated and wrap Ref<Node<T>>
     /// in a new type to only expose access to an &T.
-    pub fn peek_front(&self) -> Option<Ref<T>> {
+    pub fn peek_front(&self) -> Option<Ref<'_, T>> {
         self.head
             .as_ref()
             // just like you can map over an Option, you can map over a Ref / monads
@@ -172,33 +175,42 @@ impl<T> List<T> {
         })
     }
 
-    pub fn peek_back(&self) -> Option<Ref<T>> {
+    pub fn peek_back(&self) -> Option<Ref<'_, T>> {
         self.tail
             .as_ref()
             .map(|node| Ref::map(node.borrow(), |node| &node.elem))
     }
 
-    pub fn peek_back_mut(&mut self) -> Option<RefMut<T>> {
+    pub fn peek_back_mut(&mut self) -> Option<RefMut<'_, T>> {
         self.tail
             .as_ref()
             .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
     }
 
-    pub fn peek_front_mut(&mut self) -> Option<RefMut<T>> {
+    pub fn peek_front_mut(&mut self) -> Option<RefMut<'_, T>> {
         self.head
             .as_ref()
             .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
     }
 }
 
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Drop for List<T> {
     fn drop(&mut self) {
         while self.pop_front().is_some() {}
     }
 }
 
-impl<T> List<T> {
-    pub fn into_iter(self) -> IntoIter<T> {
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
         IntoIter(self)
     }
 }
@@ -0,0 +1,662 @@
+//! `fourth::List` again, but one that can be shared between threads.
+//!
+//! `Rc<RefCell<_>>` becomes `Arc<Mutex<_>>`, and the two ends of the list get a `Mutex` each, so
+//! every method takes `&self` and the list can sit in an `Arc`. Each node has its own lock rather
+//! than one lock for the whole list: a producer at the front and a consumer at the back only touch
+//! nodes at opposite ends and don't wait for each other.
+//!
+//! Per-node locking is where deadlocks come from. Think of a two-element list with one thread
+//! popping each end: the front popper holds the first node and wants the second, the back popper
+//! holds the second and wants the first. So every lock has a place in one global order:
+//!
+//! ```ignore
+//! head slot  <  nodes, front to back  <  tail slot
+//! ```
+//!
+//! and we only ever *wait* for a lock that comes later than everything we already hold. Working
+//! from the back goes against that order, so there we `try_lock` instead, and if the lock is
+//! taken we let go of everything and start over. Nobody waits on somebody who waits on them, so
+//! there's no deadlock, only the occasional retry.
+//!
+//! The link between two neighbours only changes with both of them locked, and an end slot only
+//! changes with that slot and the node at that end locked. Going from empty to non-empty (or back)
+//! is the one time both end slots change, and it always happens with both of them locked.
+//!
+//! The one lock we don't control is the one a `Peek` holds. If the thread holding it calls back
+//! into the list, it can end up waiting for itself, where `fourth`'s `RefCell` would have
+//! panicked. So every thread keeps track of the lists it's peeking into, and calling one of them
+//! panics too.
+
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::thread;
+
+pub struct List<T> {
+    head: Mutex<Link<T>>,
+    tail: Mutex<Link<T>>,
+}
+
+type Link<T> = Option<Arc<Mutex<Node<T>>>>;
+
+struct Node<T> {
+    /// Taken out once the node has been unlinked. Another thread may still be holding an `Arc`
+    /// to it for a moment, so we can't count on `Rc::try_unwrap` like `fourth` does.
+    elem: Option<T>,
+    next: Link<T>,
+    prev: Link<T>,
+}
+
+pub struct IntoIter<T>(List<T>);
+
+thread_local! {
+    /// The addresses of the lists this thread holds a `Peek` into. A list can't move while it's
+    /// borrowed, and a `Peek` can't change threads, so the address is enough.
+    static PEEKING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Nothing in here panics with a lock held, but a user's code can panic while holding a `PeekMut`.
+/// The links are fine then, so just carry on.
+fn lock<U>(mutex: &Mutex<U>) -> MutexGuard<'_, U> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// For locks that go against the order: `None` means back off.
+fn try_lock<U>(mutex: &Mutex<U>) -> Option<MutexGuard<'_, U>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+impl<T> Node<T> {
+    fn new(elem: T) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Node {
+            elem: Some(elem),
+            prev: None,
+            next: None,
+        }))
+    }
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List {
+            head: Mutex::new(None),
+            tail: Mutex::new(None),
+        }
+    }
+
+    fn addr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Every method calls this before taking any lock: the ones a `Peek` holds would never be
+    /// let go of.
+    fn not_peeking(&self) {
+        let list = self.addr();
+        if PEEKING.with(|peeking| peeking.borrow().contains(&list)) {
+            panic!("fourth_sync::List used by a thread holding a Peek into it");
+        }
+    }
+
+    /// Only a snapshot if other threads are using the list.
+    pub fn is_empty(&self) -> bool {
+        self.not_peeking();
+        lock(&self.head).is_none()
+    }
+
+    /// The front is the easy end: everything is locked in order, so nothing here retries.
+    pub fn push_front(&self, elem: T) {
+        self.not_peeking();
+        let new_head = Node::new(elem);
+        let mut head = lock(&self.head);
+        match head.take() {
+            Some(old_head) => {
+                lock(&old_head).prev = Some(new_head.clone());
+                lock(&new_head).next = Some(old_head);
+            }
+            None => {
+                *lock(&self.tail) = Some(new_head.clone());
+            }
+        }
+        *head = Some(new_head);
+    }
+
+    pub fn pop_front(&self) -> Option<T> {
+        self.not_peeking();
+        let mut head = lock(&self.head);
+        let old_head = head.take()?;
+        let mut old = lock(&old_head);
+        match old.next.take() {
+            Some(new_head) => {
+                lock(&new_head).prev = None;
+                *head = Some(new_head);
+            }
+            None => {
+                *lock(&self.tail) = None;
+            }
+        }
+        old.elem.take()
+    }
+
+    pub fn push_back(&self, elem: T) {
+        self.not_peeking();
+        let new_tail = Node::new(elem);
+        loop {
+            let mut tail = lock(&self.tail);
+            // Both the last node and the head slot come before the tail slot.
+            let linked = match tail.as_ref() {
+                Some(old_tail) => try_lock(old_tail).map(|mut old| {
+                    old.next = Some(new_tail.clone());
+                    lock(&new_tail).prev = Some(old_tail.clone());
+                }),
+                None => try_lock(&self.head).map(|mut head| *head = Some(new_tail.clone())),
+            };
+            if linked.is_some() {
+                *tail = Some(new_tail);
+                return;
+            }
+            drop(tail);
+            thread::yield_now();
+        }
+    }
+
+    pub fn pop_back(&self) -> Option<T> {
+        self.not_peeking();
+        loop {
+            let mut tail = lock(&self.tail);
+            let old_tail = tail.as_ref()?.clone();
+            if let Some(mut old) = try_lock(&old_tail) {
+                let unlinked = match old.prev.clone() {
+                    Some(new_tail) => try_lock(&new_tail).map(|mut new| {
+                        new.next = None;
+                        drop(new);
+                        *tail = Some(new_tail.clone());
+                    }),
+                    None => try_lock(&self.head).map(|mut head| {
+                        *head = None;
+                        *tail = None;
+                    }),
+                };
+                if unlinked.is_some() {
+                    old.prev = None;
+                    return old.elem.take();
+                }
+            }
+            drop(tail);
+            thread::yield_now();
+        }
+    }
+
+    /// Like `fourth`, we can't hand out a plain `&T`, only a guard. This one holds the head slot
+    /// and the first node, so the front can't change under it while the back of the list is still
+    /// free for other threads.
+    ///
+    /// While a `Peek` (or `PeekMut`) is alive, any other call on the same list from the thread
+    /// holding it panics, even at the other end: on a one-element list `push_back` and
+    /// `pop_back` need the very node the guard holds, and would wait for it forever. Other
+    /// threads just wait their turn.
+    pub fn peek_front(&self) -> Option<Peek<'_, T>> {
+        self.not_peeking();
+        let head = lock(&self.head);
+        // In order, so this always gets a guard.
+        Peek::new(self, head, |node| Some(lock(node))).ok()?
+    }
+
+    /// The back end's version of `peek_front`, with the same rule.
+    pub fn peek_back(&self) -> Option<Peek<'_, T>> {
+        self.not_peeking();
+        loop {
+            let tail = lock(&self.tail);
+            match Peek::new(self, tail, try_lock) {
+                Ok(peek) => return peek,
+                Err(tail) => drop(tail),
+            }
+            thread::yield_now();
+        }
+    }
+
+    pub fn peek_front_mut(&self) -> Option<PeekMut<'_, T>> {
+        self.peek_front().map(PeekMut)
+    }
+
+    pub fn peek_back_mut(&self) -> Option<PeekMut<'_, T>> {
+        self.peek_back().map(PeekMut)
+    }
+}
+
+/// `MappedMutexGuard` (the `Mutex` version of `Ref::map`) isn't stable yet, so here's the bit of
+/// it we need: the lock on one end of the list plus the lock on the node at that end, showing
+/// only the element. Calling into the same list while holding one panics; see
+/// `List::peek_front`.
+pub struct Peek<'a, T> {
+    // Fields are dropped in order: the node's lock goes before the slot that keeps it alive.
+    node: MutexGuard<'a, Node<T>>,
+    _end: MutexGuard<'a, Link<T>>,
+    /// The list's address in `PEEKING`.
+    list: usize,
+}
+
+/// `Peek` with write access.
+pub struct PeekMut<'a, T>(Peek<'a, T>);
+
+impl<'a, T> Peek<'a, T> {
+    /// `Ok(None)` for an empty list. If `lock_node` can't get the node, the slot is handed back.
+    fn new<F>(
+        list: &'a List<T>,
+        end: MutexGuard<'a, Link<T>>,
+        lock_node: F,
+    ) -> Result<Option<Self>, MutexGuard<'a, Link<T>>>
+    where
+        F: FnOnce(&'a Mutex<Node<T>>) -> Option<MutexGuard<'a, Node<T>>>,
+    {
+        let node = match end.as_ref() {
+            Some(node) => Arc::as_ptr(node),
+            None => return Ok(None),
+        };
+        // The slot holds a strong count and can't change while we hold `end`, and the node guard
+        // never outlives `end`, so the node outlives the borrow.
+        let node = unsafe { &*node };
+        match lock_node(node) {
+            Some(node) => {
+                let list = list.addr();
+                PEEKING.with(|peeking| peeking.borrow_mut().push(list));
+                Ok(Some(Peek {
+                    node,
+                    _end: end,
+                    list,
+                }))
+            }
+            None => Err(end),
+        }
+    }
+}
+
+impl<'a, T> Drop for Peek<'a, T> {
+    fn drop(&mut self) {
+        // `try_with`: the guard may be dropped while the thread's locals are going away.
+        let _ = PEEKING.try_with(|peeking| {
+            let mut peeking = peeking.borrow_mut();
+            if let Some(i) = peeking.iter().position(|&list| list == self.list) {
+                peeking.swap_remove(i);
+            }
+        });
+    }
+}
+
+impl<'a, T> Deref for Peek<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // A linked node always has its element.
+        self.node.elem.as_ref().unwrap()
+    }
+}
+
+impl<'a, T> Deref for PeekMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<'a, T> DerefMut for PeekMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0.node.elem.as_mut().unwrap()
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        // Also breaks the `prev`/`next` cycles, which `Arc` can't collect either.
+        while self.pop_front().is_some() {}
+    }
+}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.0.pop_back()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::List;
+    use crate::test_util::{Drops, XorShift};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    fn basics() {
+        let list = List::new();
+        assert!(list.is_empty());
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+
+        list.push_front(1);
+        list.push_front(2);
+        list.push_front(3);
+        assert_eq!(list.pop_front(), Some(3));
+        assert_eq!(list.pop_front(), Some(2));
+
+        list.push_front(4);
+        list.push_front(5);
+        assert_eq!(list.pop_front(), Some(5));
+        assert_eq!(list.pop_front(), Some(4));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_front(), None);
+
+        list.push_back(1);
+        list.push_back(2);
+        list.push_back(3);
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_back(), Some(2));
+
+        list.push_back(4);
+        list.push_front(0);
+        assert_eq!(list.pop_back(), Some(4));
+        assert_eq!(list.pop_back(), Some(1));
+        assert_eq!(list.pop_back(), Some(0));
+        assert_eq!(list.pop_back(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn peek() {
+        let list = List::new();
+        assert!(list.peek_front().is_none());
+        assert!(list.peek_back().is_none());
+        assert!(list.peek_front_mut().is_none());
+        assert!(list.peek_back_mut().is_none());
+
+        list.push_front(1);
+        list.push_front(2);
+        list.push_front(3);
+
+        assert_eq!(*list.peek_front().unwrap(), 3);
+        assert_eq!(*list.peek_back().unwrap(), 1);
+        *list.peek_front_mut().unwrap() = 30;
+        *list.peek_back_mut().unwrap() *= 10;
+        assert_eq!(list.pop_front(), Some(30));
+        assert_eq!(list.pop_back(), Some(10));
+        assert_eq!(list.pop_back(), Some(2));
+    }
+
+    #[test]
+    fn into_iter() {
+        let list = List::new();
+        list.push_front(1);
+        list.push_front(2);
+        list.push_front(3);
+
+        let mut iter = list.into_iter();
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next_back(), Some(1));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn peek_leaves_the_other_end_free() {
+        let list = Arc::new(List::new());
+        for i in 0..3 {
+            list.push_back(i);
+        }
+        let front = list.peek_front().unwrap();
+
+        let other = list.clone();
+        thread::spawn(move || {
+            other.push_back(3);
+            assert_eq!(other.pop_back(), Some(3));
+            assert_eq!(other.pop_back(), Some(2));
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(*front, 0);
+        drop(front);
+        assert_eq!(list.pop_back(), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "holding a Peek")]
+    fn pop_back_while_peeking_front() {
+        // One element: the back is the node the guard holds.
+        let list = List::new();
+        list.push_back(1);
+        let _front = list.peek_front().unwrap();
+        list.pop_back();
+    }
+
+    #[test]
+    #[should_panic(expected = "holding a Peek")]
+    fn push_back_while_peeking_back() {
+        let list = List::new();
+        list.push_back(1);
+        let _back = list.peek_back().unwrap();
+        list.push_back(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "holding a Peek")]
+    fn peek_twice() {
+        let list = List::new();
+        list.push_back(1);
+        list.push_back(2);
+        let _front = list.peek_front().unwrap();
+        list.peek_back();
+    }
+
+    #[test]
+    fn peeking_is_per_list_and_per_guard() {
+        let (a, b) = (List::new(), List::new());
+        a.push_back(1);
+        b.push_back(2);
+        let peek = a.peek_front().unwrap();
+        // Another list is fine...
+        b.push_back(3);
+        assert_eq!(b.pop_front(), Some(2));
+        assert_eq!(*peek, 1);
+        drop(peek);
+        // ...and so is this one, once the guard is gone.
+        a.push_back(4);
+        assert_eq!(a.pop_back(), Some(4));
+        assert_eq!(*a.peek_back().unwrap(), 1);
+        assert_eq!(a.pop_back(), Some(1));
+    }
+
+    #[test]
+    fn drops_everything_once() {
+        let drops = Drops::new();
+        let list = List::new();
+        for i in 0..100 {
+            if i % 2 == 0 {
+                list.push_front(drops.counted());
+            } else {
+                list.push_back(drops.counted());
+            }
+        }
+        drop(list.pop_front());
+        drop(list.pop_back());
+        assert_eq!(drops.get(), 2);
+        drop(list);
+        assert_eq!(drops.get(), 100);
+    }
+
+    /// Producers push `(producer, seq)` at one end while consumers pop at the other, so the list is
+    /// used as a queue: each consumer must see every producer's sequence numbers in order.
+    fn queue_through(front_to_back: bool) {
+        const PRODUCERS: usize = 3;
+        const CONSUMERS: usize = 3;
+        const PER_PRODUCER: usize = 20_000;
+
+        let list = Arc::new(List::new());
+        let done = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(PRODUCERS + CONSUMERS));
+
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let (list, done, barrier) = (list.clone(), done.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    for seq in 0..PER_PRODUCER {
+                        if front_to_back {
+                            list.push_front((p, seq));
+                        } else {
+                            list.push_back((p, seq));
+                        }
+                    }
+                    done.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let (list, done, barrier) = (list.clone(), done.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    let mut last = [None; PRODUCERS];
+                    let mut got = Vec::new();
+                    loop {
+                        let popped = if front_to_back {
+                            list.pop_back()
+                        } else {
+                            list.pop_front()
+                        };
+                        match popped {
+                            Some((p, seq)) => {
+                                assert!(last[p] < Some(seq), "producer {} out of order", p);
+                                last[p] = Some(seq);
+                                got.push((p, seq));
+                            }
+                            None if done.load(Ordering::SeqCst) == PRODUCERS => {
+                                if list.is_empty() {
+                                    return got;
+                                }
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in producers {
+            handle.join().unwrap();
+        }
+        let mut all: Vec<_> = consumers
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        all.sort_unstable();
+        let expected: Vec<_> = (0..PRODUCERS)
+            .flat_map(|p| (0..PER_PRODUCER).map(move |seq| (p, seq)))
+            .collect();
+        assert_eq!(all, expected);
+    }
+
+    #[test]
+    fn front_to_back() {
+        queue_through(true);
+    }
+
+    #[test]
+    fn back_to_front() {
+        queue_through(false);
+    }
+
+    /// Every thread pushes and pops at both ends at random, peeking now and then. Whatever comes
+    /// out, plus whatever is left, must be exactly what went in.
+    #[test]
+    fn hammer_both_ends() {
+        const THREADS: usize = 6;
+        const OPS: usize = 20_000;
+
+        let list = Arc::new(List::new());
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let (list, barrier) = (list.clone(), barrier.clone());
+                thread::spawn(move || {
+                    // Seeded per thread so runs are repeatable-ish.
+                    let mut rng = XorShift::with_seed(0x9E37_79B9_7F4A_7C15u64 ^ (t as u64 + 1));
+                    let mut pushed = Vec::new();
+                    let mut popped = Vec::new();
+                    barrier.wait();
+                    for i in 0..OPS {
+                        let bits = rng.next_u64();
+                        let value = t * OPS + i;
+                        match bits % 6 {
+                            0 => {
+                                list.push_front(value);
+                                pushed.push(value);
+                            }
+                            1 => {
+                                list.push_back(value);
+                                pushed.push(value);
+                            }
+                            2 => popped.extend(list.pop_front()),
+                            3 => popped.extend(list.pop_back()),
+                            4 => {
+                                if let Some(mut front) = list.peek_front_mut() {
+                                    // Touch it without changing it.
+                                    *front += 0;
+                                }
+                            }
+                            _ => drop(list.peek_back()),
+                        }
+                    }
+                    (pushed, popped)
+                })
+            })
+            .collect();
+
+        let mut pushed = Vec::new();
+        let mut popped = Vec::new();
+        for handle in handles {
+            let (p, q) = handle.join().unwrap();
+            pushed.extend(p);
+            popped.extend(q);
+        }
+        let list = Arc::try_unwrap(list).ok().unwrap();
+        popped.extend(list);
+
+        pushed.sort_unstable();
+        popped.sort_unstable();
+        assert_eq!(pushed, popped);
+    }
+
+    #[test]
+    fn is_send_and_sync() {
+        fn check<T: Send + Sync>() {}
+        check::<List<String>>();
+    }
+}
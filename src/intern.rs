@@ -0,0 +1,241 @@
+//! Hash-consing for `third::List`.
+//!
+//! Persistent lists share a tail whenever one is built from the other, but two lists that happen
+//! to end the same way and were built separately don't:
+//!
+//! ```ignore
+//! list1 -> 1 -> 2 -> 3
+//! list2 -> 0 -> 2 -> 3   (a second copy of 2 -> 3)
+//! ```
+//!
+//! A `ListInterner` remembers every node it has handed out, keyed by its element and the address
+//! of its next node. Asking for a node that is already alive returns that node instead of a new
+//! one, so as long as all the lists come from the same interner there is only ever one copy of
+//! each suffix:
+//!
+//! ```ignore
+//! list1 -> 1 ---v
+//!                2 -> 3
+//! list2 -> 0 ---^
+//! ```
+//!
+//! This also means that two interned lists are equal exactly when they are the same node, which
+//! `List::ptr_eq` checks in O(1).
+//!
+//! The table only holds `Weak`s, so it never keeps a list alive by itself. A `Weak` does keep the
+//! allocation around though (just not the node in it), which is what makes keying on addresses
+//! safe: a dead node's address can't be reused while its entry is still in the table.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+
+use crate::third::{Link, List, Node};
+
+pub struct ListInterner<T> {
+    /// Nodes bucketed by the hash of (elem, next address). Collisions just share a bucket.
+    table: HashMap<u64, Vec<Weak<Node<T>>>>,
+    /// Number of `Weak`s in the table, dead or alive.
+    entries: usize,
+    /// When `entries` gets past this we sweep out the dead ones.
+    sweep_at: usize,
+}
+
+impl<T: Hash + Eq> Default for ListInterner<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Hash + Eq> ListInterner<T> {
+    pub fn new() -> Self {
+        ListInterner {
+            table: HashMap::new(),
+            entries: 0,
+            sweep_at: 64,
+        }
+    }
+
+    /// cons() is the hash-consing version of `List::append`: it returns `elem` in front of `tail`,
+    /// reusing the live node for that pair if there is one.
+    ///
+    /// `tail` is compared by address, so it should come from this interner too. A foreign tail
+    /// still works, it just can't collapse with anything built on an equal interned tail.
+    pub fn cons(&mut self, elem: T, tail: &List<T>) -> List<T> {
+        let hash = Self::hash(&elem, &tail.head);
+        let bucket = self.table.entry(hash).or_default();
+
+        let mut found = None;
+        let before = bucket.len();
+        bucket.retain(|weak| match weak.upgrade() {
+            Some(node) => {
+                if found.is_none() && node.elem == elem && Self::same_next(&node.next, &tail.head) {
+                    found = Some(node);
+                }
+                true
+            }
+            None => false,
+        });
+        self.entries -= before - bucket.len();
+
+        let node = match found {
+            Some(node) => node,
+            None => {
+                let node = Rc::new(Node {
+                    elem,
+                    next: tail.head.clone(),
+                });
+                bucket.push(Rc::downgrade(&node));
+                self.entries += 1;
+                node
+            }
+        };
+        if bucket.is_empty() {
+            self.table.remove(&hash);
+        }
+
+        if self.entries > self.sweep_at {
+            self.collect_garbage();
+            self.sweep_at = (2 * self.entries).max(64);
+        }
+
+        List { head: Some(node) }
+    }
+
+    /// append() is `cons` with the arguments in `List::append` order.
+    pub fn append(&mut self, list: &List<T>, elem: T) -> List<T> {
+        self.cons(elem, list)
+    }
+
+    /// Builds the list of `iter`'s elements in order, sharing every suffix that is already live.
+    pub fn from_iter<I>(&mut self, iter: I) -> List<T>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: DoubleEndedIterator,
+    {
+        iter.into_iter()
+            .rev()
+            .fold(List::new(), |list, elem| self.cons(elem, &list))
+    }
+
+    /// Drops the entries of nodes that died since the last sweep. This also happens on its own
+    /// whenever the table has doubled since the last one.
+    pub fn collect_garbage(&mut self) {
+        self.table.retain(|_, bucket| {
+            bucket.retain(|weak| weak.strong_count() > 0);
+            !bucket.is_empty()
+        });
+        self.entries = self.table.values().map(Vec::len).sum();
+    }
+
+    /// Number of live interned nodes.
+    pub fn len(&self) -> usize {
+        self.table
+            .values()
+            .flatten()
+            .filter(|weak| weak.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn hash(elem: &T, next: &Link<T>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        elem.hash(&mut hasher);
+        next.as_ref().map(Rc::as_ptr).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn same_next(a: &Link<T>, b: &Link<T>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ListInterner;
+    use crate::third::List;
+
+    #[test]
+    fn basics() {
+        let mut interner = ListInterner::new();
+        let list = interner.from_iter(vec![1, 2, 3]);
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(interner.len(), 3);
+
+        let same = interner.from_iter(vec![1, 2, 3]);
+        assert!(same.ptr_eq(&list));
+        assert_eq!(interner.len(), 3);
+
+        let other = interner.from_iter(vec![1, 2, 4]);
+        assert!(!other.ptr_eq(&list));
+        assert_eq!(interner.len(), 6);
+    }
+
+    #[test]
+    fn duplicate_suffixes_collapse() {
+        let mut interner = ListInterner::new();
+        let suffix: Vec<u32> = (100..1_000).collect();
+
+        let a = interner.from_iter(vec![1].into_iter().chain(suffix.clone()));
+        let b = interner.from_iter(vec![2, 3].into_iter().chain(suffix.clone()));
+        let bare = interner.from_iter(suffix.clone());
+        let c = interner.append(&bare, 0);
+
+        assert!(a.tail().ptr_eq(&b.tail().tail()));
+        assert!(a.tail().ptr_eq(&c.tail()));
+        assert!(!a.ptr_eq(&c));
+        // One copy of the suffix, plus 1, 2 -> 3 and 0.
+        assert_eq!(interner.len(), suffix.len() + 4);
+    }
+
+    #[test]
+    fn structural_equality() {
+        let mut interner = ListInterner::new();
+        let mut x = List::new();
+        let mut y = List::new();
+        for i in 0..100 {
+            x = interner.cons(i % 7, &x);
+        }
+        for i in 0..100 {
+            y = interner.append(&y, i % 7);
+        }
+        assert!(x.ptr_eq(&y));
+        assert!(!x.ptr_eq(&y.tail()));
+    }
+
+    #[test]
+    fn dead_entries_are_collected() {
+        let mut interner = ListInterner::new();
+        let keep = interner.from_iter(vec![1, 2]);
+        {
+            let _gone = interner.from_iter(vec![3, 4, 5, 1, 2]);
+            assert_eq!(interner.len(), 5);
+        }
+        assert_eq!(interner.len(), 2);
+        interner.collect_garbage();
+        assert_eq!(interner.entries, 2);
+
+        // A node that died is built again rather than resurrected.
+        let again = interner.from_iter(vec![5, 1, 2]);
+        assert!(again.tail().ptr_eq(&keep));
+        assert_eq!(interner.len(), 3);
+    }
+
+    #[test]
+    fn sweeps_on_its_own() {
+        let mut interner = ListInterner::new();
+        for i in 0..10_000 {
+            interner.cons(i, &List::new());
+        }
+        assert!(interner.entries < 200);
+        assert!(interner.is_empty());
+    }
+}
@@ -1,5 +1,36 @@
+#[macro_use]
+mod macros;
+#[cfg(test)]
+mod test_util;
+
 pub mod first;
 pub mod second;
 pub mod third;
 // interior mutability
 pub mod fourth;
+pub mod fourth_sync;
+pub mod arena_list;
+pub mod ring;
+pub mod xor_list;
+pub mod unrolled;
+pub mod skiplist;
+// laziness
+pub mod stream;
+// persistent structures on top of `third`
+pub mod zipper;
+pub mod intern;
+pub mod assoc;
+pub mod persistent_heap;
+pub mod catenable;
+pub mod vlist;
+// sharing between threads
+pub mod concurrent;
+pub mod channel;
+pub mod async_channel;
+// containers built out of the lists above
+pub mod chained_map;
+pub mod linked_map;
+pub mod timer_wheel;
+pub mod dlx;
+pub mod pairing_heap;
+pub mod self_org;
@@ -0,0 +1,637 @@
+//! A hash map that remembers the order its keys went in.
+//!
+//! The entries themselves live in an `arena_list::List`, in order, and a hash table maps each
+//! key's hash to the entry's `Index`:
+//!
+//! ```ignore
+//! by_hash:  hash("port") -> #1    hash("host") -> #0    hash("user") -> #2
+//!
+//! entries:  #0 ("host", ..) <-> #1 ("port", ..) <-> #2 ("user", ..)
+//! ```
+//!
+//! Iterating walks the list, so it comes out in insertion order no matter how the keys hash.
+//! Since an `Index` survives anything that happens to the other entries, moving an entry to
+//! either end is just relinking it, and the table doesn't have to hear about it.
+//!
+//! The table is keyed by the hash rather than by the key so the key is only stored once, in the
+//! list. Two keys with the same 64-bit hash are rare but possible, so the entries that share a
+//! hash are chained together through `collision`.
+
+use crate::arena_list::{self, Index};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+pub struct LinkedHashMap<K, V, S = RandomState> {
+    entries: arena_list::List<Node<K, V>>,
+    /// The newest entry for each hash.
+    by_hash: HashMap<u64, Index, BuildHasherDefault<Prehashed>>,
+    hasher: S,
+}
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    hash: u64,
+    /// The next older entry with the same hash.
+    collision: Option<Index>,
+}
+
+/// The keys of `by_hash` are hashes already.
+#[derive(Default)]
+struct Prehashed(u64);
+
+impl Hasher for Prehashed {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, _: &[u8]) {
+        unreachable!("only u64s go in here")
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+}
+
+pub enum Entry<'a, K, V, S> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S> {
+    map: &'a mut LinkedHashMap<K, V, S>,
+    index: Index,
+}
+
+pub struct VacantEntry<'a, K, V, S> {
+    map: &'a mut LinkedHashMap<K, V, S>,
+    key: K,
+    hash: u64,
+}
+
+/// Iterator over the entries, oldest first (or newest first, from the back).
+pub struct Iter<'a, K, V>(arena_list::Iter<'a, Node<K, V>>);
+
+pub struct IterMut<'a, K, V>(arena_list::IterMut<'a, Node<K, V>>);
+
+impl<K: Hash + Eq, V> LinkedHashMap<K, V, RandomState> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> LinkedHashMap<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        LinkedHashMap {
+            entries: arena_list::List::new(),
+            by_hash: HashMap::default(),
+            hasher,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn node(&self, index: Index) -> &Node<K, V> {
+        self.entries.get(index).expect("by_hash has a stale index")
+    }
+
+    fn node_mut(&mut self, index: Index) -> &mut Node<K, V> {
+        self.entries
+            .get_mut(index)
+            .expect("by_hash has a stale index")
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<Index>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut cur = self.by_hash.get(&self.hasher.hash_one(key)).copied();
+        while let Some(index) = cur {
+            let node = self.node(index);
+            if node.key.borrow() == key {
+                return Some(index);
+            }
+            cur = node.collision;
+        }
+        None
+    }
+
+    /// Takes the entry out of the list and out of its hash's chain.
+    fn remove_at(&mut self, index: Index) -> (K, V) {
+        let node = self.entries.remove(index).expect("removing a stale index");
+        if self.by_hash[&node.hash] == index {
+            match node.collision {
+                Some(older) => self.by_hash.insert(node.hash, older),
+                None => self.by_hash.remove(&node.hash),
+            };
+        } else {
+            let mut cur = self.by_hash[&node.hash];
+            while self.node(cur).collision != Some(index) {
+                cur = self
+                    .node(cur)
+                    .collision
+                    .expect("entry missing from its chain");
+            }
+            self.node_mut(cur).collision = node.collision;
+        }
+        (node.key, node.value)
+    }
+
+    /// insert() puts a new key at the back. A key that's already there keeps its place and just
+    /// gets the new value; use `move_to_back` as well to have it count as new.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).map(|index| &self.node(index).value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        Some(&mut self.node_mut(index).value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        Some(self.remove_at(index).1)
+    }
+
+    pub fn front(&self) -> Option<(&K, &V)> {
+        self.entries.front().map(|node| (&node.key, &node.value))
+    }
+
+    pub fn back(&self) -> Option<(&K, &V)> {
+        self.entries.back().map(|node| (&node.key, &node.value))
+    }
+
+    /// pop_front() removes the oldest entry.
+    pub fn pop_front(&mut self) -> Option<(K, V)> {
+        let index = self.entries.front_index()?;
+        Some(self.remove_at(index))
+    }
+
+    pub fn pop_back(&mut self) -> Option<(K, V)> {
+        let index = self.entries.back_index()?;
+        Some(self.remove_at(index))
+    }
+
+    /// move_to_back() makes `key` the newest entry, for example when it's used, so that
+    /// `pop_front` evicts the least recently used one. Returns whether `key` was there.
+    pub fn move_to_back<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => {
+                self.entries.move_to_back(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn move_to_front<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.find(key) {
+            Some(index) => {
+                self.entries.move_to_front(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.find(&key) {
+            Some(index) => Entry::Occupied(OccupiedEntry { map: self, index }),
+            None => {
+                let hash = self.hasher.hash_one(&key);
+                Entry::Vacant(VacantEntry {
+                    map: self,
+                    key,
+                    hash,
+                })
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter(self.entries.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut(self.entries.iter_mut())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> Default for LinkedHashMap<K, V, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> fmt::Debug for LinkedHashMap<K, V, S>
+where
+    K: Hash + Eq + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.map.node(self.index).key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.map.node(self.index).value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.node_mut(self.index).value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.node_mut(self.index).value
+    }
+
+    /// insert() replaces the value; the entry stays where it is.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn move_to_back(&mut self) {
+        self.map.entries.move_to_back(self.index);
+    }
+
+    pub fn move_to_front(&mut self) {
+        self.map.entries.move_to_front(self.index);
+    }
+
+    pub fn remove(self) -> V {
+        self.map.remove_at(self.index).1
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// insert() adds the entry at the back.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let map = self.map;
+        let collision = map.by_hash.get(&self.hash).copied();
+        let index = map.entries.push_back(Node {
+            key: self.key,
+            value,
+            hash: self.hash,
+            collision,
+        });
+        map.by_hash.insert(self.hash, index);
+        &mut map.node_mut(index).value
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|node| (&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|node| (&node.key, &node.value))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|node| (&node.key, &mut node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|node| (&node.key, &mut node.value))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> IntoIterator for &'a LinkedHashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> IntoIterator for &'a mut LinkedHashMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Entry, LinkedHashMap};
+    use crate::test_util::XorShift;
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    /// Gives every key the same hash, so every lookup goes through the collision chain.
+    #[derive(Default)]
+    struct Constant;
+
+    impl Hasher for Constant {
+        fn finish(&self) -> u64 {
+            7
+        }
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    fn keys<K, V, S>(map: &LinkedHashMap<K, V, S>) -> Vec<K>
+    where
+        K: std::hash::Hash + Eq + Copy,
+        S: std::hash::BuildHasher,
+    {
+        map.iter().map(|(k, _)| *k).collect()
+    }
+
+    #[test]
+    fn insertion_order() {
+        let mut config = LinkedHashMap::new();
+        config.insert("host", "localhost");
+        config.insert("port", "8080");
+        config.insert("user", "root");
+        assert_eq!(config.insert("port", "9090"), Some("8080"));
+        assert_eq!(keys(&config), ["host", "port", "user"]);
+        assert_eq!(
+            format!("{:?}", config),
+            r#"{"host": "localhost", "port": "9090", "user": "root"}"#
+        );
+
+        assert_eq!(config.get("user"), Some(&"root"));
+        assert_eq!(config.remove("host"), Some("localhost"));
+        config.insert("host", "example.com");
+        assert_eq!(keys(&config), ["port", "user", "host"]);
+        assert_eq!(config.front(), Some((&"port", &"9090")));
+        assert_eq!(config.back(), Some((&"host", &"example.com")));
+    }
+
+    #[test]
+    fn moves_and_pops() {
+        let mut map = LinkedHashMap::new();
+        assert_eq!(map.pop_front(), None);
+        assert!(!map.move_to_back(&1));
+        for i in 0..5 {
+            map.insert(i, i * 10);
+        }
+        assert!(map.move_to_back(&1));
+        assert!(map.move_to_front(&3));
+        assert_eq!(keys(&map), [3, 0, 2, 4, 1]);
+
+        assert_eq!(map.pop_front(), Some((3, 30)));
+        assert_eq!(map.pop_back(), Some((1, 10)));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&3), None);
+        assert_eq!(map.get(&2), Some(&20));
+    }
+
+    #[test]
+    fn iter_both_ways() {
+        let mut map = LinkedHashMap::new();
+        for c in "abcde".chars() {
+            map.insert(c, 0);
+        }
+        for (i, (_, v)) in map.iter_mut().rev().enumerate() {
+            *v = i;
+        }
+        let mut iter = map.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some((&'a', &4)));
+        assert_eq!(iter.next_back(), Some((&'e', &0)));
+        assert_eq!(iter.map(|(k, _)| *k).collect::<String>(), "bcd");
+    }
+
+    #[test]
+    fn entry() {
+        let mut map = LinkedHashMap::new();
+        for word in "b a b c a b".split(' ') {
+            *map.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(
+            map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            [("b", 3), ("a", 2), ("c", 1)]
+        );
+
+        match map.entry("b") {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.key(), &"b");
+                assert_eq!(entry.insert(30), 3);
+                entry.move_to_back();
+            }
+            Entry::Vacant(_) => panic!("b should be there"),
+        }
+        assert_eq!(keys(&map), ["a", "c", "b"]);
+
+        map.entry("a").and_modify(|n| *n += 1).or_default();
+        map.entry("d").and_modify(|n| *n += 1).or_default();
+        assert_eq!(map.get("a"), Some(&3));
+        assert_eq!(map.get("d"), Some(&0));
+
+        match map.entry("c") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 1),
+            Entry::Vacant(_) => panic!("c should be there"),
+        }
+        assert_eq!(keys(&map), ["a", "b", "d"]);
+    }
+
+    #[test]
+    fn colliding_hashes() {
+        let mut map: LinkedHashMap<u32, u32, BuildHasherDefault<Constant>> =
+            LinkedHashMap::default();
+        for i in 0..10 {
+            map.insert(i, i);
+        }
+        // Out of the middle, the newest, and the oldest of the chain.
+        assert_eq!(map.remove(&5), Some(5));
+        assert_eq!(map.remove(&9), Some(9));
+        assert_eq!(map.pop_front(), Some((0, 0)));
+        for i in 0..10 {
+            let expected = if [0, 5, 9].contains(&i) {
+                None
+            } else {
+                Some(&i)
+            };
+            assert_eq!(map.get(&i), expected);
+        }
+        map.insert(5, 50);
+        assert_eq!(keys(&map), [1, 2, 3, 4, 6, 7, 8, 5]);
+    }
+
+    /// Random operations, with a `Vec` of `(key, value)` in order as the model.
+    #[test]
+    fn same_as_vec() {
+        let mut ours = LinkedHashMap::new();
+        let mut model: Vec<(u64, u64)> = Vec::new();
+        let mut rng = XorShift::new();
+
+        for i in 0..10_000u64 {
+            let bits = rng.next_u64();
+            let key = (bits >> 32) % 200;
+            let pos = model.iter().position(|&(k, _)| k == key);
+            match bits % 7 {
+                0 | 1 => {
+                    let old = match pos {
+                        Some(pos) => Some(std::mem::replace(&mut model[pos].1, i)),
+                        None => {
+                            model.push((key, i));
+                            None
+                        }
+                    };
+                    assert_eq!(ours.insert(key, i), old);
+                }
+                2 => {
+                    let old = pos.map(|pos| model.remove(pos).1);
+                    assert_eq!(ours.remove(&key), old);
+                }
+                3 => {
+                    assert_eq!(ours.move_to_back(&key), pos.is_some());
+                    if let Some(pos) = pos {
+                        let entry = model.remove(pos);
+                        model.push(entry);
+                    }
+                }
+                4 => {
+                    assert_eq!(ours.move_to_front(&key), pos.is_some());
+                    if let Some(pos) = pos {
+                        let entry = model.remove(pos);
+                        model.insert(0, entry);
+                    }
+                }
+                5 => {
+                    if bits & 8 == 0 {
+                        let expected = if model.is_empty() {
+                            None
+                        } else {
+                            Some(model.remove(0))
+                        };
+                        assert_eq!(ours.pop_front(), expected);
+                    } else {
+                        assert_eq!(ours.pop_back(), model.pop());
+                    }
+                }
+                _ => assert_eq!(ours.get(&key), pos.map(|pos| &model[pos].1)),
+            }
+            assert_eq!(ours.len(), model.len());
+        }
+        assert!(ours.iter().map(|(&k, &v)| (k, v)).eq(model.iter().copied()));
+        assert!(ours
+            .iter()
+            .rev()
+            .map(|(&k, &v)| (k, v))
+            .eq(model.iter().rev().copied()));
+    }
+}
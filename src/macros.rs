@@ -0,0 +1,256 @@
+//! `vec!`-style construction macros.
+//!
+//! All of them take the elements in the order you'd read them back: front (or top) first. For the
+//! stacks that means pushing in reverse, which is exactly the part that's easy to get wrong by
+//! hand:
+//!
+//! ```ignore
+//! // the top of this stack is 3!
+//! let mut list = second::List::new();
+//! list.push(1);
+//! list.push(2);
+//! list.push(3);
+//! ```
+//!
+//! There's one per list: `int_stack!` for `first`, `stack!` for `second`, `plist!` for `third` and
+//! `deque!` for `fourth`.
+//!
+//! Each macro expands to one loop over an array (or over `iter::repeat` for `[x; n]`) rather than
+//! peeling elements off one macro call at a time, so long literals don't run into the recursion
+//! limit.
+
+/// Builds a `first::List`, the `i32`-only stack, whose top is the first element.
+///
+/// ```
+/// let mut list = lists::int_stack![1, 2, 3];
+/// assert_eq!(list.pop(), Some(1));
+///
+/// let mut zeros = lists::int_stack![0; 2];
+/// assert_eq!((zeros.pop(), zeros.pop(), zeros.pop()), (Some(0), Some(0), None));
+/// ```
+#[macro_export]
+macro_rules! int_stack {
+    () => {
+        $crate::first::List::new()
+    };
+    ($elem:expr; $n:expr) => {{
+        let mut list = $crate::first::List::new();
+        for elem in ::std::iter::repeat($elem).take($n) {
+            list.push(elem);
+        }
+        list
+    }};
+    ($($elem:expr),+ $(,)?) => {{
+        let mut list = $crate::first::List::new();
+        for elem in ::std::iter::IntoIterator::into_iter([$($elem),+]).rev() {
+            list.push(elem);
+        }
+        list
+    }};
+}
+
+/// Builds a `second::List` whose top is the first element.
+///
+/// ```
+/// let mut list = lists::stack![1, 2, 3];
+/// assert_eq!(list.pop(), Some(1));
+///
+/// let zeros = lists::stack![0; 4];
+/// assert_eq!(zeros.iter().count(), 4);
+/// ```
+#[macro_export]
+macro_rules! stack {
+    () => {
+        $crate::second::List::new()
+    };
+    ($elem:expr; $n:expr) => {{
+        let mut list = $crate::second::List::new();
+        for elem in ::std::iter::repeat($elem).take($n) {
+            list.push(elem);
+        }
+        list
+    }};
+    ($($elem:expr),+ $(,)?) => {{
+        let mut list = $crate::second::List::new();
+        for elem in ::std::iter::IntoIterator::into_iter([$($elem),+]).rev() {
+            list.push(elem);
+        }
+        list
+    }};
+}
+
+/// Builds a persistent `third::List` whose head is the first element.
+///
+/// ```
+/// let list = lists::plist![1, 2, 3];
+/// assert_eq!(list.head(), Some(&1));
+/// assert_eq!(list.tail().head(), Some(&2));
+/// ```
+#[macro_export]
+macro_rules! plist {
+    () => {
+        $crate::third::List::new()
+    };
+    ($elem:expr; $n:expr) => {{
+        let mut list = $crate::third::List::new();
+        for elem in ::std::iter::repeat($elem).take($n) {
+            list = list.append(elem);
+        }
+        list
+    }};
+    ($($elem:expr),+ $(,)?) => {{
+        let mut list = $crate::third::List::new();
+        for elem in ::std::iter::IntoIterator::into_iter([$($elem),+]).rev() {
+            list = list.append(elem);
+        }
+        list
+    }};
+}
+
+/// Builds a `fourth::List` from front to back.
+///
+/// ```
+/// let mut list = lists::deque![1, 2, 3];
+/// assert_eq!(list.pop_front(), Some(1));
+/// assert_eq!(list.pop_back(), Some(3));
+/// ```
+#[macro_export]
+macro_rules! deque {
+    () => {
+        $crate::fourth::List::new()
+    };
+    ($elem:expr; $n:expr) => {{
+        let mut list = $crate::fourth::List::new();
+        for elem in ::std::iter::repeat($elem).take($n) {
+            list.push_back(elem);
+        }
+        list
+    }};
+    ($($elem:expr),+ $(,)?) => {{
+        let mut list = $crate::fourth::List::new();
+        for elem in ::std::iter::IntoIterator::into_iter([$($elem),+]) {
+            list.push_back(elem);
+        }
+        list
+    }};
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{first, fourth, second, third};
+
+    fn drain(mut list: first::List) -> Vec<i32> {
+        std::iter::from_fn(|| list.pop()).collect()
+    }
+
+    #[test]
+    fn int_stack() {
+        let empty: first::List = int_stack![];
+        assert_eq!(drain(empty), []);
+
+        assert_eq!(drain(int_stack![1, 2, 3,]), [1, 2, 3]);
+        assert_eq!(drain(int_stack![-1; 3]), [-1, -1, -1]);
+        assert_eq!(drain(int_stack![7; 0]), []);
+    }
+
+    #[test]
+    fn stack() {
+        let empty: second::List<i32> = stack![];
+        assert_eq!(empty.peek(), None);
+
+        let list = stack![1, 2, 3,];
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let list = stack![String::from("x"); 3];
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec!["x", "x", "x"]);
+
+        let none: second::List<u8> = stack![7; 0];
+        assert_eq!(none.peek(), None);
+    }
+
+    #[test]
+    fn plist() {
+        let empty: third::List<i32> = plist![];
+        assert_eq!(empty.head(), None);
+
+        let list = plist![1, 2, 3];
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let list = plist![vec![1]; 2];
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&vec![1], &vec![1]]);
+    }
+
+    #[test]
+    fn deque() {
+        let empty: fourth::List<i32> = deque![];
+        assert!(empty.peek_front().is_none());
+
+        let list = deque![1, 2, 3];
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let list = deque!['a'; 3];
+        assert_eq!(list.into_iter().rev().collect::<String>(), "aaa");
+    }
+
+    #[test]
+    fn large_repeat() {
+        let n = 1_000_000;
+        assert_eq!(drain(int_stack![1; n]).len(), n);
+        assert_eq!(stack![1u8; n].iter().count(), n);
+        assert_eq!(plist![1u8; n].iter().count(), n);
+        assert_eq!(deque![1u8; n].into_iter().count(), n);
+    }
+
+    /// Way past the default `recursion_limit` of 128, which a munching macro would hit.
+    #[test]
+    fn large_literal() {
+        let stack = stack![
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
+            24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45,
+            46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67,
+            68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89,
+            90, 91, 92, 93, 94, 95, 96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108,
+            109, 110, 111, 112, 113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125,
+            126, 127, 128, 129, 130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142,
+            143, 144, 145, 146, 147, 148, 149, 150, 151, 152, 153, 154, 155, 156, 157, 158, 159,
+            160, 161, 162, 163, 164, 165, 166, 167, 168, 169, 170, 171, 172, 173, 174, 175, 176,
+            177, 178, 179, 180, 181, 182, 183, 184, 185, 186, 187, 188, 189, 190, 191, 192, 193,
+            194, 195, 196, 197, 198, 199, 200, 201, 202, 203, 204, 205, 206, 207, 208, 209, 210,
+            211, 212, 213, 214, 215, 216, 217, 218, 219, 220, 221, 222, 223, 224, 225, 226, 227,
+            228, 229, 230, 231, 232, 233, 234, 235, 236, 237, 238, 239, 240, 241, 242, 243, 244,
+            245, 246, 247, 248, 249, 250, 251, 252, 253, 254, 255,
+        ];
+        let expected: Vec<i32> = (0..256).collect();
+        assert_eq!(stack.iter().copied().collect::<Vec<_>>(), expected);
+
+        let plist = plist![
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
+            24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45,
+            46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67,
+            68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89,
+            90, 91, 92, 93, 94, 95, 96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108,
+            109, 110, 111, 112, 113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125,
+            126, 127, 128, 129, 130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142,
+            143, 144, 145, 146, 147, 148, 149
+        ];
+        assert_eq!(
+            plist.iter().copied().collect::<Vec<_>>(),
+            (0..150).collect::<Vec<i32>>()
+        );
+
+        let deque = deque![
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
+            24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45,
+            46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67,
+            68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89,
+            90, 91, 92, 93, 94, 95, 96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108,
+            109, 110, 111, 112, 113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125,
+            126, 127, 128, 129, 130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142,
+            143, 144, 145, 146, 147, 148, 149
+        ];
+        assert_eq!(
+            deque.into_iter().collect::<Vec<_>>(),
+            (0..150).collect::<Vec<i32>>()
+        );
+    }
+}
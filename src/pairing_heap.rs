@@ -0,0 +1,615 @@
+//! A pairing heap: a min-heap that's a tree of singly linked lists.
+//!
+//! Every node keeps its children in a list, `second`-style, through a `child` link to the first
+//! one and a `sibling` link from each child to the next. The root is the smallest element, and
+//! every node is smaller than (or equal to) its children:
+//!
+//! ```ignore
+//! root: 1
+//!       |
+//!       4 -> 2 -> 7          children of 1, newest first
+//!       |    |
+//!       9    3 -> 5          children of 4 and of 2
+//! ```
+//!
+//! Putting two heaps together is comparing their roots and making the bigger one the first child
+//! of the smaller one: O(1), which is what makes `push` and `meld` O(1) too. All the work is in
+//! `pop`, which has to turn the root's children back into one tree. Doing that in two passes,
+//! first pairing neighbours left to right and then folding the pairs right to left, is what
+//! gives the pairing heap its O(log n) amortized `pop`. Both passes here are loops, not
+//! recursion, since a heap that was only ever pushed to has a root with n - 1 children.
+//!
+//! `decrease_key` needs to unhook a node from wherever it is, so every node also has a `prev`
+//! link back to its left sibling, or to its parent if it's the first child.
+//!
+//! A handle has to know which heap its element is in, or `a.get(&handle_into_b)` would hand out
+//! a reference into `b` that only borrows `a`. Every heap has an identity token, and every handle
+//! remembers the token of the heap it was pushed onto. Melding two heaps merges their tokens,
+//! union-find style, so a handle keeps working after its heap is melded into another:
+//!
+//! ```ignore
+//! a.meld(b); a.meld(c)
+//!
+//! token(b) -> token(a) <- token(c)      handles into b and c find a's token at the root
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ptr;
+use std::rc::{Rc, Weak};
+
+pub struct PairingHeap<T> {
+    root: *mut Node<T>,
+    len: usize,
+    /// Always the root of its union-find tree.
+    id: Rc<HeapId>,
+    _marker: PhantomData<Box<Node<T>>>,
+}
+
+/// A union-find node standing for a heap, or for several heaps that have been melded together.
+struct HeapId {
+    parent: RefCell<Option<Rc<HeapId>>>,
+    rank: Cell<u32>,
+}
+
+struct Node<T> {
+    elem: T,
+    child: *mut Node<T>,
+    sibling: *mut Node<T>,
+    /// The left sibling, or the parent for a first child; null for the root.
+    prev: *mut Node<T>,
+    /// Handles hold a `Weak` to this, so they can tell when the node is gone.
+    _alive: Rc<()>,
+}
+
+/// Refers to an element pushed onto a `PairingHeap`, for `decrease_key`. It goes stale when that
+/// element is popped, and it keeps working if its heap is melded into another one.
+pub struct Handle<T> {
+    node: *mut Node<T>,
+    alive: Weak<()>,
+    /// The token of the heap it was pushed onto.
+    heap: Rc<HeapId>,
+}
+
+/// Pops everything, smallest first.
+pub struct IntoSortedIter<T: Ord>(PairingHeap<T>);
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            node: self.node,
+            alive: self.alive.clone(),
+            heap: self.heap.clone(),
+        }
+    }
+}
+
+impl HeapId {
+    fn new() -> Rc<HeapId> {
+        Rc::new(HeapId {
+            parent: RefCell::new(None),
+            rank: Cell::new(0),
+        })
+    }
+}
+
+/// The root of `id`'s tree. Everything on the way is pointed straight at it.
+fn find(id: &Rc<HeapId>) -> Rc<HeapId> {
+    let mut root = id.clone();
+    loop {
+        let parent = root.parent.borrow().clone();
+        match parent {
+            Some(parent) => root = parent,
+            None => break,
+        }
+    }
+    let mut cur = id.clone();
+    while !Rc::ptr_eq(&cur, &root) {
+        let next = cur.parent.replace(Some(root.clone())).unwrap();
+        cur = next;
+    }
+    root
+}
+
+/// Merges two roots' trees, and returns the root of the result.
+fn union(a: Rc<HeapId>, b: Rc<HeapId>) -> Rc<HeapId> {
+    let (root, child) = if a.rank.get() < b.rank.get() {
+        (b, a)
+    } else {
+        (a, b)
+    };
+    if root.rank.get() == child.rank.get() {
+        root.rank.set(root.rank.get() + 1);
+    }
+    *child.parent.borrow_mut() = Some(root.clone());
+    root
+}
+
+impl<T> Handle<T> {
+    /// The node, if it's still in a heap.
+    fn node(&self) -> Option<*mut Node<T>> {
+        if self.alive.strong_count() > 0 {
+            Some(self.node)
+        } else {
+            None
+        }
+    }
+}
+
+/// Makes the root with the bigger element the first child of the other and returns the new root.
+/// Both have to be roots: no parent and no siblings.
+unsafe fn link<T: Ord>(a: *mut Node<T>, b: *mut Node<T>) -> *mut Node<T> {
+    let (parent, child) = if (*b).elem < (*a).elem {
+        (b, a)
+    } else {
+        (a, b)
+    };
+    (*child).sibling = (*parent).child;
+    if !(*parent).child.is_null() {
+        (*(*parent).child).prev = child;
+    }
+    (*child).prev = parent;
+    (*parent).child = child;
+    parent
+}
+
+/// Detaches `node` from its siblings and parent.
+unsafe fn make_root<T>(node: *mut Node<T>) -> *mut Node<T> {
+    (*node).prev = ptr::null_mut();
+    (*node).sibling = ptr::null_mut();
+    node
+}
+
+/// The two-pass pairing: combines a sibling list, starting at `first`, into one tree.
+unsafe fn merge_pairs<T: Ord>(first: *mut Node<T>) -> *mut Node<T> {
+    // Pass one, left to right: link each pair, and stack up the results, threaded through
+    // `sibling` so nothing is allocated.
+    let mut pairs = ptr::null_mut();
+    let mut cur = first;
+    while !cur.is_null() {
+        let a = cur;
+        let b = (*a).sibling;
+        let merged = if b.is_null() {
+            cur = ptr::null_mut();
+            make_root(a)
+        } else {
+            cur = (*b).sibling;
+            link(make_root(a), make_root(b))
+        };
+        (*merged).sibling = pairs;
+        pairs = merged;
+    }
+
+    // Pass two, right to left (the stack's top is the rightmost pair): fold them all together.
+    if pairs.is_null() {
+        return pairs;
+    }
+    let mut result = pairs;
+    pairs = (*result).sibling;
+    make_root(result);
+    while !pairs.is_null() {
+        let next = (*pairs).sibling;
+        result = link(make_root(pairs), result);
+        pairs = next;
+    }
+    result
+}
+
+impl<T: Ord> PairingHeap<T> {
+    pub fn new() -> Self {
+        PairingHeap {
+            root: ptr::null_mut(),
+            len: 0,
+            id: HeapId::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// push() is O(1), and returns a handle for `decrease_key`.
+    pub fn push(&mut self, elem: T) -> Handle<T> {
+        let alive = Rc::new(());
+        let handle_alive = Rc::downgrade(&alive);
+        let node = Box::into_raw(Box::new(Node {
+            elem,
+            child: ptr::null_mut(),
+            sibling: ptr::null_mut(),
+            prev: ptr::null_mut(),
+            _alive: alive,
+        }));
+        self.root = if self.root.is_null() {
+            node
+        } else {
+            unsafe { link(self.root, node) }
+        };
+        self.len += 1;
+        Handle {
+            node,
+            alive: handle_alive,
+            heap: self.id.clone(),
+        }
+    }
+
+    /// peek() gives the smallest element.
+    pub fn peek(&self) -> Option<&T> {
+        unsafe { self.root.as_ref() }.map(|node| &node.elem)
+    }
+
+    /// pop() removes the smallest element, in O(log n) amortized.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.root.is_null() {
+            return None;
+        }
+        unsafe {
+            let root = Box::from_raw(self.root);
+            self.root = merge_pairs(root.child);
+            self.len -= 1;
+            Some(root.elem)
+        }
+    }
+
+    /// meld() moves everything from `other` into this heap in O(1), give or take the union-find.
+    /// Handles into `other` now refer to elements of this heap.
+    pub fn meld(&mut self, mut other: PairingHeap<T>) {
+        if other.root.is_null() {
+            return;
+        }
+        self.id = union(self.id.clone(), other.id.clone());
+        self.root = if self.root.is_null() {
+            other.root
+        } else {
+            unsafe { link(self.root, other.root) }
+        };
+        self.len += other.len;
+        other.root = ptr::null_mut();
+        other.len = 0;
+    }
+
+    /// The node `handle` refers to, if it's in this heap. Doesn't touch the node.
+    fn node(&self, handle: &Handle<T>) -> Option<*mut Node<T>> {
+        let node = handle.node()?;
+        if Rc::ptr_eq(&find(&handle.heap), &self.id) {
+            Some(node)
+        } else {
+            None
+        }
+    }
+
+    /// The element `handle` refers to, if it hasn't been popped. `None` for another heap's
+    /// handle.
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.node(handle).map(|node| unsafe { &(*node).elem })
+    }
+
+    pub fn contains(&self, handle: &Handle<T>) -> bool {
+        self.node(handle).is_some()
+    }
+
+    /// decrease_key() replaces the element `handle` refers to with a smaller (or equal) one, in
+    /// O(1): the node and its subtree are cut out and linked with the root.
+    ///
+    /// # Panics
+    ///
+    /// If `handle` is stale, if it's another heap's handle, or if `elem` is bigger than the
+    /// element it replaces.
+    pub fn decrease_key(&mut self, handle: &Handle<T>, elem: T) {
+        let node = handle.node().expect("decrease_key on a stale handle");
+        assert!(
+            Rc::ptr_eq(&find(&handle.heap), &self.id),
+            "decrease_key with another heap's handle"
+        );
+        unsafe {
+            assert!(
+                elem <= (*node).elem,
+                "decrease_key can't make an element bigger"
+            );
+            (*node).elem = elem;
+            if node == self.root {
+                return;
+            }
+            let prev = (*node).prev;
+
+            // Unhook it from its sibling list: `prev` is either the parent or the left sibling.
+            let sibling = (*node).sibling;
+            if (*prev).child == node {
+                (*prev).child = sibling;
+            } else {
+                (*prev).sibling = sibling;
+            }
+            if !sibling.is_null() {
+                (*sibling).prev = prev;
+            }
+            self.root = link(self.root, make_root(node));
+        }
+    }
+
+    pub fn into_sorted_iter(self) -> IntoSortedIter<T> {
+        IntoSortedIter(self)
+    }
+}
+
+impl<T: Ord> Default for PairingHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for PairingHeap<T> {
+    fn drop(&mut self) {
+        // The tree can be as deep as the heap is long, so no recursion here either.
+        let mut pending = Vec::new();
+        if !self.root.is_null() {
+            pending.push(self.root);
+        }
+        while let Some(node) = pending.pop() {
+            let node = unsafe { Box::from_raw(node) };
+            for next in [node.child, node.sibling] {
+                if !next.is_null() {
+                    pending.push(next);
+                }
+            }
+        }
+    }
+}
+
+impl<T: Ord> Iterator for IntoSortedIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for IntoSortedIter<T> {}
+
+impl<T: Ord> Extend<T> for PairingHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T: Ord> FromIterator<T> for PairingHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = PairingHeap::new();
+        heap.extend(iter);
+        heap
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PairingHeap;
+    use crate::test_util::{Drops, XorShift};
+    use std::cmp::Reverse;
+    use std::collections::{BinaryHeap, HashMap};
+
+    #[test]
+    fn basics() {
+        let mut heap = PairingHeap::new();
+        assert_eq!(heap.pop(), None);
+        assert_eq!(heap.peek(), None);
+
+        for x in [5, 1, 8, 3, 9, 2] {
+            heap.push(x);
+        }
+        assert_eq!(heap.len(), 6);
+        assert_eq!(heap.peek(), Some(&1));
+        assert_eq!(heap.pop(), Some(1));
+        assert_eq!(heap.pop(), Some(2));
+        heap.push(0);
+        assert_eq!(heap.pop(), Some(0));
+        assert_eq!(heap.into_sorted_iter().collect::<Vec<_>>(), [3, 5, 8, 9]);
+    }
+
+    #[test]
+    fn meld() {
+        let mut a: PairingHeap<_> = vec![4, 8, 6].into_iter().collect();
+        let mut b: PairingHeap<_> = vec![5, 1, 7].into_iter().collect();
+        let seven = b.push(70);
+        a.meld(b);
+        a.meld(PairingHeap::new());
+        assert_eq!(a.len(), 7);
+
+        // The handle into `b` works on `a` now.
+        a.decrease_key(&seven, 2);
+        let mut empty = PairingHeap::new();
+        empty.meld(a);
+        assert_eq!(
+            empty.into_sorted_iter().collect::<Vec<_>>(),
+            [1, 2, 4, 5, 6, 7, 8]
+        );
+    }
+
+    #[test]
+    fn decrease_key() {
+        let mut heap = PairingHeap::new();
+        let handles: Vec<_> = (10..20).map(|x| heap.push(x)).collect();
+        heap.pop();
+        heap.pop();
+
+        // Somewhere deep in the tree after the pops.
+        heap.decrease_key(&handles[7], 5);
+        assert_eq!(heap.peek(), Some(&5));
+        heap.decrease_key(&handles[7], 5);
+        heap.decrease_key(&handles[9], 6);
+        // The root itself.
+        heap.decrease_key(&handles[7], 1);
+        assert_eq!(heap.get(&handles[9]), Some(&6));
+
+        assert!(!heap.contains(&handles[0]));
+        assert_eq!(heap.get(&handles[0]), None);
+        assert_eq!(
+            heap.into_sorted_iter().collect::<Vec<_>>(),
+            [1, 6, 12, 13, 14, 15, 16, 18]
+        );
+    }
+
+    #[test]
+    fn foreign_handles() {
+        let mut a: PairingHeap<_> = vec![1, 2, 3].into_iter().collect();
+        let mut b = PairingHeap::new();
+        let b_root = b.push(0);
+        let b_deep = b.push(10);
+        b.push(20);
+
+        assert_eq!(a.get(&b_deep), None);
+        assert!(!a.contains(&b_root));
+        assert_eq!(b.get(&b_deep), Some(&10));
+
+        // After a meld, handles from either side belong to the result and nowhere else.
+        let c = PairingHeap::new();
+        let mut d = PairingHeap::new();
+        let d_handle = d.push(5);
+        a.meld(b);
+        a.meld(d);
+        assert_eq!(a.get(&b_deep), Some(&10));
+        assert_eq!(a.get(&d_handle), Some(&5));
+        assert!(!c.contains(&b_deep));
+        a.decrease_key(&b_deep, -1);
+        assert_eq!(a.peek(), Some(&-1));
+        assert_eq!(a.len(), 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "another heap's handle")]
+    fn decrease_key_in_another_heap() {
+        let mut a = PairingHeap::new();
+        let mut b = PairingHeap::new();
+        a.push(1);
+        b.push(1);
+        let deep = b.push(2);
+        b.push(3);
+        a.decrease_key(&deep, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "another heap's handle")]
+    fn decrease_key_on_another_heaps_root() {
+        let mut a = PairingHeap::new();
+        let mut b = PairingHeap::new();
+        a.push(1);
+        let root = b.push(0);
+        a.decrease_key(&root, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "stale")]
+    fn decrease_key_after_pop() {
+        let mut heap = PairingHeap::new();
+        let handle = heap.push(1);
+        heap.pop();
+        heap.decrease_key(&handle, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "bigger")]
+    fn decrease_key_upwards() {
+        let mut heap = PairingHeap::new();
+        let handle = heap.push(1);
+        heap.decrease_key(&handle, 2);
+    }
+
+    /// A root with 200_000 children, and a path 200_000 deep. Neither `pop` nor `drop` may
+    /// recurse on them.
+    #[test]
+    fn deep_heaps() {
+        let n = 200_000;
+        let mut wide: PairingHeap<_> = (0..n).collect();
+        assert_eq!(wide.pop(), Some(0));
+        assert_eq!(wide.pop(), Some(1));
+
+        // Each push is a new root with the old one as its only child.
+        let mut deep: PairingHeap<_> = (0..n).rev().collect();
+        assert_eq!(deep.peek(), Some(&0));
+        drop(deep);
+
+        deep = (0..n).rev().collect();
+        assert!(deep.into_sorted_iter().eq(0..n));
+        drop(wide);
+    }
+
+    #[test]
+    fn drops_everything_once() {
+        let drops = Drops::new();
+        let mut heap = PairingHeap::new();
+        let handles: Vec<_> = (0..50).map(|i| heap.push(drops.with(i))).collect();
+        heap.decrease_key(&handles[30], drops.with(0));
+        assert_eq!(drops.get(), 1);
+        drop(heap.pop());
+        assert_eq!(drops.get(), 2);
+        drop(heap);
+        assert_eq!(drops.get(), 51);
+        assert!(handles.iter().all(|handle| handle.node().is_none()));
+    }
+
+    /// Random pushes, pops, melds and decrease_keys against `BinaryHeap<Reverse<_>>`. The model
+    /// can't decrease a key, so it pushes the new one and skips the old one when it comes up.
+    #[test]
+    fn same_as_binary_heap() {
+        let mut ours = PairingHeap::new();
+        let mut model = BinaryHeap::new();
+        let mut handles = Vec::new();
+        // id -> the value it has now, for spotting the model's outdated entries.
+        let mut current = HashMap::new();
+        let mut rng = XorShift::new();
+
+        for id in 0..20_000u64 {
+            let bits = rng.next_u64();
+            let value = (bits >> 32) % 10_000;
+            match bits % 6 {
+                0 | 1 => {
+                    handles.push((ours.push((value, id)), id));
+                    model.push(Reverse((value, id)));
+                    current.insert(id, value);
+                }
+                2 => {
+                    let mut other = PairingHeap::new();
+                    for k in 0..3 {
+                        let id = (1 << 40) + id * 3 + k;
+                        handles.push((other.push((value + k, id)), id));
+                        model.push(Reverse((value + k, id)));
+                        current.insert(id, value + k);
+                    }
+                    ours.meld(other);
+                }
+                3 if !handles.is_empty() => {
+                    let (handle, id) = &handles[(bits >> 8) as usize % handles.len()];
+                    if let Some(&(old, _)) = ours.get(handle) {
+                        let new = old - old.min(value % 100);
+                        ours.decrease_key(handle, (new, *id));
+                        model.push(Reverse((new, *id)));
+                        current.insert(*id, new);
+                    }
+                }
+                _ => {
+                    while model
+                        .peek()
+                        .is_some_and(|Reverse((value, id))| current.get(id) != Some(value))
+                    {
+                        model.pop();
+                    }
+                    let expected = model.pop().map(|Reverse(elem)| elem);
+                    if let Some((_, id)) = expected {
+                        current.remove(&id);
+                    }
+                    assert_eq!(ours.pop(), expected);
+                }
+            }
+            assert_eq!(ours.len(), current.len());
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! A persistent min-heap: a leftist heap built out of `Rc` nodes, the way `third::List` is.
+//!
+//! Nothing is ever changed in place. `insert`, `delete_min` and `merge` each give back a new
+//! heap, and the old one stays exactly as it was, sharing every subtree the operation didn't
+//! have to touch:
+//!
+//! ```ignore
+//! v1 = [1, 5, 3]           v2 = v1.insert(4)
+//!
+//! v1 ->   1                v2 ->   1'
+//!        / \                      / \
+//!       5   3                    5   3'        5 is shared, 1 and 3 are copied
+//!                                     \
+//!                                      4
+//! ```
+//!
+//! Everything goes through `merge`. A leftist heap keeps its right spines short: every node's
+//! right child has the shorter way down to an empty spot (its *rank*), so the right spine of a
+//! heap of n elements has at most log2(n + 1) nodes. `merge` only walks right spines, so it only
+//! copies O(log n) nodes, and since each node on them has to be rebuilt with new children, `T`
+//! has to be `Clone`.
+//!
+//! The left spines, on the other hand, can be as long as the heap, so dropping is a loop like
+//! `third::List`'s rather than recursion.
+
+use std::rc::Rc;
+
+pub struct Heap<T> {
+    root: Link<T>,
+}
+
+type Link<T> = Option<Rc<Node<T>>>;
+
+struct Node<T> {
+    elem: T,
+    /// The length of the right spine from here, counting this node.
+    rank: usize,
+    len: usize,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+/// Iterator over a heap's elements, smallest first, by way of `delete_min` on a private version.
+pub struct SortedIter<T>(Heap<T>);
+
+fn rank<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.rank)
+}
+
+fn len<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.len)
+}
+
+/// A copy of `elem`'s node with new children, swapped if that keeps the shorter spine right.
+fn make_node<T>(elem: T, a: Link<T>, b: Link<T>) -> Rc<Node<T>> {
+    let (left, right) = if rank(&a) >= rank(&b) { (a, b) } else { (b, a) };
+    Rc::new(Node {
+        elem,
+        rank: rank(&right) + 1,
+        len: 1 + len(&left) + len(&right),
+        left,
+        right,
+    })
+}
+
+/// Merges two heaps, only rebuilding the nodes on their right spines. It recurses once per
+/// spine node, so at most about 2 * log2(n) deep.
+fn merge<T: Ord + Clone>(a: &Link<T>, b: &Link<T>) -> Link<T> {
+    match (a, b) {
+        (None, _) => b.clone(),
+        (_, None) => a.clone(),
+        (Some(x), Some(y)) => {
+            let (smaller, bigger) = if y.elem < x.elem { (y, x) } else { (x, y) };
+            let merged = merge(&smaller.right, &Some(bigger.clone()));
+            Some(make_node(
+                smaller.elem.clone(),
+                smaller.left.clone(),
+                merged,
+            ))
+        }
+    }
+}
+
+impl<T: Ord + Clone> Heap<T> {
+    pub fn new() -> Self {
+        Heap { root: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        len(&self.root)
+    }
+
+    /// insert() is a merge with a heap of one.
+    pub fn insert(&self, elem: T) -> Heap<T> {
+        let single = Some(make_node(elem, None, None));
+        Heap {
+            root: merge(&self.root, &single),
+        }
+    }
+
+    /// find_min() is O(1): it's at the root.
+    pub fn find_min(&self) -> Option<&T> {
+        self.root.as_ref().map(|node| &node.elem)
+    }
+
+    /// delete_min() merges the root's two subtrees. An empty heap stays empty.
+    pub fn delete_min(&self) -> Heap<T> {
+        Heap {
+            root: self
+                .root
+                .as_ref()
+                .and_then(|node| merge(&node.left, &node.right)),
+        }
+    }
+
+    pub fn merge(&self, other: &Heap<T>) -> Heap<T> {
+        Heap {
+            root: merge(&self.root, &other.root),
+        }
+    }
+
+    pub fn sorted_iter(&self) -> SortedIter<T> {
+        SortedIter(self.clone())
+    }
+}
+
+impl<T> Heap<T> {
+    /// Whether the two are the very same version, not just equal.
+    pub fn ptr_eq(&self, other: &Heap<T>) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Ord + Clone> Default for Heap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for Heap<T> {
+    fn clone(&self) -> Self {
+        Heap {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Heap<T> {
+    /// `third::List`'s destructor, with a stack instead of a single `next`, since a node has two
+    /// children. It stops at every node some other version still holds.
+    fn drop(&mut self) {
+        let mut pending: Vec<_> = self.root.take().into_iter().collect();
+        while let Some(node) = pending.pop() {
+            if let Ok(mut node) = Rc::try_unwrap(node) {
+                pending.extend(node.left.take());
+                pending.extend(node.right.take());
+            }
+        }
+    }
+}
+
+impl<T: Ord + Clone> Iterator for SortedIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let min = self.0.find_min()?.clone();
+        self.0 = self.0.delete_min();
+        Some(min)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len(), Some(self.0.len()))
+    }
+}
+
+impl<T: Ord + Clone> ExactSizeIterator for SortedIter<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::Heap;
+    use crate::test_util::XorShift;
+    use std::rc::Rc;
+
+    /// Every node's rank and length are right, and it's no bigger than its children.
+    fn check<T: Ord + Clone>(heap: &Heap<T>) {
+        let mut pending: Vec<_> = heap.root.iter().collect();
+        while let Some(node) = pending.pop() {
+            assert!(super::rank(&node.left) >= super::rank(&node.right));
+            assert_eq!(node.rank, super::rank(&node.right) + 1);
+            assert_eq!(
+                node.len,
+                1 + super::len(&node.left) + super::len(&node.right)
+            );
+            for child in node.left.iter().chain(node.right.iter()) {
+                assert!(node.elem <= child.elem);
+                pending.push(child);
+            }
+        }
+    }
+
+    #[test]
+    fn basics() {
+        let empty = Heap::new();
+        assert_eq!(empty.find_min(), None);
+        assert!(empty.delete_min().is_empty());
+
+        let heap = empty.insert(5).insert(1).insert(8).insert(3);
+        check(&heap);
+        assert_eq!(heap.len(), 4);
+        assert_eq!(heap.find_min(), Some(&1));
+        assert_eq!(heap.delete_min().find_min(), Some(&3));
+        assert_eq!(heap.sorted_iter().collect::<Vec<_>>(), [1, 3, 5, 8]);
+
+        let other = Heap::new().insert(2).insert(9);
+        let both = heap.merge(&other);
+        check(&both);
+        assert_eq!(both.sorted_iter().collect::<Vec<_>>(), [1, 2, 3, 5, 8, 9]);
+        assert!(heap.merge(&Heap::new()).ptr_eq(&heap));
+    }
+
+    #[test]
+    fn old_versions_stay_put() {
+        let mut versions = vec![Heap::new()];
+        for x in [50, 20, 80, 10, 60, 30, 70, 40] {
+            let next = versions.last().unwrap().insert(x);
+            versions.push(next);
+        }
+        let mut expected: Vec<i32> = Vec::new();
+        for (version, x) in versions.iter().zip([50, 20, 80, 10, 60, 30, 70, 40]) {
+            assert!(version.sorted_iter().eq(expected.iter().copied()));
+            expected.push(x);
+            expected.sort_unstable();
+        }
+
+        // Deleting from a middle version doesn't reach the others.
+        let v4 = &versions[4];
+        let smaller = v4.delete_min().delete_min();
+        assert_eq!(smaller.sorted_iter().collect::<Vec<_>>(), [50, 80]);
+        assert_eq!(v4.sorted_iter().collect::<Vec<_>>(), [10, 20, 50, 80]);
+        assert_eq!(versions[8].len(), 8);
+    }
+
+    #[test]
+    fn shares_what_it_can() {
+        let heap = Heap::new().insert(1).insert(2).insert(3).insert(4);
+        let root = heap.root.as_ref().unwrap();
+        let left = root.left.as_ref().unwrap().clone();
+        let before = Rc::strong_count(&left);
+
+        // Inserting something big copies the root and its right spine, not the left subtree,
+        // though the copied root may swap its children around.
+        let bigger = heap.insert(100);
+        let new_root = bigger.root.as_ref().unwrap();
+        assert!(!Rc::ptr_eq(new_root, root));
+        assert!(new_root
+            .left
+            .iter()
+            .chain(new_root.right.iter())
+            .any(|child| Rc::ptr_eq(child, &left)));
+        assert_eq!(Rc::strong_count(&left), before + 1);
+        drop(bigger);
+        assert_eq!(Rc::strong_count(&left), before);
+    }
+
+    #[test]
+    fn long_left_spines() {
+        // Each new minimum becomes the root with the old heap as its left child: a path n long.
+        let mut heap = Heap::new();
+        for x in (0..200_000).rev() {
+            heap = heap.insert(x);
+        }
+        assert_eq!(heap.find_min(), Some(&0));
+        let older = heap.delete_min();
+        drop(heap);
+        assert_eq!(older.find_min(), Some(&1));
+    }
+
+    /// Random operations on random old versions. Every version keeps a sorted `Vec` of what it
+    /// should hold, and all of them are checked at the end.
+    #[test]
+    fn many_versions() {
+        let mut versions: Vec<(Heap<u64>, Vec<u64>)> = vec![(Heap::new(), Vec::new())];
+        let mut rng = XorShift::new();
+
+        for _ in 0..3_000 {
+            let bits = rng.next_u64();
+            let (heap, model) = &versions[(bits >> 8) as usize % versions.len()];
+            let (heap, mut model) = (heap.clone(), model.clone());
+            let (heap, model) = match bits % 4 {
+                0 | 1 => {
+                    let x = (bits >> 32) % 1_000;
+                    model.push(x);
+                    model.sort_unstable();
+                    (heap.insert(x), model)
+                }
+                2 => {
+                    if !model.is_empty() {
+                        model.remove(0);
+                    }
+                    (heap.delete_min(), model)
+                }
+                _ => {
+                    let (other, other_model) = &versions[(bits >> 20) as usize % versions.len()];
+                    model.extend(other_model);
+                    model.sort_unstable();
+                    (heap.merge(other), model)
+                }
+            };
+            assert_eq!(heap.find_min(), model.first());
+            versions.push((heap, model));
+        }
+
+        for (heap, model) in &versions {
+            assert_eq!(heap.len(), model.len());
+            assert!(heap.sorted_iter().eq(model.iter().copied()));
+        }
+        check(&versions.last().unwrap().0);
+    }
+}
@@ -0,0 +1,479 @@
+//! A circular doubly linked list, for round-robin: take the current element, do a slice of work
+//! on it, move on to the next one, and after the last one comes the first one again.
+//!
+//! A ring has no ends, so there's no `head == None` or `tail == None` to special-case. The one
+//! case left is the empty ring, and a *sentinel* node takes care of that. It holds no element,
+//! it's always in the cycle, and the empty ring is just the sentinel pointing at itself:
+//!
+//! ```ignore
+//!        +--------------------------------------+
+//!        v                                      |
+//!   [sentinel] <-> [a] <-> [b] <-> [c] <-> [d] -+     current: b
+//!
+//!   [sentinel] <-+                                    current: sentinel
+//!        ^-------+
+//! ```
+//!
+//! Every node always has a real `prev` and `next`, so inserting or unlinking a node is the same
+//! four pointer writes wherever it is. Users never see the sentinel: moving the cursor steps
+//! over it, so from the outside `d` is simply followed by `a`.
+//!
+//! The nodes are raw pointers (like the ones in `concurrent`) because a cycle has no owner to
+//! hand a `Box` to, and `Rc` would need `Weak` links everywhere to not leak.
+
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+pub struct Ring<T> {
+    sentinel: *mut Node<T>,
+    /// The sentinel exactly when the ring is empty.
+    current: *mut Node<T>,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+struct Node<T> {
+    /// Uninitialized in the sentinel only.
+    elem: MaybeUninit<T>,
+    prev: *mut Node<T>,
+    next: *mut Node<T>,
+}
+
+pub struct Iter<'a, T> {
+    node: *const Node<T>,
+    sentinel: *const Node<T>,
+    remaining: usize,
+    _marker: PhantomData<&'a T>,
+}
+
+pub struct IterMut<'a, T> {
+    node: *mut Node<T>,
+    sentinel: *mut Node<T>,
+    remaining: usize,
+    _marker: PhantomData<&'a mut T>,
+}
+
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Sync> Sync for Ring<T> {}
+
+impl<T> Node<T> {
+    fn alloc(elem: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            elem,
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+        }))
+    }
+}
+
+/// Puts `node` between `prev` and `next`, which must be neighbours.
+unsafe fn link_between<T>(node: *mut Node<T>, prev: *mut Node<T>, next: *mut Node<T>) {
+    (*node).prev = prev;
+    (*node).next = next;
+    (*prev).next = node;
+    (*next).prev = node;
+}
+
+/// Takes `node` out of its cycle and leaves it pointing at itself.
+unsafe fn unlink<T>(node: *mut Node<T>) {
+    (*(*node).prev).next = (*node).next;
+    (*(*node).next).prev = (*node).prev;
+    (*node).prev = node;
+    (*node).next = node;
+}
+
+impl<T> Ring<T> {
+    pub fn new() -> Self {
+        let sentinel = Node::alloc(MaybeUninit::uninit());
+        unsafe {
+            (*sentinel).prev = sentinel;
+            (*sentinel).next = sentinel;
+        }
+        Ring {
+            sentinel,
+            current: sentinel,
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn current(&self) -> Option<&T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(unsafe { &*(*self.current).elem.as_ptr() })
+        }
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(unsafe { &mut *(*self.current).elem.as_mut_ptr() })
+        }
+    }
+
+    /// rotate_forward() makes the next element current. On an empty ring it does nothing, and on
+    /// a one-element ring it comes straight back to the same element.
+    pub fn rotate_forward(&mut self) {
+        unsafe {
+            self.current = (*self.current).next;
+            if self.current == self.sentinel {
+                self.current = (*self.current).next;
+            }
+        }
+    }
+
+    pub fn rotate_backward(&mut self) {
+        unsafe {
+            self.current = (*self.current).prev;
+            if self.current == self.sentinel {
+                self.current = (*self.current).prev;
+            }
+        }
+    }
+
+    /// insert_after_current() puts `elem` right after the current element, so it comes up on the
+    /// next `rotate_forward`. In an empty ring it becomes the current element.
+    pub fn insert_after_current(&mut self, elem: T) {
+        let node = Node::alloc(MaybeUninit::new(elem));
+        unsafe { link_between(node, self.current, (*self.current).next) };
+        if self.is_empty() {
+            self.current = node;
+        }
+        self.len += 1;
+    }
+
+    /// remove_current() takes the current element out; the one after it becomes current.
+    pub fn remove_current(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let node = self.current;
+        self.rotate_forward();
+        self.len -= 1;
+        if self.is_empty() {
+            self.current = self.sentinel;
+        }
+        unsafe {
+            unlink(node);
+            let node = Box::from_raw(node);
+            Some(node.elem.as_ptr().read())
+        }
+    }
+
+    /// split_ring() moves `n` elements, starting with the current one and going forward, out into
+    /// a ring of their own, whose current element is the old current one. What's left keeps its
+    /// order, with the element after the ones that moved as current.
+    ///
+    /// Walking to the end of the run is O(n); cutting it out is O(1).
+    ///
+    /// # Panics
+    ///
+    /// If `n` is more than `len`.
+    pub fn split_ring(&mut self, n: usize) -> Ring<T> {
+        assert!(
+            n <= self.len,
+            "can't split {} elements off a ring of {}",
+            n,
+            self.len
+        );
+        let mut other = Ring::new();
+        if n == 0 {
+            return other;
+        }
+        let first = self.current;
+        unsafe {
+            // Without the sentinel the elements are one plain cycle, and the run we want is
+            // `first..=last` in it.
+            unlink(self.sentinel);
+            let mut last = first;
+            for _ in 1..n {
+                last = (*last).next;
+            }
+            let rest = (*last).next;
+
+            if n == self.len {
+                self.current = self.sentinel;
+            } else {
+                // Close the remaining cycle over the gap, with our sentinel where the run was.
+                link_between(self.sentinel, (*first).prev, rest);
+                self.current = rest;
+            }
+            // And close the run into a cycle around the new sentinel.
+            (*other.sentinel).prev = last;
+            (*other.sentinel).next = first;
+            (*first).prev = other.sentinel;
+            (*last).next = other.sentinel;
+        }
+        other.current = first;
+        other.len = n;
+        self.len -= n;
+        other
+    }
+
+    /// merge_ring() splices all of `other`'s elements in right after the current element, in
+    /// `other`'s order starting from its current element. O(1).
+    pub fn merge_ring(&mut self, mut other: Ring<T>) {
+        if other.is_empty() {
+            return;
+        }
+        unsafe {
+            // Turn `other` into a plain cycle that starts at its current element...
+            unlink(other.sentinel);
+            let first = other.current;
+            let last = (*first).prev;
+            // ...and open it up between our current element and the one after it.
+            let after = (*self.current).next;
+            (*self.current).next = first;
+            (*first).prev = self.current;
+            (*last).next = after;
+            (*after).prev = last;
+        }
+        if self.is_empty() {
+            self.current = other.current;
+        }
+        self.len += other.len;
+        // `other` is just its sentinel now, which its `Drop` frees.
+        other.current = other.sentinel;
+        other.len = 0;
+    }
+
+    /// iter() starts at the current element and goes forward around the ring, stopping once it's
+    /// seen every element exactly once.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            node: self.current,
+            sentinel: self.sentinel,
+            remaining: self.len,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            node: self.current,
+            sentinel: self.sentinel,
+            remaining: self.len,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for Ring<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        while self.remove_current().is_some() {}
+        // The sentinel's element was never initialized, so there's nothing to drop in it.
+        drop(unsafe { Box::from_raw(self.sentinel) });
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        unsafe {
+            let elem = &*(*self.node).elem.as_ptr();
+            self.node = (*self.node).next;
+            if self.node == self.sentinel {
+                self.node = (*self.node).next;
+            }
+            Some(elem)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        unsafe {
+            let elem = &mut *(*self.node).elem.as_mut_ptr();
+            self.node = (*self.node).next;
+            if self.node == self.sentinel {
+                self.node = (*self.node).next;
+            }
+            Some(elem)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::Ring;
+    use crate::test_util::Drops;
+
+    fn ring_of(elems: &[i32]) -> Ring<i32> {
+        let mut ring = Ring::new();
+        for &elem in elems {
+            ring.insert_after_current(elem);
+            ring.rotate_forward();
+        }
+        // Back round to the first one.
+        ring.rotate_forward();
+        ring
+    }
+
+    fn contents(ring: &Ring<i32>) -> Vec<i32> {
+        ring.iter().copied().collect()
+    }
+
+    #[test]
+    fn empty() {
+        let mut ring = Ring::<i32>::new();
+        assert!(ring.is_empty());
+        assert_eq!(ring.current(), None);
+        assert_eq!(ring.current_mut(), None);
+        ring.rotate_forward();
+        ring.rotate_backward();
+        assert_eq!(ring.remove_current(), None);
+        assert_eq!(ring.iter().next(), None);
+        assert_eq!(ring.iter_mut().next(), None);
+
+        let split = ring.split_ring(0);
+        assert!(split.is_empty());
+        ring.merge_ring(Ring::new());
+        assert!(ring.is_empty());
+        assert_eq!(ring.current(), None);
+    }
+
+    #[test]
+    fn single_element() {
+        let mut ring = Ring::new();
+        ring.insert_after_current(7);
+        assert_eq!(ring.current(), Some(&7));
+        ring.rotate_forward();
+        assert_eq!(ring.current(), Some(&7));
+        ring.rotate_backward();
+        ring.rotate_backward();
+        assert_eq!(ring.current(), Some(&7));
+        assert_eq!(contents(&ring), [7]);
+
+        *ring.current_mut().unwrap() = 8;
+        assert_eq!(ring.remove_current(), Some(8));
+        assert!(ring.is_empty());
+        assert_eq!(ring.current(), None);
+
+        // And it still works after going back to empty.
+        ring.insert_after_current(9);
+        assert_eq!(ring.current(), Some(&9));
+        let split = ring.split_ring(1);
+        assert!(ring.is_empty());
+        assert_eq!(contents(&split), [9]);
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut ring = ring_of(&[1, 2, 3, 4]);
+        assert_eq!(contents(&ring), [1, 2, 3, 4]);
+
+        let mut seen = Vec::new();
+        for _ in 0..10 {
+            seen.push(*ring.current().unwrap());
+            ring.rotate_forward();
+        }
+        assert_eq!(seen, [1, 2, 3, 4, 1, 2, 3, 4, 1, 2]);
+        assert_eq!(contents(&ring), [3, 4, 1, 2]);
+
+        ring.rotate_backward();
+        ring.rotate_backward();
+        ring.rotate_backward();
+        assert_eq!(ring.current(), Some(&4));
+
+        ring.insert_after_current(5);
+        assert_eq!(contents(&ring), [4, 5, 1, 2, 3]);
+        assert_eq!(ring.remove_current(), Some(4));
+        assert_eq!(contents(&ring), [5, 1, 2, 3]);
+
+        for elem in ring.iter_mut() {
+            *elem *= 10;
+        }
+        assert_eq!(contents(&ring), [50, 10, 20, 30]);
+        assert_eq!(ring.iter().len(), 4);
+    }
+
+    #[test]
+    fn split_and_merge() {
+        let mut ring = ring_of(&[1, 2, 3, 4, 5, 6]);
+        // Start the run near the end so it has to wrap around past the sentinel.
+        for _ in 0..4 {
+            ring.rotate_forward();
+        }
+        let split = ring.split_ring(3);
+        assert_eq!(contents(&split), [5, 6, 1]);
+        assert_eq!(contents(&ring), [2, 3, 4]);
+        assert_eq!((ring.len(), split.len()), (3, 3));
+
+        ring.rotate_backward();
+        ring.merge_ring(split);
+        assert_eq!(contents(&ring), [4, 5, 6, 1, 2, 3]);
+        assert_eq!(ring.len(), 6);
+
+        let all = ring.split_ring(6);
+        assert!(ring.is_empty());
+        assert_eq!(contents(&all), [4, 5, 6, 1, 2, 3]);
+        ring.merge_ring(all);
+        assert_eq!(contents(&ring), [4, 5, 6, 1, 2, 3]);
+        assert_eq!(ring.remove_current(), Some(4));
+        ring.rotate_backward();
+        assert_eq!(ring.current(), Some(&3));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't split")]
+    fn split_too_many() {
+        ring_of(&[1, 2]).split_ring(3);
+    }
+
+    #[test]
+    fn drops_everything_once() {
+        let drops = Drops::new();
+        let mut ring = Ring::new();
+        for _ in 0..10 {
+            ring.insert_after_current(drops.counted());
+        }
+        drop(ring.remove_current());
+        assert_eq!(drops.get(), 1);
+
+        let split = ring.split_ring(4);
+        drop(ring);
+        assert_eq!(drops.get(), 6);
+        drop(split);
+        assert_eq!(drops.get(), 10);
+    }
+}
@@ -16,7 +16,7 @@ pub struct List<T> {
 /// ```
 type Link<T> = Option<Box<Node<T>>>;
 
-struct Node<T> {
+pub(crate) struct Node<T> {
     elem: T,
     next: Link<T>,
 }
@@ -63,10 +63,19 @@ pub struct IterMut<'a, T: 'a> {
     next: Option<&'a mut Node<T>>,
 }
 
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
 impl<T> Iterator for IntoIter<T> {
     type Item = T;
     fn next(&mut self) -> Option<Self::Item> {
-        return self.0.pop();
+        self.0.pop()
     }
 }
 
@@ -94,7 +103,7 @@ impl<T> List<T> {
     /// ```
     pub fn push(&mut self, elem: T) {
         let new_node = Box::new(Node {
-            elem: elem,
+            elem,
             next: self.head.take(),
         });
 
@@ -159,16 +168,12 @@ impl<T> List<T> {
 
     /// iter_mut()
     ///
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut {
-            next: self.head.as_mut().map(|node| &mut **node),
+            next: self.head.as_deref_mut(),
         }
     }
 
-    pub fn into_iter(self) -> IntoIter<T> {
-        return IntoIter(self);
-    }
-
     /// Note that this is syntax sugar for
     /// ```ignore
     /// pub fn iter<'a>(&'a self) -> Iter<'a, T> {
@@ -178,13 +183,77 @@ impl<T> List<T> {
     // We declare a fresh lifetime here for the *exact* borrow that
     // creates the iter. Now &self needs to be valid as long as the
     // Iter is around.
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter {
-            next: self.head.as_ref().map(|node| &**node),
+            next: self.head.as_deref(),
         }
     }
 }
 
+/// Node-level operations for other lists in the crate that move boxes around instead of
+/// elements, like the buckets of `chained_map::HashMap` when it grows.
+impl<T> List<T> {
+    /// Unhooks the top node, box and all.
+    pub(crate) fn pop_node(&mut self) -> Option<Box<Node<T>>> {
+        self.head.take().map(|mut node| {
+            self.head = node.next.take();
+            node
+        })
+    }
+
+    /// Hooks a node popped from some list onto this one. Nothing is allocated.
+    pub(crate) fn push_node(&mut self, mut node: Box<Node<T>>) {
+        node.next = self.head.take();
+        self.head = Some(node);
+    }
+
+    /// Unlinks the first element `pred` accepts, wherever it is in the list.
+    pub(crate) fn remove_first<F>(&mut self, pred: F) -> Option<T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.unlink_first(pred).map(|(_, node)| node.elem)
+    }
+
+    /// Unhooks the first node `pred` accepts, and tells how many nodes were in front of it.
+    pub(crate) fn unlink_first<F>(&mut self, mut pred: F) -> Option<(usize, Box<Node<T>>)>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut link = &mut self.head;
+        let mut index = 0;
+        // Step along the links until the one pointing at a match (or at nothing).
+        while link.as_ref().is_some_and(|node| !pred(&node.elem)) {
+            link = &mut link.as_mut().unwrap().next;
+            index += 1;
+        }
+        let mut node = link.take()?;
+        *link = node.next.take();
+        Some((index, node))
+    }
+
+    /// Hooks a node in so that `index` nodes come before it. Panics if the list is shorter than
+    /// that, like `Vec::insert`.
+    pub(crate) fn insert_node(&mut self, index: usize, mut node: Box<Node<T>>) {
+        let mut link = &mut self.head;
+        for _ in 0..index {
+            link = &mut link.as_mut().expect("index out of bounds").next;
+        }
+        node.next = link.take();
+        *link = Some(node);
+    }
+}
+
+impl<T> Node<T> {
+    pub(crate) fn elem(&self) -> &T {
+        &self.elem
+    }
+
+    pub(crate) fn elem_mut(&mut self) -> &mut T {
+        &mut self.elem
+    }
+}
+
 /// ```ignore
 /// impl Drop for List {
 ///     fn drop(&mut self) {
@@ -225,13 +294,17 @@ impl<'a, T> Iterator for Iter<'a, T> {
     // Self continues to be incredibly hype and amazing
     fn next(&mut self) -> Option<Self::Item> {
         self.next.map(|node| {
-            self.next = node.next.as_ref().map(|node| &**node);
+            self.next = node.next.as_deref();
             &node.elem
         })
     }
 }
 
-impl<T> List<T> {}
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 // *Do* have a lifetime here, because Iter does have an associated lifetime
 impl<'a, T> Iterator for IterMut<'a, T> {
@@ -242,7 +315,7 @@ impl<'a, T> Iterator for IterMut<'a, T> {
     // Self continues to be incredibly hype and amazing
     fn next(&mut self) -> Option<Self::Item> {
         self.next.take().map(|node| {
-            self.next = node.next.as_mut().map(|node| &mut **node);
+            self.next = node.next.as_deref_mut();
             &mut node.elem
         })
     }
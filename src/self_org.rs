@@ -0,0 +1,366 @@
+//! A self-organizing list: a `second::List` that moves what you look up towards the front.
+//!
+//! Searching a list costs one comparison per node in front of the match. If some elements are
+//! looked up far more often than others (symbol tables, caches, dictionaries of English words),
+//! it pays to keep those near the head. We don't know the frequencies up front, so every
+//! successful `find` nudges the match forward, according to a `Policy`:
+//!
+//! ```ignore
+//! a -> b -> c -> d         find(c)
+//!
+//! MoveToFront:  c -> a -> b -> d
+//! Transpose:    a -> c -> b -> d
+//! Count:        c's hit count goes up, and it moves in front of everything found as often or
+//!               less, so the list stays sorted by count
+//! ```
+//!
+//! Move-to-front adapts fastest, and is never worse than twice the best fixed order. Transpose
+//! is more cautious: an element that's looked up once doesn't jump over everything else. Count
+//! converges on the best fixed order for a steady workload, but it's slow to forget.
+//!
+//! Nodes are moved box and all, nothing is reallocated. `stats` counts how deep the lookups had
+//! to go, so a policy can be judged against the list you'd have without one.
+
+use crate::second;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    MoveToFront,
+    Transpose,
+    Count,
+}
+
+/// What the lookups cost so far. A hit on the n-th node costs n comparisons; a miss costs one
+/// per node in the list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AccessStats {
+    pub finds: u64,
+    pub misses: u64,
+    pub comparisons: u64,
+}
+
+pub struct List<T> {
+    entries: second::List<Entry<T>>,
+    len: usize,
+    policy: Policy,
+    stats: AccessStats,
+}
+
+struct Entry<T> {
+    elem: T,
+    /// Only kept up to date under `Policy::Count`.
+    hits: u64,
+}
+
+pub struct Iter<'a, T: 'a>(second::Iter<'a, Entry<T>>);
+
+impl AccessStats {
+    /// The average number of comparisons per `find`, misses included. 0 before any lookups.
+    pub fn mean_depth(&self) -> f64 {
+        if self.finds == 0 {
+            0.0
+        } else {
+            self.comparisons as f64 / self.finds as f64
+        }
+    }
+}
+
+impl<T> List<T> {
+    pub fn new(policy: Policy) -> Self {
+        List {
+            entries: second::List::new(),
+            len: 0,
+            policy,
+            stats: AccessStats::default(),
+        }
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn stats(&self) -> AccessStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = AccessStats::default();
+    }
+
+    /// insert() adds an element as if it had just been found: at the front, except under
+    /// `Count`, where it goes behind everything that has been found at least once.
+    pub fn insert(&mut self, elem: T) {
+        let index = match self.policy {
+            Policy::Count => self.entries.iter().take_while(|e| e.hits > 0).count(),
+            Policy::MoveToFront | Policy::Transpose => 0,
+        };
+        self.entries.push(Entry { elem, hits: 0 });
+        let node = self.entries.pop_node().unwrap();
+        self.entries.insert_node(index, node);
+        self.len += 1;
+    }
+
+    /// find() looks for the first element `pred` accepts, moves it according to the policy, and
+    /// hands it back. The move happens before the element is returned, so mutating it through
+    /// the reference is fine.
+    pub fn find<F>(&mut self, mut pred: F) -> Option<&mut T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.stats.finds += 1;
+        let (index, mut node) = match self.entries.unlink_first(|e| pred(&e.elem)) {
+            Some(found) => found,
+            None => {
+                self.stats.misses += 1;
+                self.stats.comparisons += self.len as u64;
+                return None;
+            }
+        };
+        self.stats.comparisons += index as u64 + 1;
+
+        let to = match self.policy {
+            Policy::MoveToFront => 0,
+            Policy::Transpose => index.saturating_sub(1),
+            Policy::Count => {
+                node.elem_mut().hits += 1;
+                let hits = node.elem().hits;
+                // The list is sorted by hits, and only this one changed, so everything that
+                // should stay in front of it is at the top.
+                self.entries.iter().take_while(|e| e.hits > hits).count()
+            }
+        };
+        self.entries.insert_node(to, node);
+        self.entries.iter_mut().nth(to).map(|e| &mut e.elem)
+    }
+
+    /// remove() takes out the first element `pred` accepts. It doesn't count as a lookup.
+    pub fn remove<F>(&mut self, mut pred: F) -> Option<T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let entry = self.entries.remove_first(|e| pred(&e.elem))?;
+        self.len -= 1;
+        Some(entry.elem)
+    }
+
+    /// iter() goes from the front, i.e. roughly from most to least popular. Looking doesn't move
+    /// anything.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self.entries.iter())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|e| &e.elem)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{List, Policy};
+    use crate::test_util::XorShift;
+
+    const POLICIES: [Policy; 3] = [Policy::MoveToFront, Policy::Transpose, Policy::Count];
+
+    fn letters(policy: Policy) -> List<char> {
+        let mut list = List::new(policy);
+        for c in "dcba".chars() {
+            list.insert(c);
+        }
+        list
+    }
+
+    fn order(list: &List<char>) -> String {
+        list.iter().collect()
+    }
+
+    #[test]
+    fn move_to_front() {
+        let mut list = letters(Policy::MoveToFront);
+        assert_eq!(order(&list), "abcd");
+        assert_eq!(list.find(|&c| c == 'c'), Some(&mut 'c'));
+        assert_eq!(order(&list), "cabd");
+        list.find(|&c| c == 'd');
+        assert_eq!(order(&list), "dcab");
+        list.find(|&c| c == 'd');
+        assert_eq!(order(&list), "dcab");
+    }
+
+    #[test]
+    fn transpose() {
+        let mut list = letters(Policy::Transpose);
+        list.find(|&c| c == 'c');
+        assert_eq!(order(&list), "acbd");
+        list.find(|&c| c == 'c');
+        assert_eq!(order(&list), "cabd");
+        list.find(|&c| c == 'c');
+        assert_eq!(order(&list), "cabd");
+    }
+
+    #[test]
+    fn count() {
+        let mut list = letters(Policy::Count);
+        list.find(|&c| c == 'c');
+        list.find(|&c| c == 'd');
+        assert_eq!(order(&list), "dcab");
+        list.find(|&c| c == 'c');
+        assert_eq!(order(&list), "cdab");
+        list.find(|&c| c == 'b');
+        assert_eq!(order(&list), "cbda");
+
+        // New elements go behind everything that's been found.
+        list.insert('e');
+        assert_eq!(order(&list), "cbdea");
+    }
+
+    #[test]
+    fn misses_remove_and_stats() {
+        for &policy in &POLICIES {
+            let mut list = letters(policy);
+            assert_eq!(list.find(|&c| c == 'z'), None);
+            assert_eq!(order(&list), "abcd");
+            list.find(|&c| c == 'b');
+
+            let stats = list.stats();
+            assert_eq!(
+                (stats.finds, stats.misses, stats.comparisons),
+                (2, 1, 4 + 2)
+            );
+            assert_eq!(stats.mean_depth(), 3.0);
+
+            assert_eq!(list.remove(|&c| c == 'a'), Some('a'));
+            assert_eq!(list.remove(|&c| c == 'a'), None);
+            assert_eq!(list.len(), 3);
+            assert_eq!(list.stats().finds, 2);
+
+            list.reset_stats();
+            assert_eq!(list.stats().mean_depth(), 0.0);
+        }
+    }
+
+    #[test]
+    fn find_hands_out_the_element() {
+        let mut list = List::new(Policy::Transpose);
+        list.insert(("x", 1));
+        list.insert(("y", 2));
+        list.find(|&(name, _)| name == "x").unwrap().1 += 10;
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            [("x", 11), ("y", 2)]
+        );
+    }
+
+    const KEYS: u64 = 64;
+    const LOOKUPS: usize = 20_000;
+
+    /// A reproducible stream of keys. `skewed` makes key k come up with probability about
+    /// 2^-(k + 1), otherwise every key is equally likely.
+    fn workload(skewed: bool) -> Vec<u64> {
+        let mut rng = XorShift::new();
+        (0..LOOKUPS)
+            .map(|_| {
+                let bits = rng.next_u64();
+                if skewed {
+                    u64::from(bits.trailing_zeros()).min(KEYS - 1)
+                } else {
+                    bits % KEYS
+                }
+            })
+            .collect()
+    }
+
+    /// Inserts every key, smallest first, so the popular ones start out at the back, then runs
+    /// the workload.
+    fn run(policy: Policy, keys: &[u64]) -> List<u64> {
+        let mut list = List::new(policy);
+        for key in 0..KEYS {
+            list.insert(key);
+        }
+        for &key in keys {
+            assert_eq!(list.find(|&k| k == key).copied(), Some(key));
+        }
+        list
+    }
+
+    /// The same lookups on a list that never moves: key k stays at depth KEYS - k.
+    fn static_depth(keys: &[u64]) -> f64 {
+        keys.iter().map(|&k| (KEYS - k) as f64).sum::<f64>() / keys.len() as f64
+    }
+
+    #[test]
+    fn skewed_lookups_get_cheaper() {
+        let keys = workload(true);
+        let baseline = static_depth(&keys);
+        assert!(baseline > 60.0);
+
+        for &policy in &POLICIES {
+            let list = run(policy, &keys);
+            let depth = list.stats().mean_depth();
+            assert!(
+                depth * 10.0 < baseline,
+                "{:?}: {} vs {}",
+                policy,
+                depth,
+                baseline
+            );
+            // The two most popular keys have made it to the front, in some order.
+            let mut front: Vec<u64> = list.iter().take(2).copied().collect();
+            front.sort_unstable();
+            assert_eq!(front, [0, 1]);
+        }
+
+        // Counting converges on the best order for a steady workload: most popular first.
+        let counted = run(Policy::Count, &keys);
+        let best: f64 = keys.iter().map(|&k| (k + 1) as f64).sum::<f64>() / keys.len() as f64;
+        assert!(counted.stats().mean_depth() < best * 1.1);
+    }
+
+    #[test]
+    fn uniform_lookups_cost_the_same() {
+        let keys = workload(false);
+        let baseline = static_depth(&keys);
+        for &policy in &POLICIES {
+            let depth = run(policy, &keys).stats().mean_depth();
+            assert!((depth - baseline).abs() < baseline * 0.1, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        for &skewed in &[true, false] {
+            let keys = workload(skewed);
+            for &policy in &POLICIES {
+                let (a, b) = (run(policy, &keys), run(policy, &keys));
+                assert_eq!(a.stats(), b.stats());
+                assert!(a.iter().eq(b.iter()));
+            }
+        }
+    }
+}
@@ -0,0 +1,673 @@
+//! A skip list: a sorted singly linked list with express lanes.
+//!
+//! The bottom level is a plain sorted list of nodes, one `next` each, just like `second::List`,
+//! and it alone decides what's in the map and in what order. Searching it would be O(n), so some
+//! nodes also sit in higher levels that skip over everything in between:
+//!
+//! ```ignore
+//! level 2: head ------------------------> 30 ---------------------------> nil
+//! level 1: head ------> 10 -------------> 30 ------> 50 ----------------> nil
+//! level 0: head -> 5 -> 10 -> 20 -> 25 -> 30 -> 40 -> 50 -> 60 -> 70 ---> nil
+//! ```
+//!
+//! A search starts at the top and moves right while the next key is still smaller, then drops a
+//! level. Each node's height is picked at random when it's inserted: it goes up one more level
+//! with probability 1/4. That keeps every level about a quarter the size of the one below, which
+//! makes searches O(log n) on average with no rebalancing at all.
+//!
+//! The random numbers come from a small seeded generator, so the same seed and the same
+//! operations always build the same towers. Range scans just walk the bottom level.
+
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::ptr;
+
+/// Enough for 4^16 elements at p = 1/4.
+const MAX_LEVEL: usize = 16;
+
+const DEFAULT_SEED: u64 = 0x853C_49E6_748F_EA9B;
+
+pub struct SkipMap<K, V> {
+    /// The head's tower: the first node on each level.
+    head: [*mut Node<K, V>; MAX_LEVEL],
+    /// How many levels are in use.
+    level: usize,
+    len: usize,
+    rng: u64,
+    _marker: PhantomData<Box<Node<K, V>>>,
+}
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    /// Level 0, the list that owns the nodes.
+    next: *mut Node<K, V>,
+    /// Levels 1 and up; its length is the node's height minus one.
+    tower: Box<[*mut Node<K, V>]>,
+}
+
+/// An ordered set on top of `SkipMap`.
+pub struct SkipSet<K> {
+    map: SkipMap<K, ()>,
+}
+
+/// Iterator over a slice of the map, in key order.
+pub struct Range<'a, K, V> {
+    node: *const Node<K, V>,
+    /// The first node *not* in the range, or null.
+    end: *const Node<K, V>,
+    _marker: PhantomData<&'a Node<K, V>>,
+}
+
+pub struct SetRange<'a, K>(Range<'a, K, ()>);
+
+unsafe impl<K: Send, V: Send> Send for SkipMap<K, V> {}
+unsafe impl<K: Sync, V: Sync> Sync for SkipMap<K, V> {}
+
+impl<K, V> Node<K, V> {
+    fn height(&self) -> usize {
+        self.tower.len() + 1
+    }
+
+    fn link(&self, level: usize) -> *mut Node<K, V> {
+        if level == 0 {
+            self.next
+        } else {
+            self.tower[level - 1]
+        }
+    }
+}
+
+impl<K: Ord, V> SkipMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_seed(DEFAULT_SEED)
+    }
+
+    /// Two maps with the same seed that see the same operations have the same shape.
+    pub fn with_seed(seed: u64) -> Self {
+        SkipMap {
+            head: [ptr::null_mut(); MAX_LEVEL],
+            level: 1,
+            len: 0,
+            // xorshift gets stuck at 0.
+            rng: seed | 1,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 1, plus one more for every time a 1-in-4 chance comes up.
+    fn random_height(&mut self) -> usize {
+        // xorshift64*
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let bits = self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (1 + bits.trailing_zeros() as usize / 2).min(MAX_LEVEL)
+    }
+
+    /// The link after `pred` on `level`, where a null `pred` means the head.
+    fn link_after(&self, pred: *mut Node<K, V>, level: usize) -> *mut Node<K, V> {
+        match unsafe { pred.as_ref() } {
+            Some(pred) => pred.link(level),
+            None => self.head[level],
+        }
+    }
+
+    /// Where that link is stored, to change it.
+    fn link_slot(&mut self, pred: *mut Node<K, V>, level: usize) -> &mut *mut Node<K, V> {
+        match unsafe { pred.as_mut() } {
+            Some(pred) if level == 0 => &mut pred.next,
+            Some(pred) => &mut pred.tower[level - 1],
+            None => &mut self.head[level],
+        }
+    }
+
+    /// The search: on every level, the last node whose key is `before` the target (null for the
+    /// head). Returns those, and the first node at level 0 that isn't before it.
+    fn seek<F>(&self, before: F) -> ([*mut Node<K, V>; MAX_LEVEL], *mut Node<K, V>)
+    where
+        F: Fn(&K) -> bool,
+    {
+        let mut preds = [ptr::null_mut(); MAX_LEVEL];
+        let mut pred = ptr::null_mut();
+        for level in (0..self.level).rev() {
+            loop {
+                let next = self.link_after(pred, level);
+                match unsafe { next.as_ref() } {
+                    Some(node) if before(&node.key) => pred = next,
+                    _ => break,
+                }
+            }
+            preds[level] = pred;
+        }
+        (preds, self.link_after(pred, 0))
+    }
+
+    /// The node holding `key`, or null. The pointer comes straight from the links, so
+    /// `get_mut` may write through it.
+    fn find_ptr<Q>(&self, key: &Q) -> *mut Node<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (_, node) = self.seek(|k| k.borrow() < key);
+        if !node.is_null() && unsafe { (*node).key.borrow() } == key {
+            node
+        } else {
+            ptr::null_mut()
+        }
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<&Node<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        unsafe { self.find_ptr(key).as_ref() }
+    }
+
+    /// insert() returns the old value if `key` was already there. The key itself is kept, like
+    /// `BTreeMap` does.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (mut preds, found) = self.seek(|k| *k < key);
+        if let Some(node) = unsafe { found.as_mut() } {
+            if node.key == key {
+                return Some(std::mem::replace(&mut node.value, value));
+            }
+        }
+
+        let height = self.random_height();
+        // New levels start at the head, which `preds` already says (null).
+        self.level = self.level.max(height);
+        let node = Box::into_raw(Box::new(Node {
+            key,
+            value,
+            next: ptr::null_mut(),
+            tower: vec![ptr::null_mut(); height - 1].into_boxed_slice(),
+        }));
+        for (level, pred) in preds.iter_mut().enumerate().take(height) {
+            let slot = self.link_slot(*pred, level);
+            let next = std::mem::replace(slot, node);
+            unsafe {
+                if level == 0 {
+                    (*node).next = next;
+                } else {
+                    (*node).tower[level - 1] = next;
+                }
+            }
+        }
+        self.len += 1;
+        None
+    }
+
+    /// Unlinks `node`, whose predecessors on each of its levels are `preds`, and frees it.
+    fn unlink(&mut self, preds: &[*mut Node<K, V>; MAX_LEVEL], node: *mut Node<K, V>) -> (K, V) {
+        let height = unsafe { (*node).height() };
+        for (level, &pred) in preds.iter().enumerate().take(height) {
+            let next = unsafe { (*node).link(level) };
+            *self.link_slot(pred, level) = next;
+        }
+        while self.level > 1 && self.head[self.level - 1].is_null() {
+            self.level -= 1;
+        }
+        self.len -= 1;
+        let node = unsafe { Box::from_raw(node) };
+        (node.key, node.value)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (preds, found) = self.seek(|k| k.borrow() < key);
+        match unsafe { found.as_ref() } {
+            Some(node) if node.key.borrow() == key => Some(self.unlink(&preds, found)),
+            _ => None,
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key).map(|node| &node.value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let node = self.find_ptr(key);
+        if node.is_null() {
+            None
+        } else {
+            Some(unsafe { &mut (*node).value })
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        unsafe { self.head[0].as_ref() }.map(|node| (&node.key, &node.value))
+    }
+
+    /// last() takes the express lanes as far right as they go, so it's O(log n) too.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        let mut pred: *mut Node<K, V> = ptr::null_mut();
+        for level in (0..self.level).rev() {
+            loop {
+                let next = self.link_after(pred, level);
+                if next.is_null() {
+                    break;
+                }
+                pred = next;
+            }
+        }
+        unsafe { pred.as_ref() }.map(|node| (&node.key, &node.value))
+    }
+
+    /// pop_first() is O(1) apart from freeing the node: the first node is first on every level
+    /// it's on, so all its predecessors are the head.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let first = self.head[0];
+        if first.is_null() {
+            return None;
+        }
+        Some(self.unlink(&[ptr::null_mut(); MAX_LEVEL], first))
+    }
+
+    pub fn iter(&self) -> Range<'_, K, V> {
+        Range {
+            node: self.head[0],
+            end: ptr::null(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// range() finds where the range starts in O(log n) and then walks the bottom level. Unlike
+    /// `BTreeMap::range`, a range that ends before it starts is just empty.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.seek(|k| k.borrow() < start).1,
+            Bound::Excluded(start) => self.seek(|k| k.borrow() <= start).1,
+            Bound::Unbounded => self.head[0],
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.seek(|k| k.borrow() <= end).1,
+            Bound::Excluded(end) => self.seek(|k| k.borrow() < end).1,
+            Bound::Unbounded => ptr::null_mut(),
+        };
+        // If the end comes first, walking from `start` would never reach it.
+        let backwards = match unsafe { (start.as_ref(), end.as_ref()) } {
+            (Some(start), Some(end)) => end.key < start.key,
+            _ => false,
+        };
+        Range {
+            node: if backwards { end } else { start },
+            end,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K: Ord, V> Default for SkipMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for SkipMap<K, V> {
+    fn drop(&mut self) {
+        // Same as `second`: walk the bottom level, freeing as we go.
+        let mut cur = self.head[0];
+        while !cur.is_null() {
+            let node = unsafe { Box::from_raw(cur) };
+            cur = node.next;
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.node == self.end {
+            return None;
+        }
+        let node = unsafe { self.node.as_ref()? };
+        self.node = node.next;
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a SkipMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Range<'a, K, V>;
+
+    fn into_iter(self) -> Range<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K: Ord> SkipSet<K> {
+    pub fn new() -> Self {
+        SkipSet {
+            map: SkipMap::new(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        SkipSet {
+            map: SkipMap::with_seed(seed),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// insert() returns whether `key` is new.
+    pub fn insert(&mut self, key: K) -> bool {
+        self.map.insert(key, ()).is_none()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove(key).is_some()
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn first(&self) -> Option<&K> {
+        self.map.first().map(|(key, _)| key)
+    }
+
+    pub fn last(&self) -> Option<&K> {
+        self.map.last().map(|(key, _)| key)
+    }
+
+    pub fn pop_first(&mut self) -> Option<K> {
+        self.map.pop_first().map(|(key, _)| key)
+    }
+
+    pub fn iter(&self) -> SetRange<'_, K> {
+        SetRange(self.map.iter())
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> SetRange<'_, K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        SetRange(self.map.range(range))
+    }
+}
+
+impl<K: Ord> Default for SkipSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, K> Iterator for SetRange<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.0.next().map(|(key, _)| key)
+    }
+}
+
+impl<'a, K: Ord> IntoIterator for &'a SkipSet<K> {
+    type Item = &'a K;
+    type IntoIter = SetRange<'a, K>;
+
+    fn into_iter(self) -> SetRange<'a, K> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SkipMap, SkipSet, MAX_LEVEL};
+    use crate::test_util::{Drops, XorShift};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::ops::Bound;
+
+    /// Every node's height, in key order, checking each level is sorted on the way.
+    fn heights<K: Ord, V>(map: &SkipMap<K, V>) -> Vec<usize> {
+        for level in 0..MAX_LEVEL {
+            let mut cur = map.head[level];
+            if level >= map.level {
+                assert!(cur.is_null());
+            }
+            while let Some(node) = unsafe { cur.as_ref() } {
+                let next = node.link(level);
+                if let Some(next) = unsafe { next.as_ref() } {
+                    assert!(node.key < next.key);
+                }
+                cur = next;
+            }
+        }
+        let mut heights = Vec::new();
+        let mut cur = map.head[0];
+        while let Some(node) = unsafe { cur.as_ref() } {
+            heights.push(node.height());
+            cur = node.next;
+        }
+        assert_eq!(heights.len(), map.len());
+        heights
+    }
+
+    #[test]
+    fn basics() {
+        let mut map = SkipMap::new();
+        assert_eq!(map.first(), None);
+        assert_eq!(map.last(), None);
+        assert_eq!(map.pop_first(), None);
+        assert_eq!(map.remove(&1), None);
+
+        for key in [30, 10, 50, 20, 40] {
+            assert_eq!(map.insert(key, key * 10), None);
+        }
+        assert_eq!(map.insert(20, 0), Some(200));
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.get(&20), Some(&0));
+        assert_eq!(map.get(&25), None);
+        *map.get_mut(&40).unwrap() += 1;
+
+        assert_eq!(map.first(), Some((&10, &100)));
+        assert_eq!(map.last(), Some((&50, &500)));
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            [
+                (&10, &100),
+                (&20, &0),
+                (&30, &300),
+                (&40, &401),
+                (&50, &500)
+            ]
+        );
+
+        assert_eq!(map.remove(&30), Some(300));
+        assert_eq!(map.remove(&30), None);
+        assert_eq!(map.pop_first(), Some((10, 100)));
+        assert_eq!(
+            map.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            [20, 40, 50]
+        );
+        heights(&map);
+    }
+
+    #[test]
+    fn borrowed_keys() {
+        let mut map = SkipMap::new();
+        map.insert("b".to_string(), 2);
+        map.insert("a".to_string(), 1);
+        assert_eq!(map.get("a"), Some(&1));
+        assert!(map.contains_key("b"));
+        assert_eq!(
+            map.range::<str, _>((Bound::Excluded("a"), Bound::Unbounded))
+                .count(),
+            1
+        );
+        assert_eq!(map.remove("a"), Some(1));
+    }
+
+    #[test]
+    fn ranges() {
+        let map: SkipMap<i32, ()> = {
+            let mut map = SkipMap::new();
+            for key in (0..100).step_by(10) {
+                map.insert(key, ());
+            }
+            map
+        };
+        let keys = |range: super::Range<'_, i32, ()>| range.map(|(k, _)| *k).collect::<Vec<_>>();
+
+        assert_eq!(keys(map.range(20..50)), [20, 30, 40]);
+        assert_eq!(keys(map.range(15..=50)), [20, 30, 40, 50]);
+        assert_eq!(keys(map.range(..25)), [0, 10, 20]);
+        assert_eq!(keys(map.range(85..)), [90]);
+        assert_eq!(
+            keys(map.range((Bound::Excluded(20), Bound::Excluded(50)))),
+            [30, 40]
+        );
+        assert_eq!(keys(map.range(41..49)), []);
+        assert_eq!(keys(map.range(200..)), []);
+        let (start, end) = (50, 20);
+        assert_eq!(keys(map.range(start..end)), []);
+        assert_eq!(keys(map.range(..)).len(), 10);
+    }
+
+    #[test]
+    fn same_seed_same_shape() {
+        let build = |seed| {
+            let mut map = SkipMap::with_seed(seed);
+            for key in 0..500 {
+                map.insert((key * 7919) % 500, ());
+            }
+            heights(&map)
+        };
+        assert_eq!(build(1), build(1));
+        assert_ne!(build(1), build(2));
+
+        // About 3/4 of the nodes should have height 1.
+        let heights = build(3);
+        let short = heights.iter().filter(|&&h| h == 1).count();
+        assert!((300..450).contains(&short), "{} of 500 at height 1", short);
+    }
+
+    /// Random inserts, removes, lookups, pops and range scans against a `BTreeMap`.
+    #[test]
+    fn same_as_btreemap() {
+        for seed in 1..4u64 {
+            let mut ours = SkipMap::with_seed(seed);
+            let mut model = BTreeMap::new();
+            let mut rng = XorShift::with_seed(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+
+            for i in 0..5_000 {
+                let bits = rng.next_u64();
+                let key = (bits >> 20) % 1_000;
+                match bits % 6 {
+                    0 | 1 => assert_eq!(ours.insert(key, i), model.insert(key, i)),
+                    2 => assert_eq!(ours.remove(&key), model.remove(&key)),
+                    3 => assert_eq!(ours.get(&key), model.get(&key)),
+                    4 => {
+                        if i % 50 == 0 {
+                            assert_eq!(ours.pop_first(), model.pop_first());
+                        }
+                    }
+                    _ => {
+                        let end = key + (bits >> 40) % 100;
+                        assert!(ours.range(key..end).eq(model.range(key..end)));
+                        assert!(ours.range(..=key).eq(model.range(..=key)));
+                    }
+                }
+                assert_eq!(ours.len(), model.len());
+                assert_eq!(ours.first(), model.iter().next());
+                assert_eq!(ours.last(), model.iter().next_back());
+            }
+            heights(&ours);
+            assert!(ours.iter().eq(model.iter()));
+        }
+    }
+
+    #[test]
+    fn set() {
+        let mut ours = SkipSet::new();
+        let mut model = BTreeSet::new();
+        for key in [5, 3, 9, 3, 1, 7] {
+            assert_eq!(ours.insert(key), model.insert(key));
+        }
+        assert!(ours.iter().eq(model.iter()));
+        assert!(ours.contains(&9));
+        assert!(ours.remove(&9));
+        assert!(!ours.remove(&9));
+        assert_eq!(ours.first(), Some(&1));
+        assert_eq!(ours.last(), Some(&7));
+        assert_eq!(ours.range(2..6).copied().collect::<Vec<_>>(), [3, 5]);
+        assert_eq!(ours.pop_first(), Some(1));
+        assert_eq!(ours.len(), 3);
+    }
+
+    #[test]
+    fn drops_everything_once() {
+        let drops = Drops::new();
+        let mut map = SkipMap::new();
+        for key in 0..100 {
+            map.insert(key, drops.counted());
+        }
+        map.insert(5, drops.counted());
+        assert_eq!(drops.get(), 1);
+        drop(map.remove(&6));
+        drop(map.pop_first());
+        assert_eq!(drops.get(), 3);
+        drop(map);
+        assert_eq!(drops.get(), 101);
+    }
+}
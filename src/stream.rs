@@ -0,0 +1,457 @@
+//! A lazy, memoized stream.
+//!
+//! This is `third::List` with one twist: the tail of a cell doesn't have to exist yet. A node
+//! starts out as a suspended computation (a *thunk*) and is overwritten with its result the first
+//! time somebody looks at it, so every element is computed at most once no matter how many
+//! streams share the node. That's how Haskell lists behave:
+//!
+//! ```ignore
+//! nats = 0 -> <thunk>
+//! take 3 nats
+//! nats = 0 -> 1 -> 2 -> <thunk>
+//! ```
+//!
+//! Two things would blow the stack if we did this naively:
+//!
+//! - a thunk may evaluate to *another* stream that is itself a thunk, and so on. We never force
+//!   those recursively: a forced thunk just records "I'm the same as that stream" and the forcing
+//!   loop keeps walking.
+//! - a long, fully forced stream is the same chain of `Rc`s as `third::List`, so it gets the same
+//!   `Rc::try_unwrap` destructor.
+
+use std::cell::{Cell, OnceCell, RefCell};
+use std::rc::Rc;
+
+use crate::third;
+
+pub struct Stream<T> {
+    head: Link<T>,
+}
+
+/// `None` is the (already evaluated) empty stream, just like `third::Link`.
+type Link<T> = Option<Rc<Node<T>>>;
+
+type Thunk<T> = Box<dyn FnOnce() -> Stream<T>>;
+
+struct Node<T> {
+    /// Set exactly once, by whoever forces the node first.
+    value: OnceCell<Value<T>>,
+    /// Taken out right before it runs. A node with neither a value nor a thunk is being forced
+    /// right now, so seeing one again means a stream depends on itself.
+    thunk: Cell<Option<Thunk<T>>>,
+}
+
+enum Value<T> {
+    Cons(T, Stream<T>),
+    /// The thunk evaluated to another stream; this node is that stream.
+    Same(Stream<T>),
+}
+
+impl<T> Node<T> {
+    fn into_next(self) -> Link<T> {
+        match self.value.into_inner() {
+            Some(Value::Cons(_, mut next)) | Some(Value::Same(mut next)) => next.head.take(),
+            None => None,
+        }
+    }
+}
+
+impl<T> Stream<T> {
+    pub fn empty() -> Self {
+        Stream { head: None }
+    }
+
+    /// cons() puts an element in front of a (possibly lazy) tail. The new cell is already
+    /// evaluated.
+    pub fn cons(elem: T, tail: Stream<T>) -> Self {
+        let value = OnceCell::new();
+        let _ = value.set(Value::Cons(elem, tail));
+        Stream {
+            head: Some(Rc::new(Node {
+                value,
+                thunk: Cell::new(None),
+            })),
+        }
+    }
+
+    /// Walks to the first cell that is either empty or a `Cons`, running thunks on the way.
+    ///
+    /// This is a loop rather than a recursion: a thunk returning a thunk returning a thunk just
+    /// adds another trip around it.
+    fn force(&self) -> Option<(&T, &Stream<T>)> {
+        let mut cur = self;
+        loop {
+            let node = cur.head.as_ref()?;
+            if node.value.get().is_none() {
+                let thunk = node
+                    .thunk
+                    .take()
+                    .expect("stream forced while forcing itself");
+                let _ = node.value.set(Value::Same(thunk()));
+            }
+            match node.value.get() {
+                Some(Value::Cons(elem, next)) => return Some((elem, next)),
+                Some(Value::Same(next)) => cur = next,
+                None => unreachable!(),
+            }
+        }
+    }
+
+    /// head() forces the first cell and returns a reference to its element.
+    pub fn head(&self) -> Option<&T> {
+        self.force().map(|(elem, _)| elem)
+    }
+
+    /// tail() forces the first cell and returns the rest of the stream, which shares its cells
+    /// with this one. The tail of the empty stream is empty.
+    pub fn tail(&self) -> Stream<T> {
+        self.force()
+            .map(|(_, next)| next.clone())
+            .unwrap_or_else(Stream::empty)
+    }
+
+    pub fn uncons(&self) -> Option<(&T, Stream<T>)> {
+        self.force().map(|(elem, next)| (elem, next.clone()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.force().is_none()
+    }
+
+    /// Forces the stream as it goes; elements that were already computed are just read back.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { next: Some(self) }
+    }
+}
+
+impl<T: 'static> Stream<T> {
+    /// lazy() suspends a computation that produces a stream. Nothing runs until the stream is
+    /// looked at, and then it runs only once.
+    pub fn lazy<F>(f: F) -> Self
+    where
+        F: FnOnce() -> Stream<T> + 'static,
+    {
+        Stream {
+            head: Some(Rc::new(Node {
+                value: OnceCell::new(),
+                thunk: Cell::new(Some(Box::new(f))),
+            })),
+        }
+    }
+
+    /// The stream of `f()`, `f()`, ... up to the first `None`. `f` is only called when a new cell
+    /// is forced, and in order.
+    pub fn from_fn<F>(f: F) -> Self
+    where
+        F: FnMut() -> Option<T> + 'static,
+    {
+        from_fn_shared(Rc::new(RefCell::new(f)))
+    }
+
+    pub fn map<U, F>(&self, f: F) -> Stream<U>
+    where
+        U: 'static,
+        F: Fn(&T) -> U + 'static,
+    {
+        map_shared(self.clone(), Rc::new(f))
+    }
+}
+
+impl<T: Clone + 'static> Stream<T> {
+    /// The infinite stream `seed`, `f(seed)`, `f(f(seed))`, ...
+    pub fn iterate<F>(seed: T, f: F) -> Self
+    where
+        F: Fn(&T) -> T + 'static,
+    {
+        iterate_shared(seed, Rc::new(f))
+    }
+
+    /// The infinite stream `elem`, `elem`, ...
+    pub fn repeat(elem: T) -> Self {
+        Stream::iterate(elem, T::clone)
+    }
+
+    /// The first `n` elements.
+    pub fn take(&self, n: usize) -> Stream<T> {
+        if n == 0 {
+            return Stream::empty();
+        }
+        let src = self.clone();
+        Stream::lazy(move || match src.uncons() {
+            Some((elem, rest)) => Stream::cons(elem.clone(), rest.take(n - 1)),
+            None => Stream::empty(),
+        })
+    }
+
+    /// Rejected elements are skipped in a loop inside a single thunk, so a long run of them
+    /// doesn't nest.
+    pub fn filter<P>(&self, pred: P) -> Stream<T>
+    where
+        P: Fn(&T) -> bool + 'static,
+    {
+        filter_shared(self.clone(), Rc::new(pred))
+    }
+
+    /// Pairs up elements until either stream runs out.
+    pub fn zip<U: Clone + 'static>(&self, other: &Stream<U>) -> Stream<(T, U)> {
+        let (a, b) = (self.clone(), other.clone());
+        Stream::lazy(move || match (a.uncons(), b.uncons()) {
+            (Some((x, a)), Some((y, b))) => Stream::cons((x.clone(), y.clone()), a.zip(&b)),
+            _ => Stream::empty(),
+        })
+    }
+
+    /// Alternates elements, starting with `self`. Once one side runs out the rest of the other
+    /// side follows.
+    pub fn interleave(&self, other: &Stream<T>) -> Stream<T> {
+        let (a, b) = (self.clone(), other.clone());
+        Stream::lazy(move || match a.uncons() {
+            Some((x, a)) => Stream::cons(x.clone(), b.interleave(&a)),
+            None => b,
+        })
+    }
+
+    /// Forces the whole stream into a `third::List` with the same order. Never returns for an
+    /// infinite stream; `take` first.
+    pub fn to_list(&self) -> third::List<T> {
+        let elems: Vec<&T> = self.iter().collect();
+        elems
+            .into_iter()
+            .rev()
+            .fold(third::List::new(), |list, elem| list.append(elem.clone()))
+    }
+}
+
+fn from_fn_shared<T, F>(f: Rc<RefCell<F>>) -> Stream<T>
+where
+    T: 'static,
+    F: FnMut() -> Option<T> + 'static,
+{
+    Stream::lazy(move || {
+        let next = (f.borrow_mut())();
+        match next {
+            Some(elem) => Stream::cons(elem, from_fn_shared(f)),
+            None => Stream::empty(),
+        }
+    })
+}
+
+fn iterate_shared<T, F>(seed: T, f: Rc<F>) -> Stream<T>
+where
+    T: Clone + 'static,
+    F: Fn(&T) -> T + 'static,
+{
+    let prev = seed.clone();
+    Stream::cons(seed, Stream::lazy(move || iterate_shared(f(&prev), f)))
+}
+
+fn map_shared<T, U, F>(src: Stream<T>, f: Rc<F>) -> Stream<U>
+where
+    T: 'static,
+    U: 'static,
+    F: Fn(&T) -> U + 'static,
+{
+    Stream::lazy(move || match src.uncons() {
+        Some((elem, rest)) => Stream::cons(f(elem), map_shared(rest, f)),
+        None => Stream::empty(),
+    })
+}
+
+fn filter_shared<T, P>(src: Stream<T>, pred: Rc<P>) -> Stream<T>
+where
+    T: Clone + 'static,
+    P: Fn(&T) -> bool + 'static,
+{
+    Stream::lazy(move || {
+        let mut cur = src;
+        loop {
+            let rest = match cur.uncons() {
+                Some((elem, rest)) if pred(elem) => {
+                    return Stream::cons(elem.clone(), filter_shared(rest, pred))
+                }
+                Some((_, rest)) => rest,
+                None => return Stream::empty(),
+            };
+            cur = rest;
+        }
+    })
+}
+
+impl<T> Clone for Stream<T> {
+    /// Shares the cells, including the ones that haven't been forced yet.
+    fn clone(&self) -> Self {
+        Stream {
+            head: self.head.clone(),
+        }
+    }
+}
+
+impl<T> Default for Stream<T> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+/// Same `Rc::try_unwrap` destructor as `third::List`: keep hoisting nodes out while we're the
+/// last owner. A thunk that was never run still owns whatever it captured, which is dropped as
+/// usual.
+impl<T> Drop for Stream<T> {
+    fn drop(&mut self) {
+        let mut head = self.head.take();
+        while let Some(node) = head {
+            if let Ok(node) = Rc::try_unwrap(node) {
+                head = node.into_next();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+pub struct Iter<'a, T: 'a> {
+    next: Option<&'a Stream<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next.take()?.force() {
+            Some((elem, rest)) => {
+                self.next = Some(rest);
+                Some(elem)
+            }
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Stream;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn nats() -> Stream<u64> {
+        Stream::iterate(0, |x| x + 1)
+    }
+
+    #[test]
+    fn basics() {
+        let s = Stream::cons(1, Stream::cons(2, Stream::empty()));
+        assert_eq!(s.head(), Some(&1));
+        assert_eq!(s.tail().head(), Some(&2));
+        assert_eq!(s.tail().tail().head(), None);
+        assert!(s.tail().tail().tail().is_empty());
+        assert!(!s.is_empty());
+    }
+
+    #[test]
+    fn memoized() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let s = Stream::from_fn(move || {
+            counter.set(counter.get() + 1);
+            if counter.get() <= 5 {
+                Some(counter.get())
+            } else {
+                None
+            }
+        });
+        assert_eq!(calls.get(), 0);
+
+        assert_eq!(s.head(), Some(&1));
+        assert_eq!(calls.get(), 1);
+
+        let shared = s.clone();
+        assert_eq!(
+            shared.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(calls.get(), 6);
+
+        // Everything is cached now.
+        assert_eq!(s.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(calls.get(), 6);
+    }
+
+    #[test]
+    fn infinite() {
+        let evens = nats().filter(|x| x % 2 == 0);
+        let squares = evens.map(|x| x * x);
+        assert_eq!(
+            squares.take(4).iter().copied().collect::<Vec<_>>(),
+            vec![0, 4, 16, 36]
+        );
+        assert_eq!(
+            Stream::repeat('a').take(3).iter().collect::<String>(),
+            "aaa"
+        );
+    }
+
+    #[test]
+    fn zip_interleave() {
+        let odds = nats().map(|x| 2 * x + 1);
+        let pairs = nats().zip(&odds).take(3);
+        assert_eq!(
+            pairs.iter().copied().collect::<Vec<_>>(),
+            vec![(0, 1), (1, 3), (2, 5)]
+        );
+
+        let short = Stream::cons(100, Stream::empty());
+        let mixed = nats().take(3).interleave(&short);
+        assert_eq!(
+            mixed.iter().copied().collect::<Vec<_>>(),
+            vec![0, 100, 1, 2]
+        );
+    }
+
+    #[test]
+    fn to_list() {
+        let list = nats().take(3).to_list();
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(Stream::<u64>::empty().to_list().head(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "forced while forcing itself")]
+    fn self_reference() {
+        let slot: Rc<Cell<Option<Stream<u8>>>> = Rc::new(Cell::new(None));
+        let inner = slot.clone();
+        let s = Stream::lazy(move || {
+            let me = inner.take().unwrap();
+            me.tail()
+        });
+        slot.set(Some(s.clone()));
+        s.head();
+    }
+
+    #[test]
+    fn deep_lazy_chain() {
+        // A million thunks that each evaluate to the next one.
+        let mut s = Stream::cons(42, Stream::empty());
+        for _ in 0..1_000_000 {
+            let prev = s;
+            s = Stream::lazy(move || prev);
+        }
+        assert_eq!(s.head(), Some(&42));
+    }
+
+    #[test]
+    fn deep_filter() {
+        let s = nats().filter(|x| *x == 1_000_000);
+        assert_eq!(s.head(), Some(&1_000_000));
+    }
+
+    #[test]
+    fn long_drop() {
+        let s = nats();
+        assert_eq!(s.iter().nth(1_000_000), Some(&1_000_000));
+        drop(s);
+    }
+}
@@ -0,0 +1,126 @@
+//! Bits and pieces the test modules share.
+//!
+//! Most containers in here are tested the same way: a few thousand random operations, applied to
+//! ours and to something from `std` that's known to work, comparing the two as we go. The
+//! operations come from `XorShift`, so a failure shows up on every run, not just an unlucky one.
+//! And most of them check that every element gets dropped exactly once, by filling the container
+//! with `Counted`s.
+
+use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Arc;
+
+/// Marsaglia's xorshift64: not much of a random number generator, but plenty for picking test
+/// operations, and the same seed always gives the same sequence.
+pub struct XorShift(u64);
+
+impl XorShift {
+    /// The seed the test modules use unless they have a reason not to.
+    pub const SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+    pub fn new() -> Self {
+        Self::with_seed(Self::SEED)
+    }
+
+    /// with_seed() panics on 0: xorshift never leaves it.
+    pub fn with_seed(seed: u64) -> Self {
+        assert_ne!(seed, 0, "xorshift needs a nonzero seed");
+        XorShift(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Hands out `Counted`s and keeps count of how many of them have been dropped. The count is an
+/// `Arc<AtomicUsize>`, so the concurrent containers can drop them on any thread.
+#[derive(Clone, Default)]
+pub struct Drops(Arc<AtomicUsize>);
+
+/// A value that adds one to its `Drops` when it's dropped. Compares by `value` alone.
+pub struct Counted<T = ()> {
+    pub value: T,
+    drops: Arc<AtomicUsize>,
+}
+
+impl Drops {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn counted(&self) -> Counted {
+        self.with(())
+    }
+
+    pub fn with<T>(&self, value: T) -> Counted<T> {
+        Counted {
+            value,
+            drops: self.0.clone(),
+        }
+    }
+
+    /// How many of our `Counted`s have been dropped so far.
+    pub fn get(&self) -> usize {
+        self.0.load(atomic::Ordering::SeqCst)
+    }
+}
+
+impl<T> Drop for Counted<T> {
+    fn drop(&mut self) {
+        self.drops.fetch_add(1, atomic::Ordering::SeqCst);
+    }
+}
+
+impl<T: PartialEq> PartialEq for Counted<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Eq> Eq for Counted<T> {}
+
+impl<T: PartialOrd> PartialOrd for Counted<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl<T: Ord> Ord for Counted<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Drops, XorShift};
+
+    #[test]
+    fn repeatable() {
+        let (mut a, mut b) = (XorShift::new(), XorShift::with_seed(XorShift::SEED));
+        for _ in 0..1_000 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(
+            XorShift::with_seed(1).next_u64(),
+            XorShift::new().next_u64()
+        );
+    }
+
+    #[test]
+    fn counts_drops() {
+        let drops = Drops::new();
+        let (a, b) = (drops.counted(), drops.with(2));
+        assert_eq!(drops.get(), 0);
+        drop(a);
+        assert_eq!(drops.get(), 1);
+        assert!(drops.with(1) < b);
+        assert_eq!(drops.get(), 2);
+        drop(b);
+        assert_eq!(drops.get(), 3);
+    }
+}
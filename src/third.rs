@@ -1,39 +1,39 @@
-/// list1 = A -> B -> C -> D
-/// list2 = tail(list1) = B -> C -> D
-/// list3 = push(list2, X) = X -> B -> C -> D
-///
-/// list1 -> A ---v
-/// list2 ------> B -> C -> D
-/// list3 -> X ---^
-///
-/// Rust doesn't have anything like the garbage collectors these languages have. They have tracing
-/// GC, which will dig through all the memory that's sitting around at runtime and figure out
-/// what's garbage automatically. Instead, all Rust has today is reference counting. Reference
-/// counting is basically a poor-man's GC. For many workloads, it has significantly less throughput
-/// than a tracing collector, and it completely falls over if you manage to build cycles.
-/// Thankfully, for our usecase we'll never run into cycles (feel free to try to prove this to
-/// yourself -- I sure won't).
-///
-///
-/// So how do we do reference counted garbage collection? Rc! Rc is just like Box, but we can
-/// duplicate it, and its memory will only be freed when all the Rc's derived from are dropped.
-/// Unforuntately, this flexibility comes at a serious cost: we can only Deref an Rc. No DerefMut
-/// or DerefMove. This means we can't ever really get data out of one of our lists, nor can we
-/// mutate them.
-///
-
-/// We need reference counting now
+//! list1 = A -> B -> C -> D
+//! list2 = tail(list1) = B -> C -> D
+//! list3 = push(list2, X) = X -> B -> C -> D
+//!
+//! list1 -> A ---v
+//! list2 ------> B -> C -> D
+//! list3 -> X ---^
+//!
+//! Rust doesn't have anything like the garbage collectors these languages have. They have tracing
+//! GC, which will dig through all the memory that's sitting around at runtime and figure out
+//! what's garbage automatically. Instead, all Rust has today is reference counting. Reference
+//! counting is basically a poor-man's GC. For many workloads, it has significantly less throughput
+//! than a tracing collector, and it completely falls over if you manage to build cycles.
+//! Thankfully, for our usecase we'll never run into cycles (feel free to try to prove this to
+//! yourself -- I sure won't).
+//!
+//!
+//! So how do we do reference counted garbage collection? Rc! Rc is just like Box, but we can
+//! duplicate it, and its memory will only be freed when all the Rc's derived from are dropped.
+//! Unforuntately, this flexibility comes at a serious cost: we can only Deref an Rc. No DerefMut
+//! or DerefMove. This means we can't ever really get data out of one of our lists, nor can we
+//! mutate them.
+//!
+
+// We need reference counting now
 use std::rc::Rc;
 
 pub struct List<T> {
-    head: Link<T>,
+    pub(crate) head: Link<T>,
 }
 
-type Link<T> = Option<Rc<Node<T>>>;
+pub(crate) type Link<T> = Option<Rc<Node<T>>>;
 
-struct Node<T> {
-    elem: T,
-    next: Link<T>,
+pub(crate) struct Node<T> {
+    pub(crate) elem: T,
+    pub(crate) next: Link<T>,
 }
 
 pub struct Iter<'a, T: 'a> {
@@ -43,7 +43,7 @@ pub struct Iter<'a, T: 'a> {
 impl<T> List<T> {
     pub fn iter<'a>(&'a self) -> Iter<'a, T> {
         Iter {
-            next: self.head.as_ref().map(|node| &**node),
+            next: self.head.as_deref(),
         }
     }
 }
@@ -53,12 +53,18 @@ impl<'a, T> Iterator for Iter<'a, T> {
 
     fn next(&mut self) -> Option<Self::Item> {
         self.next.map(|node| {
-            self.next = node.next.as_ref().map(|node| &**node);
+            self.next = node.next.as_deref();
             &node.elem
         })
     }
 }
 
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> List<T> {
     pub fn new() -> Self {
         List { head: None }
@@ -82,7 +88,7 @@ impl<T> List<T> {
     pub fn append(&self, elem: T) -> List<T> {
         List {
             head: Some(Rc::new(Node {
-                elem: elem,
+                elem,
                 next: self.head.clone(),
             })),
         }
@@ -94,7 +100,7 @@ impl<T> List<T> {
     /// it exists).
     pub fn tail(&self) -> List<T> {
         List {
-            /// and_then
+            // and_then
             head: self.head.as_ref().and_then(|node| node.next.clone()),
         }
     }
@@ -105,6 +111,25 @@ impl<T> List<T> {
     pub fn head(&self) -> Option<&T> {
         self.head.as_ref().map(|node| &node.elem)
     }
+
+    /// ptr_eq() tells whether two lists start at the very same node, i.e. one is a clone of the
+    /// other. Two empty lists are the same list.
+    pub fn ptr_eq(&self, other: &List<T>) -> bool {
+        match (&self.head, &other.head) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T> Clone for List<T> {
+    /// Cloning a list is just bumping the count of its head; all the nodes are shared.
+    fn clone(&self) -> Self {
+        List {
+            head: self.head.clone(),
+        }
+    }
 }
 
 /// recursive destructor
@@ -120,46 +145,44 @@ impl<T> List<T> {
 ///     }
 /// }
 /// ```
+// a recursive deconstructor that works in O(n)
+//
+// The first way is that we can keep grabbing the tail of the list and dropping the previous
+// one to decrement its count. This will prevent the old list from recursively dropping the
+// rest of the list because we hold an outstanding reference to it. This has the unfortunate
+// problem that we traverse the entire list whenever we drop it. In particular this means
+// building a list of length n in place takes O(n2) as we traverse a lists of length n-1, n-2,
+// .., 1 to guard against overflow.
+//
+//impl<T> Drop for List<T> {
+//    fn drop(&mut self) {
+//        let mut cur_list = self.head.take();
+//        while let Some(node) = cur_list {
+//            cur_list = node.next.clone();
+//        }
+//    }
+//}
 impl<T> Drop for List<T> {
-    /// a recursive deconstructor that works in O(n)
+    /// a recursive deconstructor that works in amortized O(1)
+    ///
+    /// The second way is if we could identify that we're the last list that knows about this node,
+    /// we could in principle actually move the Node out of the Rc. Then we could also know when to
+    /// stop: whenver we can't hoist out the Node. `Rc::try_unwrap` does exactly that.
     ///
-    /// The first way is that we can keep grabbing the tail of the list and dropping the previous
-    /// one to decrement its count. This will prevent the old list from recursively dropping the
-    /// rest of the list because we hold an outstanding reference to it. This has the unfortunate
-    /// problem that we traverse the entire list whenever we drop it. In particular this means
-    /// building a list of length n in place takes O(n2) as we traverse a lists of length n-1, n-2,
-    /// .., 1 to guard against overflow.
+    /// This is the one we want once lists get built in place (`list = list.append(x)`) or
+    /// converted from other sequences: the old head is still shared by the new node, so the loop
+    /// stops right away.
     fn drop(&mut self) {
-        // Steal the list's head
-        let mut cur_list = self.head.take();
-        while let Some(node) = cur_list {
-            // Clone the current node's next node.
-            cur_list = node.next.clone();
-            // Node dropped here. If the old node had
-            // refcount 1, then it will be dropped and freed, but it won't
-            // be able to fully recurse and drop its child, because we
-            // hold another Rc to it.
+        let mut head = self.head.take();
+        while let Some(node) = head {
+            if let Ok(mut node) = Rc::try_unwrap(node) {
+                head = node.next.take();
+            } else {
+                break;
+            }
         }
     }
 }
-/// a recursive deconstructor that works in amortized O(1)
-///
-/// The second way is if we could identify that we're the last list that knows about this node,
-/// we could in principle actually move the Node out of the Rc. Then we could also know when to
-/// stop: whenver we can't hoist out the Node. For reference, the unstable function is called
-/// try_unwrap.
-///impl<T> Drop for List<T> {
-/// fn drop(&mut self) {
-/// let mut head = self.head.take();
-/// while let Some(node) = head {
-///     if let Ok(mut node) = Rc::try_unwrap(node) {
-///         head = node.next.take();
-///     } else {
-///         break;
-///     }
-/// }
-/// }
-/// }
 
 #[cfg(test)]
 mod test {
@@ -0,0 +1,511 @@
+//! A hierarchical timer wheel: thousands of timeouts, O(1) to schedule and to cancel.
+//!
+//! Level 0 has 64 slots, one per tick. Each level above has 64 slots too, but each of its slots
+//! covers a whole turn of the level below, so six levels reach 2^36 ticks ahead:
+//!
+//! ```ignore
+//! level 0: |0|1|2|...|63|             1 tick per slot
+//! level 1: |0|1|2|...|63|            64 ticks per slot
+//! level 2: |0|1|2|...|63|         4_096 ticks per slot
+//! ...
+//! ```
+//!
+//! A timer goes in the lowest level whose slot can tell its deadline apart from now. When time
+//! reaches one of the higher slots, its timers *cascade*: each is put back in, and now that its
+//! deadline is close it lands in a lower level, until at level 0 it expires. A timer is touched
+//! at most once per level on its way down, however long it waits.
+//!
+//! Each slot is a doubly linked list, and the links live in the timers themselves, which sit in
+//! one `Vec` like `arena_list`'s nodes. So cancelling a timer is unlinking it from whatever slot
+//! it's in, without looking for it. A `TimerHandle` has a generation for the same reason an
+//! `arena_list::Index` does: a handle to a timer that already fired can't cancel its successor.
+//!
+//! The wheel has no clock of its own. Time is a `u64` count of ticks, in whatever unit the
+//! caller likes, and it only moves when the caller says so with `advance`.
+
+use std::fmt;
+
+const BITS: u32 = 6;
+const SLOTS: usize = 1 << BITS;
+const LEVELS: usize = 6;
+/// How far ahead the top level reaches. Timers further out than that park in the top level and
+/// get put back in each time their slot comes around.
+const SPAN: u64 = 1 << (BITS * LEVELS as u32);
+
+const NIL: u32 = u32::MAX;
+/// The slot for timers scheduled at or before the current time.
+const DUE: usize = LEVELS * SLOTS;
+
+pub struct TimerWheel<T> {
+    timers: Vec<Slot<T>>,
+    /// First slot of the free list.
+    free: u32,
+    /// `lists[level * SLOTS + slot]`, plus `DUE` at the end.
+    lists: Vec<List>,
+    /// Which slots of each level have timers in them, to find the next one without looking.
+    occupied: [u64; LEVELS],
+    now: u64,
+    len: usize,
+}
+
+struct Slot<T> {
+    generation: u32,
+    entry: Entry<T>,
+}
+
+enum Entry<T> {
+    Scheduled(Timer<T>),
+    Free { next_free: u32 },
+}
+
+struct Timer<T> {
+    value: T,
+    deadline: u64,
+    /// The list it's in, so `cancel` can fix up that list's ends.
+    list: usize,
+    prev: u32,
+    next: u32,
+}
+
+#[derive(Clone, Copy)]
+struct List {
+    head: u32,
+    tail: u32,
+}
+
+const EMPTY: List = List {
+    head: NIL,
+    tail: NIL,
+};
+
+/// Refers to one scheduled timer. It goes stale once the timer fires or is cancelled.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerHandle {
+    index: u32,
+    generation: u32,
+}
+
+impl fmt::Debug for TimerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TimerHandle({}v{})", self.index, self.generation)
+    }
+}
+
+impl<T> TimerWheel<T> {
+    /// A wheel whose clock reads `now`.
+    pub fn new(now: u64) -> Self {
+        TimerWheel {
+            timers: Vec::new(),
+            free: NIL,
+            lists: vec![EMPTY; DUE + 1],
+            occupied: [0; LEVELS],
+            now,
+            len: 0,
+        }
+    }
+
+    /// The time of the last `advance`.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn timer(&self, index: u32) -> &Timer<T> {
+        match &self.timers[index as usize].entry {
+            Entry::Scheduled(timer) => timer,
+            Entry::Free { .. } => unreachable!("followed a link to a free slot"),
+        }
+    }
+
+    fn timer_mut(&mut self, index: u32) -> &mut Timer<T> {
+        match &mut self.timers[index as usize].entry {
+            Entry::Scheduled(timer) => timer,
+            Entry::Free { .. } => unreachable!("followed a link to a free slot"),
+        }
+    }
+
+    fn index_of(&self, handle: TimerHandle) -> Option<u32> {
+        match self.timers.get(handle.index as usize) {
+            Some(Slot {
+                generation,
+                entry: Entry::Scheduled(_),
+            }) if *generation == handle.generation => Some(handle.index),
+            _ => None,
+        }
+    }
+
+    /// Which list a timer due at `deadline` belongs in, as of now.
+    fn list_for(&self, deadline: u64) -> usize {
+        if deadline <= self.now {
+            return DUE;
+        }
+        // The highest bit where the deadline and now differ decides the level; the `| SLOTS - 1`
+        // puts everything that differs only in the lowest six bits in level 0.
+        let differ = ((deadline ^ self.now) | (SLOTS as u64 - 1)).min(SPAN - 1);
+        let level = (63 - differ.leading_zeros()) / BITS;
+        let slot = (deadline >> (level * BITS)) as usize % SLOTS;
+        level as usize * SLOTS + slot
+    }
+
+    fn push_back(&mut self, list: usize, index: u32) {
+        let tail = self.lists[list].tail;
+        {
+            let timer = self.timer_mut(index);
+            timer.list = list;
+            timer.prev = tail;
+            timer.next = NIL;
+        }
+        match tail {
+            NIL => {
+                self.lists[list].head = index;
+                if list != DUE {
+                    self.occupied[list / SLOTS] |= 1 << (list % SLOTS);
+                }
+            }
+            _ => self.timer_mut(tail).next = index,
+        }
+        self.lists[list].tail = index;
+    }
+
+    /// Takes the timer out of its list. O(1): it knows its neighbours and its list.
+    fn unlink(&mut self, index: u32) {
+        let (list, prev, next) = {
+            let timer = self.timer(index);
+            (timer.list, timer.prev, timer.next)
+        };
+        match prev {
+            NIL => self.lists[list].head = next,
+            _ => self.timer_mut(prev).next = next,
+        }
+        match next {
+            NIL => self.lists[list].tail = prev,
+            _ => self.timer_mut(next).prev = prev,
+        }
+        if self.lists[list].head == NIL && list != DUE {
+            self.occupied[list / SLOTS] &= !(1 << (list % SLOTS));
+        }
+    }
+
+    /// Frees the slot of a timer that's already unlinked.
+    fn release(&mut self, index: u32) -> T {
+        let slot = &mut self.timers[index as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        let old = std::mem::replace(
+            &mut slot.entry,
+            Entry::Free {
+                next_free: self.free,
+            },
+        );
+        self.free = index;
+        self.len -= 1;
+        match old {
+            Entry::Scheduled(timer) => timer.value,
+            Entry::Free { .. } => unreachable!(),
+        }
+    }
+
+    /// schedule() sets a timer to fire at `deadline`. A deadline that's already passed fires on
+    /// the next `advance`.
+    pub fn schedule(&mut self, deadline: u64, value: T) -> TimerHandle {
+        let entry = Entry::Scheduled(Timer {
+            value,
+            deadline,
+            list: DUE,
+            prev: NIL,
+            next: NIL,
+        });
+        let index = if self.free != NIL {
+            let index = self.free;
+            match std::mem::replace(&mut self.timers[index as usize].entry, entry) {
+                Entry::Free { next_free } => self.free = next_free,
+                Entry::Scheduled(_) => unreachable!("scheduled timer on the free list"),
+            }
+            index
+        } else {
+            assert!(
+                self.timers.len() < NIL as usize,
+                "a TimerWheel can't hold more than {} timers",
+                NIL
+            );
+            self.timers.push(Slot {
+                generation: 0,
+                entry,
+            });
+            (self.timers.len() - 1) as u32
+        };
+        self.push_back(self.list_for(deadline), index);
+        self.len += 1;
+        TimerHandle {
+            index,
+            generation: self.timers[index as usize].generation,
+        }
+    }
+
+    /// cancel() stops the timer and gives its value back, or `None` if it already fired or was
+    /// cancelled.
+    pub fn cancel(&mut self, handle: TimerHandle) -> Option<T> {
+        let index = self.index_of(handle)?;
+        self.unlink(index);
+        Some(self.release(index))
+    }
+
+    pub fn is_scheduled(&self, handle: TimerHandle) -> bool {
+        self.index_of(handle).is_some()
+    }
+
+    /// The deadline `handle` was scheduled with, while it's still pending.
+    pub fn deadline(&self, handle: TimerHandle) -> Option<u64> {
+        self.index_of(handle)
+            .map(|index| self.timer(index).deadline)
+    }
+
+    /// The next list `advance` would have to look at, and the time it gets there.
+    fn next_list(&self) -> Option<(usize, u64)> {
+        if self.lists[DUE].head != NIL {
+            return Some((DUE, self.now));
+        }
+        // Everything in a level comes before everything in the levels above it.
+        let level = (0..LEVELS).find(|&level| self.occupied[level] != 0)?;
+        let shift = level as u32 * BITS;
+        let slot_range = 1u64 << shift;
+        let level_range = slot_range << BITS;
+
+        // The first occupied slot after the current one, going round. The current slot itself
+        // comes last: below the top level it's always empty, and at the top it holds timers a
+        // whole turn or more away.
+        let current = (self.now >> shift) as usize % SLOTS;
+        let ahead = self.occupied[level]
+            .rotate_right((current as u32 + 1) % SLOTS as u32)
+            .trailing_zeros() as usize;
+        let slot = (current + 1 + ahead) % SLOTS;
+
+        let mut at = (self.now & !(level_range - 1)) + slot as u64 * slot_range;
+        if at <= self.now {
+            // Only the top level wraps around, with timers from beyond its reach.
+            debug_assert_eq!(level, LEVELS - 1);
+            at += level_range;
+        }
+        Some((level * SLOTS + slot, at))
+    }
+
+    /// The earliest time `advance` might have something to return, for picking how long to
+    /// sleep. That's a lower bound: a timer many ticks away is only looked at again when its
+    /// slot comes up, so the wheel may wake up early and find nothing due yet.
+    pub fn next_expiration(&self) -> Option<u64> {
+        self.next_list().map(|(_, at)| at)
+    }
+
+    /// advance() moves the clock to `now` and returns the values of every timer due by then,
+    /// earliest deadline first. Time doesn't go backwards: an earlier `now` is a no-op.
+    pub fn advance(&mut self, now: u64) -> Vec<T> {
+        let mut expired = Vec::new();
+        while let Some((list, at)) = self.next_list() {
+            if at > now {
+                break;
+            }
+            self.now = at;
+
+            // Detach the whole list, then sort out its timers one by one.
+            let mut cur = std::mem::replace(&mut self.lists[list], EMPTY).head;
+            if list != DUE {
+                self.occupied[list / SLOTS] &= !(1 << (list % SLOTS));
+            }
+            let mut batch = Vec::new();
+            while cur != NIL {
+                let (deadline, next) = {
+                    let timer = self.timer(cur);
+                    (timer.deadline, timer.next)
+                };
+                if deadline <= self.now {
+                    batch.push((deadline, self.release(cur)));
+                } else {
+                    // Cascade: closer now, so it goes lower.
+                    self.push_back(self.list_for(deadline), cur);
+                }
+                cur = next;
+            }
+            // A slot's timers all expire at the one tick the slot comes up, but `DUE` holds
+            // whatever was scheduled in the past, in the order it was scheduled. The sort is
+            // stable, so ties still come out in that order.
+            if list == DUE {
+                batch.sort_by_key(|&(deadline, _)| deadline);
+            }
+            expired.extend(batch.into_iter().map(|(_, value)| value));
+        }
+        self.now = self.now.max(now);
+        expired
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TimerWheel, SPAN};
+    use crate::test_util::XorShift;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn basics() {
+        let mut wheel = TimerWheel::new(0);
+        assert_eq!(wheel.advance(100), Vec::<&str>::new());
+        assert_eq!(wheel.now(), 100);
+
+        wheel.schedule(105, "b");
+        wheel.schedule(103, "a");
+        wheel.schedule(110, "c");
+        assert_eq!(wheel.len(), 3);
+        assert_eq!(wheel.next_expiration(), Some(103));
+
+        assert_eq!(wheel.advance(102), Vec::<&str>::new());
+        assert_eq!(wheel.advance(105), ["a", "b"]);
+        assert_eq!(wheel.advance(200), ["c"]);
+        assert!(wheel.is_empty());
+        assert_eq!(wheel.next_expiration(), None);
+
+        // Time only goes forward.
+        assert_eq!(wheel.advance(150), Vec::<&str>::new());
+        assert_eq!(wheel.now(), 200);
+    }
+
+    #[test]
+    fn past_deadlines_fire_on_the_next_advance() {
+        let mut wheel = TimerWheel::new(50);
+        wheel.schedule(10, 'x');
+        wheel.schedule(50, 'y');
+        assert_eq!(wheel.next_expiration(), Some(50));
+        assert_eq!(wheel.advance(50), ['x', 'y']);
+
+        // Scheduled the other way round, they still come out earliest first.
+        let mut wheel = TimerWheel::new(50);
+        wheel.schedule(50, 'y');
+        wheel.schedule(10, 'x');
+        wheel.schedule(30, 'w');
+        wheel.schedule(10, 'v');
+        wheel.schedule(51, 'z');
+        assert_eq!(wheel.advance(51), ['x', 'v', 'w', 'y', 'z']);
+    }
+
+    #[test]
+    fn cancel() {
+        let mut wheel = TimerWheel::new(0);
+        let a = wheel.schedule(10, 'a');
+        let b = wheel.schedule(10, 'b');
+        let c = wheel.schedule(5_000, 'c');
+        assert_eq!(wheel.deadline(c), Some(5_000));
+
+        assert_eq!(wheel.cancel(b), Some('b'));
+        assert_eq!(wheel.cancel(b), None);
+        assert_eq!(wheel.cancel(c), Some('c'));
+        assert_eq!(wheel.len(), 1);
+        assert_eq!(wheel.advance(10_000), ['a']);
+
+        // `a` fired, and its slot goes to `d`; the old handle doesn't reach it.
+        assert!(!wheel.is_scheduled(a));
+        let d = wheel.schedule(20_000, 'd');
+        assert_ne!(a, d);
+        assert_eq!(wheel.cancel(a), None);
+        assert_eq!(wheel.cancel(d), Some('d'));
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn cascades_through_every_level() {
+        let mut wheel = TimerWheel::new(7);
+        let deadlines = [
+            8,
+            70,
+            4_000,
+            300_000,
+            20_000_000,
+            1_000_000_000,
+            SPAN - 1,
+            SPAN * 3 + 17,
+        ];
+        for &deadline in deadlines.iter().rev() {
+            wheel.schedule(deadline, deadline);
+        }
+        for &deadline in &deadlines {
+            assert!(wheel.next_expiration().unwrap() <= deadline);
+            assert_eq!(wheel.advance(deadline - 1), Vec::<u64>::new());
+            assert_eq!(wheel.advance(deadline), [deadline]);
+        }
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn one_big_jump() {
+        let mut wheel = TimerWheel::new(0);
+        let mut expected = Vec::new();
+        for i in 0..1_000u64 {
+            let deadline = (i * 7_919) % 100_000 + 1;
+            wheel.schedule(deadline, deadline);
+            expected.push(deadline);
+        }
+        expected.sort_unstable();
+        assert_eq!(wheel.advance(100_000), expected);
+    }
+
+    /// Random schedules, cancels and advances, against a `BTreeMap` from `(deadline, id)`.
+    #[test]
+    fn same_as_btreemap() {
+        let mut wheel = TimerWheel::new(0);
+        let mut model = BTreeMap::new();
+        let mut handles = Vec::new();
+        let mut rng = XorShift::new();
+
+        for id in 0..20_000u64 {
+            let bits = rng.next_u64();
+            let now = wheel.now();
+            match bits % 8 {
+                0..=3 => {
+                    // Mostly short timeouts, some very long ones.
+                    let deadline = now
+                        + match (bits >> 8) % 10 {
+                            0 => (bits >> 16) % 10_000_000,
+                            1 => 0,
+                            2 if bits & 0x100_0000 == 0 => (bits >> 16) % (3 * SPAN),
+                            _ => (bits >> 16) % 1_000,
+                        };
+                    handles.push(wheel.schedule(deadline, (deadline, id)));
+                    model.insert((deadline, id), ());
+                }
+                4 | 5 if !handles.is_empty() => {
+                    let handle = handles.swap_remove((bits >> 8) as usize % handles.len());
+                    if let Some(key) = wheel.cancel(handle) {
+                        assert_eq!(model.remove(&key), Some(()));
+                    }
+                }
+                _ => {
+                    // Now and then, jump far enough to reach the far-off timers.
+                    let to = match (bits >> 8) % 100 {
+                        0 => now + (bits >> 16) % (2 * SPAN),
+                        _ => now + (bits >> 16) % 2_000,
+                    };
+                    let fired = wheel.advance(to);
+                    assert!(fired.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+                    let mut expected = Vec::new();
+                    while model
+                        .first_key_value()
+                        .is_some_and(|(&(deadline, _), _)| deadline <= to)
+                    {
+                        expected.push(model.pop_first().unwrap().0);
+                    }
+                    // Timers due at the same tick can come out in any order.
+                    let mut fired = fired;
+                    fired.sort_unstable();
+                    assert_eq!(fired, expected);
+                }
+            }
+            assert_eq!(wheel.len(), model.len());
+            if let Some((&(first, _), _)) = model.first_key_value() {
+                assert!(wheel.next_expiration().unwrap() <= first.max(wheel.now()));
+            }
+        }
+    }
+}
@@ -0,0 +1,665 @@
+//! An unrolled linked list: a doubly linked list whose nodes hold up to `N` elements each.
+//!
+//! Walking `second::List` means a pointer chase, and usually a cache miss, for every single
+//! element. Here the elements sit next to each other in an array inside the node, so a scan
+//! chases one pointer per `N` elements and reads the rest straight out of the same cache lines:
+//!
+//! ```ignore
+//! head -> [a b c d . . . .] <-> [e f g . . . . .] <-> [h i j k l m . .] <- tail
+//!          N = 8, len 4              len 3                len 6
+//! ```
+//!
+//! Inserting in the middle of a full node splits it in two half-full ones, and removing from a
+//! node that drops below half full pulls an element over from the next one (or merges with it if
+//! they fit together). So nodes stay at least half full, apart from the ends, where pushes start
+//! new nodes and pops just take elements away.
+//!
+//! Finding element `i` still walks the list, but only node by node, so it's O(n/N).
+
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::{fmt, ptr, slice};
+
+pub struct List<T, const N: usize> {
+    head: *mut Node<T, N>,
+    tail: *mut Node<T, N>,
+    len: usize,
+    _marker: PhantomData<Box<Node<T, N>>>,
+}
+
+/// Never empty while it's in a list.
+struct Node<T, const N: usize> {
+    /// `elems[..len]` are initialized.
+    elems: [MaybeUninit<T>; N],
+    len: usize,
+    prev: *mut Node<T, N>,
+    next: *mut Node<T, N>,
+}
+
+pub struct Iter<'a, T, const N: usize> {
+    front: slice::Iter<'a, T>,
+    /// The node after the one `front` is in.
+    front_next: *const Node<T, N>,
+    back: slice::Iter<'a, T>,
+    /// The node before the one `back` is in.
+    back_prev: *const Node<T, N>,
+    /// `front` and `back` end up in the same node, and this keeps them from yielding an element
+    /// twice.
+    remaining: usize,
+}
+
+pub struct IntoIter<T, const N: usize>(List<T, N>);
+
+unsafe impl<T: Send, const N: usize> Send for List<T, N> {}
+unsafe impl<T: Sync, const N: usize> Sync for List<T, N> {}
+
+impl<T, const N: usize> Node<T, N> {
+    fn alloc() -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            // An array of `MaybeUninit` doesn't need initializing.
+            elems: unsafe { MaybeUninit::uninit().assume_init() },
+            len: 0,
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+        }))
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    fn as_ptr(&mut self) -> *mut T {
+        self.elems.as_mut_ptr() as *mut T
+    }
+
+    fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.elems.as_ptr() as *const T, self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.as_ptr(), self.len) }
+    }
+
+    /// `Vec::insert` for a node that isn't full.
+    fn insert(&mut self, i: usize, elem: T) {
+        debug_assert!(!self.is_full() && i <= self.len);
+        unsafe {
+            let p = self.as_ptr().add(i);
+            ptr::copy(p, p.add(1), self.len - i);
+            p.write(elem);
+        }
+        self.len += 1;
+    }
+
+    /// `Vec::remove`.
+    fn remove(&mut self, i: usize) -> T {
+        debug_assert!(i < self.len);
+        self.len -= 1;
+        unsafe {
+            let p = self.as_ptr().add(i);
+            let elem = p.read();
+            ptr::copy(p.add(1), p, self.len - i);
+            elem
+        }
+    }
+
+    /// Moves `self[at..]` into `other`, which must be empty.
+    fn split_off_into(&mut self, at: usize, other: &mut Self) {
+        debug_assert!(other.len == 0 && at <= self.len);
+        let moved = self.len - at;
+        unsafe { ptr::copy_nonoverlapping(self.as_ptr().add(at), other.as_ptr(), moved) };
+        self.len = at;
+        other.len = moved;
+    }
+
+    /// Moves all of `other` onto the end of `self`; they must fit.
+    fn append(&mut self, other: &mut Self) {
+        debug_assert!(self.len + other.len <= N);
+        unsafe {
+            ptr::copy_nonoverlapping(other.as_ptr(), self.as_ptr().add(self.len), other.len);
+        }
+        self.len += other.len;
+        other.len = 0;
+    }
+}
+
+impl<T, const N: usize> Drop for Node<T, N> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) };
+    }
+}
+
+impl<T, const N: usize> List<T, N> {
+    /// # Panics
+    ///
+    /// If `N` is less than 2: a node has to be able to split into two.
+    pub fn new() -> Self {
+        assert!(N >= 2, "unrolled::List needs at least 2 elements per node");
+        List {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Links a fresh node in after `prev`, or at the front if `prev` is null.
+    unsafe fn new_node_after(&mut self, prev: *mut Node<T, N>) -> *mut Node<T, N> {
+        let node = Node::alloc();
+        let next = if prev.is_null() {
+            self.head
+        } else {
+            (*prev).next
+        };
+        (*node).prev = prev;
+        (*node).next = next;
+        match prev.as_mut() {
+            Some(prev) => prev.next = node,
+            None => self.head = node,
+        }
+        match next.as_mut() {
+            Some(next) => next.prev = node,
+            None => self.tail = node,
+        }
+        node
+    }
+
+    /// Unlinks and frees a node that's been emptied out.
+    unsafe fn free_node(&mut self, node: *mut Node<T, N>) {
+        debug_assert!((*node).len == 0);
+        let (prev, next) = ((*node).prev, (*node).next);
+        match prev.as_mut() {
+            Some(prev) => prev.next = next,
+            None => self.head = next,
+        }
+        match next.as_mut() {
+            Some(next) => next.prev = prev,
+            None => self.tail = prev,
+        }
+        drop(Box::from_raw(node));
+    }
+
+    /// The node holding element `i` and where in it; `i` must be in bounds. Walks from whichever
+    /// end is closer.
+    fn locate(&self, mut i: usize) -> (*mut Node<T, N>, usize) {
+        debug_assert!(i < self.len);
+        unsafe {
+            if i < self.len / 2 {
+                let mut node = self.head;
+                while i >= (*node).len {
+                    i -= (*node).len;
+                    node = (*node).next;
+                }
+                (node, i)
+            } else {
+                let mut from_back = self.len - i;
+                let mut node = self.tail;
+                while from_back > (*node).len {
+                    from_back -= (*node).len;
+                    node = (*node).prev;
+                }
+                (node, (*node).len - from_back)
+            }
+        }
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        unsafe {
+            if self.head.is_null() || (*self.head).is_full() {
+                self.new_node_after(ptr::null_mut());
+            }
+            (*self.head).insert(0, elem);
+        }
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        unsafe {
+            if self.tail.is_null() || (*self.tail).is_full() {
+                self.new_node_after(self.tail);
+            }
+            let tail = &mut *self.tail;
+            tail.insert(tail.len, elem);
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        let head = unsafe { self.head.as_mut()? };
+        let elem = head.remove(0);
+        if head.len == 0 {
+            unsafe { self.free_node(head) };
+        }
+        self.len -= 1;
+        Some(elem)
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        let tail = unsafe { self.tail.as_mut()? };
+        let elem = tail.remove(tail.len - 1);
+        if tail.len == 0 {
+            unsafe { self.free_node(tail) };
+        }
+        self.len -= 1;
+        Some(elem)
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        unsafe { self.head.as_ref().map(|node| &node.as_slice()[0]) }
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        unsafe { self.tail.as_ref().and_then(|node| node.as_slice().last()) }
+    }
+
+    pub fn get(&self, i: usize) -> Option<&T> {
+        if i >= self.len {
+            return None;
+        }
+        let (node, i) = self.locate(i);
+        unsafe { Some(&(*node).as_slice()[i]) }
+    }
+
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+        if i >= self.len {
+            return None;
+        }
+        let (node, i) = self.locate(i);
+        unsafe { Some(&mut (*node).as_mut_slice()[i]) }
+    }
+
+    /// insert() puts `elem` at position `i`, shifting everything after it along. A full node is
+    /// split in half first.
+    ///
+    /// # Panics
+    ///
+    /// If `i > len`.
+    pub fn insert(&mut self, i: usize, elem: T) {
+        assert!(
+            i <= self.len,
+            "insert index {} out of bounds (len {})",
+            i,
+            self.len
+        );
+        if i == self.len {
+            return self.push_back(elem);
+        }
+        let (node, at) = self.locate(i);
+        unsafe {
+            let prev = (*node).prev;
+            if at == 0 && !prev.is_null() && !(*prev).is_full() {
+                // Between two nodes: the end of the one before is just as good, and needs no
+                // shifting.
+                let len = (*prev).len;
+                (*prev).insert(len, elem);
+            } else if (*node).is_full() {
+                let half = N / 2;
+                let new = self.new_node_after(node);
+                (*node).split_off_into(half, &mut *new);
+                if at <= half {
+                    (*node).insert(at, elem);
+                } else {
+                    (*new).insert(at - half, elem);
+                }
+            } else {
+                (*node).insert(at, elem);
+            }
+        }
+        self.len += 1;
+    }
+
+    /// remove() takes out the element at position `i`. If that leaves its node less than half
+    /// full, it's topped up from the next node, or merged with it if they fit in one.
+    ///
+    /// # Panics
+    ///
+    /// If `i >= len`.
+    pub fn remove(&mut self, i: usize) -> T {
+        assert!(
+            i < self.len,
+            "remove index {} out of bounds (len {})",
+            i,
+            self.len
+        );
+        let (node, at) = self.locate(i);
+        let elem = unsafe { (*node).remove(at) };
+        self.len -= 1;
+        unsafe { self.refill(node) };
+        elem
+    }
+
+    unsafe fn refill(&mut self, node: *mut Node<T, N>) {
+        let next = (*node).next;
+        if (*node).len >= N / 2 || next.is_null() {
+            if (*node).len == 0 {
+                self.free_node(node);
+            }
+            return;
+        }
+        if (*node).len + (*next).len <= N {
+            (*node).append(&mut *next);
+            self.free_node(next);
+        } else {
+            // `next` has more than `N - N/2` elements, so it stays at least half full too.
+            let elem = (*next).remove(0);
+            let len = (*node).len;
+            (*node).insert(len, elem);
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, N> {
+        unsafe {
+            match (self.head.as_ref(), self.tail.as_ref()) {
+                (Some(head), Some(tail)) => Iter {
+                    front: head.as_slice().iter(),
+                    front_next: head.next,
+                    back: tail.as_slice().iter(),
+                    back_prev: tail.prev,
+                    remaining: self.len,
+                },
+                _ => Iter {
+                    front: [].iter(),
+                    front_next: ptr::null(),
+                    back: [].iter(),
+                    back_prev: ptr::null(),
+                    remaining: 0,
+                },
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> Default for List<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for List<T, N> {
+    fn drop(&mut self) {
+        let mut cur = self.head;
+        while !cur.is_null() {
+            let node = unsafe { Box::from_raw(cur) };
+            cur = node.next;
+        }
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for List<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, const N: usize> Extend<T> for List<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<T, const N: usize> std::iter::FromIterator<T> for List<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<'a, T, const N: usize> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        loop {
+            if let Some(elem) = self.front.next() {
+                return Some(elem);
+            }
+            // Nodes are never empty, so this runs at most once per call.
+            let node = unsafe { &*self.front_next };
+            self.front = node.as_slice().iter();
+            self.front_next = node.next;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, const N: usize> DoubleEndedIterator for Iter<'a, T, N> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        loop {
+            if let Some(elem) = self.back.next_back() {
+                return Some(elem);
+            }
+            let node = unsafe { &*self.back_prev };
+            self.back = node.as_slice().iter();
+            self.back_prev = node.prev;
+        }
+    }
+}
+
+impl<'a, T, const N: usize> ExactSizeIterator for Iter<'a, T, N> {}
+
+impl<'a, T, const N: usize> IntoIterator for &'a List<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, N>;
+
+    fn into_iter(self) -> Iter<'a, T, N> {
+        self.iter()
+    }
+}
+
+impl<T, const N: usize> IntoIterator for List<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        IntoIter(self)
+    }
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    fn next_back(&mut self) -> Option<T> {
+        self.0.pop_back()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::List;
+    use crate::test_util::{Drops, XorShift};
+    use std::collections::VecDeque;
+
+    /// Node lengths front to back, checking the links on the way.
+    fn node_lens<T, const N: usize>(list: &List<T, N>) -> Vec<usize> {
+        let mut lens = Vec::new();
+        let mut prev = std::ptr::null_mut();
+        let mut cur = list.head;
+        while !cur.is_null() {
+            let node = unsafe { &*cur };
+            assert_eq!(node.prev, prev);
+            assert!(node.len > 0 && node.len <= N);
+            lens.push(node.len);
+            prev = cur;
+            cur = node.next;
+        }
+        assert_eq!(list.tail, prev);
+        assert_eq!(lens.iter().sum::<usize>(), list.len());
+        lens
+    }
+
+    #[test]
+    fn basics() {
+        let mut list = List::<i32, 4>::new();
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+        assert_eq!(list.front(), None);
+
+        for i in 0..10 {
+            list.push_back(i);
+        }
+        list.push_front(-1);
+        assert_eq!(node_lens(&list), [1, 4, 4, 2]);
+        assert_eq!(list.front(), Some(&-1));
+        assert_eq!(list.back(), Some(&9));
+
+        assert_eq!(list.pop_front(), Some(-1));
+        assert_eq!(node_lens(&list), [4, 4, 2]);
+        assert_eq!(list.pop_back(), Some(9));
+        assert_eq!(list.pop_back(), Some(8));
+        assert_eq!(node_lens(&list), [4, 4]);
+        assert_eq!(list.get(5), Some(&5));
+        *list.get_mut(5).unwrap() = 50;
+        assert_eq!(list.get(8), None);
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            [0, 1, 2, 3, 4, 50, 6, 7]
+        );
+    }
+
+    #[test]
+    fn split_and_merge() {
+        let mut list: List<i32, 4> = (0..8).collect();
+        assert_eq!(node_lens(&list), [4, 4]);
+
+        // Into a full node: it splits in half.
+        list.insert(1, 100);
+        assert_eq!(node_lens(&list), [3, 2, 4]);
+        list.insert(5, 101);
+        assert_eq!(node_lens(&list), [3, 3, 4]);
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            [0, 100, 1, 2, 3, 101, 4, 5, 6, 7]
+        );
+
+        // Below half full: merged with the next node when they fit...
+        assert_eq!(list.remove(0), 0);
+        assert_eq!(list.remove(0), 100);
+        assert_eq!(node_lens(&list), [4, 4]);
+        assert_eq!(list.remove(0), 1);
+        assert_eq!(list.remove(2), 101);
+        assert_eq!(node_lens(&list), [2, 4]);
+        // ...and topped up from it when they don't.
+        assert_eq!(list.remove(0), 2);
+        assert_eq!(node_lens(&list), [2, 3]);
+        assert_eq!(list.remove(0), 3);
+        assert_eq!(node_lens(&list), [4]);
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [4, 5, 6, 7]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn remove_out_of_bounds() {
+        let mut list: List<i32, 4> = (0..3).collect();
+        list.remove(3);
+    }
+
+    #[test]
+    fn iter_both_ways() {
+        let list: List<i32, 3> = (0..10).collect();
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&9));
+        assert_eq!(iter.len(), 8);
+        assert_eq!(iter.by_ref().rev().take(5).count(), 5);
+        // Only 1..=3 are left, and the back end has to stop in the same node as the front.
+        assert_eq!(iter.collect::<Vec<_>>(), [&1, &2, &3]);
+
+        let empty = List::<i32, 3>::new();
+        assert_eq!(empty.iter().next(), None);
+        assert_eq!(empty.iter().next_back(), None);
+
+        assert!(list.iter().rev().copied().eq((0..10).rev()));
+        assert!(list.into_iter().rev().eq((0..10).rev()));
+    }
+
+    /// Random inserts, removes, pushes and pops against a `VecDeque`.
+    fn same_as_vecdeque<const N: usize>() {
+        let mut ours = List::<u32, N>::new();
+        let mut model = VecDeque::new();
+        let mut rng = XorShift::with_seed(0x9E37_79B9_7F4A_7C15);
+
+        for i in 0..5_000 {
+            let bits = rng.next_u64();
+            let at = (bits >> 32) as usize % (model.len() + 1);
+            match bits % 7 {
+                0 | 1 => {
+                    ours.insert(at, i);
+                    model.insert(at, i);
+                }
+                2 if at < model.len() => assert_eq!(ours.remove(at), model.remove(at).unwrap()),
+                3 => {
+                    ours.push_front(i);
+                    model.push_front(i);
+                }
+                4 => {
+                    ours.push_back(i);
+                    model.push_back(i);
+                }
+                5 => assert_eq!(ours.pop_front(), model.pop_front()),
+                _ => assert_eq!(ours.pop_back(), model.pop_back()),
+            }
+            assert_eq!(ours.len(), model.len());
+            assert_eq!(ours.get(at), model.get(at));
+            if i % 100 == 0 {
+                node_lens(&ours);
+                assert!(ours.iter().eq(model.iter()));
+            }
+        }
+        assert!(ours.into_iter().eq(model));
+    }
+
+    #[test]
+    fn same_as_vecdeque_2() {
+        same_as_vecdeque::<2>();
+    }
+
+    #[test]
+    fn same_as_vecdeque_5() {
+        same_as_vecdeque::<5>();
+    }
+
+    #[test]
+    fn same_as_vecdeque_16() {
+        same_as_vecdeque::<16>();
+    }
+
+    #[test]
+    fn drops_everything_once() {
+        let drops = Drops::new();
+        let mut list = List::<_, 4>::new();
+        for _ in 0..20 {
+            list.push_back(drops.counted());
+        }
+        drop(list.remove(7));
+        drop(list.pop_front());
+        assert_eq!(drops.get(), 2);
+        drop(list);
+        assert_eq!(drops.get(), 20);
+    }
+}
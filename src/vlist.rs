@@ -0,0 +1,345 @@
+//! Bagwell's VList: `third::List` with the nodes packed into blocks that double in size.
+//!
+//! A `third::List` of n elements is n allocations scattered around the heap, and finding the i-th
+//! element means following i pointers. A VList keeps the same interface (`cons` onto a shared
+//! tail, old versions stay valid) but stores elements in arrays. Each block holds twice as many
+//! as the one after it, so half of the list is in the first block, a quarter in the second, and
+//! so on:
+//!
+//! ```ignore
+//! list = [14, 13, ..., 0]
+//!
+//! [14 .. 7] -> [6 5 4 3] -> [2 1] -> [0]
+//!  8 slots      4 slots     2 slots  1 slot
+//! ```
+//!
+//! A list is a block plus how many of its slots it uses. Blocks fill from the end that's nearest
+//! their tail, so `cons` on the newest version just writes the next slot, and `get(i)` skips a
+//! whole block at a time: O(log n) blocks at worst, and O(1) on average over the indices.
+//!
+//! The catch is persistence. Two versions can share a block while using different numbers of its
+//! slots, and both may want to `cons`:
+//!
+//! ```ignore
+//! block:  slot 0  1  2  3
+//!              1  2  3  _         a = [3, 2, 1] uses 3 slots, b = a.tail() uses 2
+//!
+//! a.cons(4):   1  2  3  4         slot 3 was free, a takes it
+//! b.cons(9):   [9 _ _ _] -> b     slot 2 is taken: b gets a fresh block on top of itself
+//! ```
+//!
+//! Each slot is a `OnceCell`, so whoever gets to it first keeps it, and everybody else gets a new
+//! block, twice the size of the part of the old block they use. Nothing a version can see is ever
+//! written twice.
+
+use std::cell::OnceCell;
+use std::rc::Rc;
+
+pub struct VList<T> {
+    block: Option<Rc<Block<T>>>,
+    /// How many of `block`'s slots this version uses, from the bottom. Never 0 when there's a
+    /// block: a list that uses none of its block is just the block's `rest`.
+    used: usize,
+}
+
+struct Block<T> {
+    /// Slot 0 is the oldest element; the newest one this block holds is the list's head.
+    slots: Box<[OnceCell<T>]>,
+    /// The list this block was started on top of.
+    rest: VList<T>,
+}
+
+pub struct Iter<'a, T: 'a> {
+    block: Option<&'a Block<T>>,
+    used: usize,
+}
+
+impl<T> VList<T> {
+    pub fn new() -> Self {
+        VList {
+            block: None,
+            used: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block.is_none()
+    }
+
+    /// len() walks the blocks, so it's O(log n) for a list built by consing.
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        let mut cur = self;
+        while let Some(block) = &cur.block {
+            len += cur.used;
+            cur = &block.rest;
+        }
+        len
+    }
+
+    /// cons() puts an element in front, in this version's block if nobody has taken the next
+    /// slot, and in a new block if somebody has (or the block is full).
+    pub fn cons(&self, elem: T) -> VList<T> {
+        let elem = match &self.block {
+            Some(block) if self.used < block.slots.len() => {
+                match block.slots[self.used].set(elem) {
+                    Ok(()) => {
+                        return VList {
+                            block: self.block.clone(),
+                            used: self.used + 1,
+                        }
+                    }
+                    Err(elem) => elem,
+                }
+            }
+            _ => elem,
+        };
+
+        let size = (2 * self.used).max(1);
+        let slots: Box<[OnceCell<T>]> = (0..size).map(|_| OnceCell::new()).collect();
+        let _ = slots[0].set(elem);
+        VList {
+            block: Some(Rc::new(Block {
+                slots,
+                rest: self.clone(),
+            })),
+            used: 1,
+        }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        let block = self.block.as_ref()?;
+        block.slots[self.used - 1].get()
+    }
+
+    /// tail() is the same block with one slot fewer, or the block's `rest` once that would be
+    /// none.
+    pub fn tail(&self) -> VList<T> {
+        match &self.block {
+            Some(block) if self.used == 1 => block.rest.clone(),
+            Some(_) => VList {
+                block: self.block.clone(),
+                used: self.used - 1,
+            },
+            None => VList::new(),
+        }
+    }
+
+    /// get() counts from the head, like `iter().nth(index)`, but a block at a time.
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        let mut cur = self;
+        while let Some(block) = &cur.block {
+            if index < cur.used {
+                return block.slots[cur.used - 1 - index].get();
+            }
+            index -= cur.used;
+            cur = &block.rest;
+        }
+        None
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            block: self.block.as_deref(),
+            used: self.used,
+        }
+    }
+
+    /// ptr_eq() tells whether the two are the very same version: same block, same slots.
+    pub fn ptr_eq(&self, other: &VList<T>) -> bool {
+        match (&self.block, &other.block) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b) && self.used == other.used,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T> Default for VList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for VList<T> {
+    fn clone(&self) -> Self {
+        VList {
+            block: self.block.clone(),
+            used: self.used,
+        }
+    }
+}
+
+impl<T> Drop for VList<T> {
+    /// `third::List`'s destructor, a block at a time. Consing on old versions can make the chain
+    /// of blocks as long as the list, so it can't be left to recursion.
+    fn drop(&mut self) {
+        let mut block = self.block.take();
+        while let Some(shared) = block {
+            match Rc::try_unwrap(shared) {
+                Ok(mut owned) => block = owned.rest.block.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block = self.block?;
+        let elem = block.slots[self.used - 1].get();
+        self.used -= 1;
+        if self.used == 0 {
+            self.block = block.rest.block.as_deref();
+            self.used = block.rest.used;
+        }
+        elem
+    }
+}
+
+impl<'a, T> IntoIterator for &'a VList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VList;
+    use crate::test_util::XorShift;
+
+    /// (capacity, used) of every block, head first.
+    fn blocks<T>(list: &VList<T>) -> Vec<(usize, usize)> {
+        let mut out = Vec::new();
+        let mut cur = list;
+        while let Some(block) = &cur.block {
+            out.push((block.slots.len(), cur.used));
+            cur = &block.rest;
+        }
+        out
+    }
+
+    fn collect(list: &VList<u32>) -> Vec<u32> {
+        list.iter().copied().collect()
+    }
+
+    #[test]
+    fn basics() {
+        let empty = VList::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.head(), None);
+        assert_eq!(empty.get(0), None);
+        assert!(empty.tail().is_empty());
+
+        let list = empty.cons(1).cons(2).cons(3);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.head(), Some(&3));
+        assert_eq!(collect(&list), [3, 2, 1]);
+        assert_eq!(collect(&list.tail()), [2, 1]);
+        assert_eq!(list.get(0), Some(&3));
+        assert_eq!(list.get(2), Some(&1));
+        assert_eq!(list.get(3), None);
+        assert!(list.tail().tail().tail().is_empty());
+    }
+
+    #[test]
+    fn blocks_double() {
+        let mut list = VList::new();
+        for i in 0..15 {
+            list = list.cons(i);
+        }
+        assert_eq!(blocks(&list), [(8, 8), (4, 4), (2, 2), (1, 1)]);
+
+        list = list.cons(15);
+        assert_eq!(blocks(&list), [(16, 1), (8, 8), (4, 4), (2, 2), (1, 1)]);
+
+        for i in 16..1_000 {
+            list = list.cons(i);
+        }
+        let sizes: Vec<usize> = blocks(&list).iter().map(|&(size, _)| size).collect();
+        assert_eq!(sizes, [512, 256, 128, 64, 32, 16, 8, 4, 2, 1]);
+        assert_eq!(list.len(), 1_000);
+        for i in 0..1_000 {
+            assert_eq!(list.get(i), Some(&(999 - i as u32)));
+        }
+    }
+
+    #[test]
+    fn old_versions_get_fresh_blocks() {
+        let mut base = VList::new();
+        for i in 0..5 {
+            base = base.cons(i);
+        }
+        // [4, 3] in a block of 4 slots, using 2.
+        assert_eq!(blocks(&base)[0], (4, 2));
+
+        // The first cons onto `base` takes the free slot...
+        let a = base.cons(100);
+        assert_eq!(blocks(&a)[0], (4, 3));
+        assert!(a.tail().ptr_eq(&base));
+
+        // ...so the second can't, and starts a block of its own on top of `base`, twice the size
+        // of what `base` uses of its block.
+        let b = base.cons(200);
+        assert_eq!(blocks(&b)[0], (4, 1));
+        assert!(b.tail().ptr_eq(&base));
+
+        // Same for consing onto a tail: the slot after it is already someone's.
+        let c = a.tail().tail().cons(300);
+        assert_eq!(blocks(&c)[0], (2, 1));
+
+        assert_eq!(collect(&base), [4, 3, 2, 1, 0]);
+        assert_eq!(collect(&a), [100, 4, 3, 2, 1, 0]);
+        assert_eq!(collect(&b), [200, 4, 3, 2, 1, 0]);
+        assert_eq!(collect(&c), [300, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn long_chain_of_blocks() {
+        // Always consing onto the version that lost the race leaves one tiny block per element.
+        let mut list = VList::new().cons(0);
+        for i in 1..200_000 {
+            let _winner = list.cons(u32::MAX);
+            list = list.cons(i);
+        }
+        assert_eq!(blocks(&list).len(), 200_000);
+        assert_eq!(list.head(), Some(&199_999));
+        assert!(list.iter().copied().eq((0..200_000).rev()));
+    }
+
+    /// Random conses and tails on random old versions, each checked against a `Vec` of what it
+    /// should hold, head last.
+    #[test]
+    fn same_as_vec() {
+        let mut versions: Vec<(VList<u32>, Vec<u32>)> = vec![(VList::new(), Vec::new())];
+        let mut rng = XorShift::new();
+
+        for step in 0..5_000 {
+            let bits = rng.next_u64();
+            let (list, model) = &versions[(bits >> 8) as usize % versions.len()];
+            let mut model = model.clone();
+            let list = if bits & 3 == 0 {
+                model.pop();
+                list.tail()
+            } else {
+                model.push(step);
+                list.cons(step)
+            };
+            assert_eq!(list.head(), model.last());
+            versions.push((list, model));
+        }
+
+        for (list, model) in &versions {
+            assert_eq!(list.len(), model.len());
+            assert!(list.iter().eq(model.iter().rev()));
+            for (i, elem) in model.iter().rev().enumerate() {
+                assert_eq!(list.get(i), Some(elem));
+            }
+        }
+    }
+}
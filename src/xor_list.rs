@@ -0,0 +1,388 @@
+//! An experimental doubly linked list that spends one word per node on links instead of two.
+//!
+//! Instead of `prev` and `next`, each node stores `prev ^ next`, the XOR of the two addresses
+//! (with a missing neighbour counting as 0). That on its own tells you nothing, but if you know
+//! where you came from, you can work out where you're going:
+//!
+//! ```ignore
+//!         0 ^ B      A ^ C      B ^ 0
+//! head -> [A] <----> [B] <----> [C] <- tail
+//!
+//! at B, coming from A:  link(B) ^ A = (A ^ C) ^ A = C
+//! at B, coming from C:  link(B) ^ C = (A ^ C) ^ C = A
+//! ```
+//!
+//! So a walk always carries two pointers, the node and the one before it. The ends are easy
+//! since the missing neighbour is 0, which is also why `reverse` is free: the links read the
+//! same in both directions, so swapping `head` and `tail` is all it takes.
+//!
+//! The price is that you can't do anything with a node you only have a pointer to, so there is
+//! no cursor or remove-in-the-middle here, and addresses round-trip through `usize`, which is
+//! exactly the kind of thing tools like Miri's strict provenance mode refuse. Hence experimental.
+
+use std::marker::PhantomData;
+use std::ptr;
+
+pub struct List<T> {
+    head: *mut Node<T>,
+    tail: *mut Node<T>,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+struct Node<T> {
+    elem: T,
+    /// `prev as usize ^ next as usize`.
+    link: usize,
+}
+
+pub struct Iter<'a, T> {
+    front: *const Node<T>,
+    /// The node before `front`, walking forward.
+    front_prev: usize,
+    back: *const Node<T>,
+    /// The node after `back`, walking backward.
+    back_next: usize,
+    remaining: usize,
+    _marker: PhantomData<&'a T>,
+}
+
+pub struct IntoIter<T>(List<T>);
+
+unsafe impl<T: Send> Send for List<T> {}
+unsafe impl<T: Sync> Sync for List<T> {}
+
+fn addr<T>(node: *const Node<T>) -> usize {
+    node as usize
+}
+
+/// One step: from `node`, having come from `from`, to the node on the other side.
+unsafe fn step<T>(node: *const Node<T>, from: usize) -> *mut Node<T> {
+    ((*node).link ^ from) as *mut Node<T>
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        let new = Box::into_raw(Box::new(Node {
+            elem,
+            link: addr(self.head),
+        }));
+        if self.head.is_null() {
+            self.tail = new;
+        } else {
+            // The old head's prev was 0; now it's `new`.
+            unsafe { (*self.head).link ^= addr(new) };
+        }
+        self.head = new;
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        // The list looks the same from the other end, so push at the front of the reversed list.
+        self.reverse();
+        self.push_front(elem);
+        self.reverse();
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.head.is_null() {
+            return None;
+        }
+        unsafe {
+            let old = Box::from_raw(self.head);
+            // Its prev is 0, so the link is just `next`.
+            self.head = step(&*old, 0);
+            if self.head.is_null() {
+                self.tail = ptr::null_mut();
+            } else {
+                (*self.head).link ^= addr(&*old);
+            }
+            self.len -= 1;
+            Some(old.elem)
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.reverse();
+        let elem = self.pop_front();
+        self.reverse();
+        elem
+    }
+
+    pub fn peek_front(&self) -> Option<&T> {
+        unsafe { self.head.as_ref().map(|node| &node.elem) }
+    }
+
+    pub fn peek_back(&self) -> Option<&T> {
+        unsafe { self.tail.as_ref().map(|node| &node.elem) }
+    }
+
+    pub fn peek_front_mut(&mut self) -> Option<&mut T> {
+        unsafe { self.head.as_mut().map(|node| &mut node.elem) }
+    }
+
+    pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+        unsafe { self.tail.as_mut().map(|node| &mut node.elem) }
+    }
+
+    /// reverse() is O(1): no node knows which of its neighbours is "next", so all it takes is
+    /// starting from the other end.
+    pub fn reverse(&mut self) {
+        std::mem::swap(&mut self.head, &mut self.tail);
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: self.head,
+            front_prev: 0,
+            back: self.tail,
+            back_next: 0,
+            remaining: self.len,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        unsafe {
+            let node = self.front;
+            self.front = step(node, self.front_prev);
+            self.front_prev = addr(node);
+            Some(&(*node).elem)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        unsafe {
+            let node = self.back;
+            self.back = step(node, self.back_next);
+            self.back_next = addr(node);
+            Some(&(*node).elem)
+        }
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.0.pop_back()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{List, Node};
+    use crate::fourth;
+    use crate::test_util::{Drops, XorShift};
+    use std::cell::RefCell;
+    use std::mem::size_of;
+
+    #[test]
+    fn basics() {
+        let mut list = List::new();
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+
+        list.push_front(2);
+        list.push_front(1);
+        list.push_back(3);
+        list.push_back(4);
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.peek_front(), Some(&1));
+        assert_eq!(list.peek_back(), Some(&4));
+
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_back(), Some(4));
+        *list.peek_front_mut().unwrap() *= 10;
+        *list.peek_back_mut().unwrap() *= 10;
+        assert_eq!(list.pop_back(), Some(30));
+        assert_eq!(list.pop_back(), Some(20));
+        assert_eq!(list.pop_back(), None);
+        assert!(list.is_empty());
+
+        list.push_back(5);
+        assert_eq!(list.pop_front(), Some(5));
+        assert_eq!(list.peek_back(), None);
+    }
+
+    #[test]
+    fn reverse() {
+        let mut list = List::new();
+        list.reverse();
+        assert!(list.is_empty());
+
+        for i in 0..5 {
+            list.push_back(i);
+        }
+        list.reverse();
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [4, 3, 2, 1, 0]);
+        list.push_front(5);
+        list.push_back(-1);
+        list.reverse();
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            [-1, 0, 1, 2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn iter_both_ways() {
+        let mut list = List::new();
+        for i in 0..6 {
+            list.push_back(i);
+        }
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&5));
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&4));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next_back(), Some(&3));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        let mut iter = list.into_iter();
+        assert_eq!(iter.next_back(), Some(5));
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.rev().collect::<Vec<_>>(), [4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn same_as_fourth() {
+        let mut ours = List::new();
+        let mut theirs = fourth::List::new();
+        let mut rng = XorShift::new();
+
+        for i in 0..20_000 {
+            let bits = rng.next_u64();
+            match bits % 5 {
+                0 => {
+                    ours.push_front(i);
+                    theirs.push_front(i);
+                }
+                1 => {
+                    ours.push_back(i);
+                    theirs.push_back(i);
+                }
+                2 => assert_eq!(ours.pop_front(), theirs.pop_front()),
+                3 => assert_eq!(ours.pop_back(), theirs.pop_back()),
+                _ => {
+                    // `fourth` has no reverse, so turn it around by hand.
+                    let mut reversed = fourth::List::new();
+                    while let Some(elem) = theirs.pop_front() {
+                        reversed.push_front(elem);
+                    }
+                    theirs = reversed;
+                    ours.reverse();
+                }
+            }
+            assert_eq!(ours.peek_front(), theirs.peek_front().as_deref());
+            assert_eq!(ours.peek_back(), theirs.peek_back().as_deref());
+        }
+        assert!(ours.into_iter().eq(theirs));
+    }
+
+    #[test]
+    fn drops_everything_once() {
+        let drops = Drops::new();
+        let mut list = List::new();
+        for _ in 0..10 {
+            list.push_back(drops.counted());
+        }
+        drop(list.pop_back());
+        drop(list.pop_front());
+        assert_eq!(drops.get(), 2);
+        drop(list);
+        assert_eq!(drops.get(), 10);
+    }
+
+    /// What one element costs on the heap. There's no `sixth` (raw `prev`/`next` pointers) in
+    /// this crate, so its node is spelled out here: the element plus two pointers.
+    #[test]
+    fn memory_per_node() {
+        fn xor<T>() -> usize {
+            size_of::<Node<T>>()
+        }
+        fn sixth<T>() -> usize {
+            size_of::<(T, *mut u8, *mut u8)>()
+        }
+        fn fourth<T>() -> usize {
+            // `Rc` puts a strong and a weak count in front of the `RefCell`.
+            2 * size_of::<usize>() + size_of::<RefCell<fourth::Node<T>>>()
+        }
+
+        let word = size_of::<usize>();
+        assert_eq!(xor::<u64>(), 8 + word);
+        assert_eq!(xor::<u8>(), word + word);
+        assert_eq!(sixth::<u64>(), 8 + 2 * word);
+        assert_eq!(fourth::<u64>(), 8 + 5 * word);
+
+        assert!(xor::<u64>() < sixth::<u64>());
+        assert!(sixth::<u64>() < fourth::<u64>());
+        assert!(xor::<[u8; 100]>() < sixth::<[u8; 100]>());
+    }
+}
@@ -0,0 +1,197 @@
+//! A zipper over the persistent list.
+//!
+//! The idea is to cut a list in two at the focus and keep both halves as `third::List`s, with the
+//! left half reversed so that the element right before the focus is at its head:
+//!
+//! ```ignore
+//! list   = A -> B -> C -> D -> E
+//!                    ^ focus
+//! left   = B -> A
+//! right  = C -> D -> E
+//! ```
+//!
+//! Moving the focus by one pops the head of one side and pushes it onto the other, and editing at
+//! the focus only touches the head of `right`. Everything else is shared with the zipper we
+//! started from, so keeping old zippers around (say, for undo) costs O(1) per edit.
+//!
+//! The focus may also sit one past the last element (`right` is empty); that's where `insert`
+//! appends.
+
+use crate::third::List;
+
+pub struct Zipper<T> {
+    left: List<T>,
+    right: List<T>,
+}
+
+impl<T> Zipper<T> {
+    /// Focuses the first element of `list`. The zipper shares every node with it.
+    pub fn from_list(list: &List<T>) -> Self {
+        Zipper {
+            left: List::new(),
+            right: list.clone(),
+        }
+    }
+
+    /// focus() returns the element under the focus, or `None` past the end.
+    pub fn focus(&self) -> Option<&T> {
+        self.right.head()
+    }
+
+    pub fn is_at_start(&self) -> bool {
+        self.left.head().is_none()
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.right.head().is_none()
+    }
+
+    /// insert() puts `elem` right before the focus and focuses it. Only one node is allocated.
+    pub fn insert(&self, elem: T) -> Self {
+        Zipper {
+            left: self.left.clone(),
+            right: self.right.append(elem),
+        }
+    }
+
+    /// delete() removes the focused element and focuses the one after it. `None` past the end.
+    pub fn delete(&self) -> Option<Self> {
+        self.focus()?;
+        Some(Zipper {
+            left: self.left.clone(),
+            right: self.right.tail(),
+        })
+    }
+
+    /// replace() swaps the focused element for `elem`. `None` past the end.
+    pub fn replace(&self, elem: T) -> Option<Self> {
+        self.focus()?;
+        Some(Zipper {
+            left: self.left.clone(),
+            right: self.right.tail().append(elem),
+        })
+    }
+}
+
+/// Moving around needs to copy the element that changes sides: nodes are shared, so we can't move
+/// it out of one list and into the other.
+impl<T: Clone> Zipper<T> {
+    /// left() moves the focus one element towards the start. `None` at the start.
+    pub fn left(&self) -> Option<Self> {
+        let elem = self.left.head()?;
+        Some(Zipper {
+            left: self.left.tail(),
+            right: self.right.append(elem.clone()),
+        })
+    }
+
+    /// right() moves the focus one element towards the end. `None` past the end.
+    pub fn right(&self) -> Option<Self> {
+        let elem = self.right.head()?;
+        Some(Zipper {
+            left: self.left.append(elem.clone()),
+            right: self.right.tail(),
+        })
+    }
+
+    /// to_list() zips the two halves back together. The result shares the whole right half with
+    /// this zipper; only the elements left of the focus are copied.
+    pub fn to_list(&self) -> List<T> {
+        self.left
+            .iter()
+            .fold(self.right.clone(), |list, elem| list.append(elem.clone()))
+    }
+}
+
+impl<T> Clone for Zipper<T> {
+    fn clone(&self) -> Self {
+        Zipper {
+            left: self.left.clone(),
+            right: self.right.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Zipper;
+    use crate::third::List;
+
+    fn collect(list: &List<i32>) -> Vec<i32> {
+        list.iter().copied().collect()
+    }
+
+    #[test]
+    fn basics() {
+        let list = List::new().append(3).append(2).append(1);
+        let z = Zipper::from_list(&list);
+        assert!(z.is_at_start());
+        assert_eq!(z.focus(), Some(&1));
+        assert!(z.left().is_none());
+
+        let z = z.right().unwrap().right().unwrap();
+        assert_eq!(z.focus(), Some(&3));
+        let end = z.right().unwrap();
+        assert!(end.is_at_end());
+        assert_eq!(end.focus(), None);
+        assert!(end.right().is_none());
+        assert!(end.delete().is_none());
+        assert!(end.replace(0).is_none());
+
+        let back = end.left().unwrap().left().unwrap();
+        assert_eq!(back.focus(), Some(&2));
+        assert_eq!(collect(&back.to_list()), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn edits() {
+        let list = List::new().append(3).append(2).append(1);
+        let z = Zipper::from_list(&list).right().unwrap();
+
+        let replaced = z.replace(20).unwrap();
+        assert_eq!(replaced.focus(), Some(&20));
+        assert_eq!(collect(&replaced.to_list()), vec![1, 20, 3]);
+
+        let inserted = z.insert(15);
+        assert_eq!(inserted.focus(), Some(&15));
+        assert_eq!(collect(&inserted.to_list()), vec![1, 15, 2, 3]);
+
+        let deleted = z.delete().unwrap();
+        assert_eq!(deleted.focus(), Some(&3));
+        assert_eq!(collect(&deleted.to_list()), vec![1, 3]);
+
+        let appended = deleted.right().unwrap().insert(4);
+        assert_eq!(collect(&appended.to_list()), vec![1, 3, 4]);
+
+        // None of that touched the original.
+        assert_eq!(z.focus(), Some(&2));
+        assert_eq!(collect(&z.to_list()), vec![1, 2, 3]);
+        assert_eq!(collect(&list), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn sharing() {
+        let list = List::new().append(3).append(2).append(1);
+        let z = Zipper::from_list(&list);
+        assert!(z.to_list().ptr_eq(&list));
+
+        // Editing in the middle keeps the suffix after the edit.
+        let z = z.right().unwrap();
+        let edited = z.replace(20).unwrap();
+        assert!(edited.right.tail().ptr_eq(&list.tail().tail()));
+        assert!(edited.left.ptr_eq(&z.left));
+
+        // Moving doesn't copy the rest of the list either.
+        let moved = edited.right().unwrap();
+        assert!(moved.right.ptr_eq(&list.tail().tail()));
+    }
+
+    #[test]
+    fn empty() {
+        let z = Zipper::from_list(&List::<i32>::new());
+        assert!(z.is_at_start() && z.is_at_end());
+        assert!(z.left().is_none() && z.right().is_none());
+        let z = z.insert(1);
+        assert_eq!(collect(&z.to_list()), vec![1]);
+    }
+}